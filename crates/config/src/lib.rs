@@ -6,6 +6,7 @@ use std::{
 
 use log::info;
 use semver::Version;
+#[cfg(target_os = "switch")]
 use skyline::nn;
 use skyline_config::*;
 use smash_arc::{Hash40, Region};
@@ -33,6 +34,14 @@ pub static GLOBAL_CONFIG: LazyLock<Mutex<StorageHolder<ArcStorage>>> = LazyLock:
             // Update the version in the config
             storage.set_field("version", get_arcropolis_version().to_string()).unwrap();
         }
+
+        // Presets used to be sets of Hash40 for the mod's folder path. This does not depend on the version,
+        // as configurations written by this crate have been stamped with its own version instead of ARCropolis'.
+        if !storage.get_flag("mod_id_presets") {
+            info!("Migrating presets from folder hashes to mod identifiers.");
+            mods::migrate_hashed_presets(&mut storage);
+            storage.set_flag("mod_id_presets", true).unwrap();
+        }
     } else {
         // Version file does not exist
         generate_default_config(&mut storage)
@@ -50,8 +59,12 @@ fn generate_default_config<CS: ConfigStorage>(storage: &mut StorageHolder<CS>) -
 
     storage.set_field("version", get_arcropolis_version().to_string())?;
     storage.set_field("logging_level", "Warn")?;
+    storage.set_field("prc_merge_policy", PrcMergePolicy::default().as_str())?;
+    storage.set_field_json("region_fallbacks", &default_region_fallbacks())?;
+    storage.set_field_json("prc_row_keys", &default_prc_row_keys())?;
     storage.set_flag("auto_update", true)?;
-    storage.set_field_json("presets", &HashSet::<Hash40>::new())?;
+    storage.set_field_json("presets", &HashSet::<String>::new())?;
+    storage.set_flag("mod_id_presets", true)?;
 
     let mut default_workspace = HashMap::<&str, &str>::new();
     default_workspace.insert("Default", "presets");
@@ -80,12 +93,80 @@ pub fn skip_title_scene() -> bool {
     GLOBAL_CONFIG.lock().unwrap().get_flag("skip_title_scene")
 }
 
+pub fn skip_memory_warning() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("skip_memory_warning")
+}
+
 pub static REGION: RwLock<Region> = RwLock::new(Region::UsEnglish);
 
 pub fn region() -> Region {
     *REGION.read().unwrap()
 }
 
+/// Name used in region fallback chains for patches that don't target a region
+pub const ANY_REGION: &str = "any";
+
+/// Regions whose patches can stand in for each other, since they use the same language
+fn default_region_fallbacks() -> HashMap<String, Vec<String>> {
+    [("us_en", "eu_en"), ("us_fr", "eu_fr"), ("us_es", "eu_es")]
+        .into_iter()
+        .flat_map(|(us, eu)| [(us.to_string(), vec![eu.to_string()]), (eu.to_string(), vec![us.to_string()])])
+        .collect()
+}
+
+/// Regions to look for patches in when there is none for the current region, from most to least preferred.
+/// The chain always starts with the current region and falls back to patches for any region if the configuration doesn't place them elsewhere.
+pub fn region_fallback_chain() -> Vec<String> {
+    let fallbacks: HashMap<String, Vec<String>> = GLOBAL_CONFIG
+        .lock()
+        .unwrap()
+        .get_field_json("region_fallbacks")
+        .unwrap_or_else(|_| default_region_fallbacks());
+
+    let region = region().to_string();
+    let mut chain = vec![region.clone()];
+
+    for fallback in fallbacks.get(&region).into_iter().flatten() {
+        if !chain.contains(fallback) {
+            chain.push(fallback.clone());
+        }
+    }
+
+    if !chain.iter().any(|region| region == ANY_REGION) {
+        chain.push(ANY_REGION.to_string());
+    }
+
+    chain
+}
+
+/// Fields that identify the rows of the param databases, like `ui_chara_db` or `ui_bgm_db`
+fn default_prc_row_keys() -> Vec<String> {
+    [
+        "ui_chara_id",
+        "ui_bgm_id",
+        "ui_stage_id",
+        "ui_series_id",
+        "ui_gametitle_id",
+        "ui_spirit_id",
+        "ui_item_id",
+        "stream_set_id",
+        "info_id",
+        "stream_id",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+/// Names of the fields that param patches use to match list rows by key instead of by index, in order of preference
+pub fn prc_row_keys() -> Vec<String> {
+    GLOBAL_CONFIG
+        .lock()
+        .unwrap()
+        .get_field_json("prc_row_keys")
+        .unwrap_or_else(|_| default_prc_row_keys())
+}
+
 pub fn logger_level() -> String {
     let level: String = GLOBAL_CONFIG
         .lock()
@@ -95,6 +176,50 @@ pub fn logger_level() -> String {
     level
 }
 
+/// How to pick the value of a param that several PRC patches change
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PrcMergePolicy {
+    /// The patch loaded last provides the value, like before patches were merged
+    #[default]
+    LastWins,
+    /// The patch loaded first provides the value
+    FirstWins,
+    /// None of the patches are applied to the file
+    Error,
+}
+
+impl PrcMergePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrcMergePolicy::LastWins => "last_wins",
+            PrcMergePolicy::FirstWins => "first_wins",
+            PrcMergePolicy::Error => "error",
+        }
+    }
+}
+
+impl std::str::FromStr for PrcMergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last_wins" => Ok(PrcMergePolicy::LastWins),
+            "first_wins" => Ok(PrcMergePolicy::FirstWins),
+            "error" => Ok(PrcMergePolicy::Error),
+            _ => Err(format!("Unknown PRC merge policy '{}'", s)),
+        }
+    }
+}
+
+pub fn prc_merge_policy() -> PrcMergePolicy {
+    let policy: String = GLOBAL_CONFIG
+        .lock()
+        .unwrap()
+        .get_field("prc_merge_policy")
+        .unwrap_or_else(|_| String::from(PrcMergePolicy::default().as_str()));
+    policy.parse().unwrap_or_default()
+}
+
 pub fn file_logging_enabled() -> bool {
     GLOBAL_CONFIG.lock().unwrap().get_flag("log_to_file")
 }
@@ -103,22 +228,195 @@ pub fn legacy_discovery() -> bool {
     GLOBAL_CONFIG.lock().unwrap().get_flag("legacy_discovery")
 }
 
+/// Whether a `motion_list.yml` still replaces the motion list when other mods patch it with `.motdiff` files
+pub fn motion_list_replace_with_diffs() -> bool {
+    GLOBAL_CONFIG.lock().unwrap().get_flag("motion_list_replace_with_diffs")
+}
+
 pub fn use_folder_name() -> bool {
     GLOBAL_CONFIG.lock().unwrap().get_flag("use_folder_name")
 }
 
-pub fn set_mod_cache(cache: &HashSet<Hash40>) -> Result<(), ConfigError> {
+pub fn set_mod_cache(cache: &HashSet<String>) -> Result<(), ConfigError> {
     GLOBAL_CONFIG.lock().unwrap().set_field_json("mod_cache", &cache)
 }
 
-pub fn get_mod_cache() -> Result<HashSet<Hash40>, ConfigError> {
+pub fn get_mod_cache() -> Result<HashSet<String>, ConfigError> {
     GLOBAL_CONFIG.lock().unwrap().get_field_json("mod_cache")
 }
 
+pub mod mods {
+    use std::path::{Path, PathBuf};
+
+    use log::warn;
+    use serde::{Deserialize, Deserializer, Serialize};
+
+    use super::*;
+
+    /// Mod identifiers used to be integers, older info.toml files still write them without quotes
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawModId {
+        Integer(u64),
+        String(String),
+    }
+
+    /// Read the `id` of an info.toml, whether it is written as an integer or a string
+    pub fn deserialize_mod_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+        Ok(Option::<RawModId>::deserialize(deserializer)?.map(|id| match id {
+            RawModId::Integer(id) => id.to_string(),
+            RawModId::String(id) => id,
+        }))
+    }
+
+    /// An optional sub-folder of a mod, mounted as an additional root when selected
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    pub struct ModVariant {
+        pub name: String,
+        /// Path of the folder relative to the mod. It has to be inside a folder starting with a period, like `.variants/alt_music`,
+        /// so that the mod itself doesn't load it.
+        pub path: PathBuf,
+        pub description: Option<String>,
+        /// Whether the variant is selected when the workspace doesn't say otherwise
+        #[serde(default)]
+        pub default: bool,
+    }
+
+    impl ModVariant {
+        /// Check that the variant stays inside of the mod and is hidden from the mod's own discovery
+        pub fn is_valid(&self) -> bool {
+            use std::path::Component;
+
+            let mut components = self.path.components();
+
+            components.clone().all(|component| matches!(component, Component::Normal(_)))
+                && components.any(|component| component.as_os_str().to_str().map(|name| name.starts_with('.')).unwrap_or(false))
+        }
+    }
+
+    /// The fields of a mod's info.toml that change how it is loaded, the ones that are only displayed are read by the mod manager
+    #[derive(Debug, Default, Deserialize)]
+    pub struct ModManifest {
+        #[serde(default, deserialize_with = "deserialize_mod_id")]
+        pub id: Option<String>,
+        /// Identifiers of mods that have to be enabled for this one to work
+        #[serde(default)]
+        pub requires: Vec<String>,
+        /// Identifiers of mods that can't be enabled at the same time as this one
+        #[serde(default)]
+        pub conflicts_with: Vec<String>,
+        pub min_arcropolis_version: Option<String>,
+        #[serde(default)]
+        pub variants: Vec<ModVariant>,
+    }
+
+    impl ModManifest {
+        /// Identifier of the mod at the provided root with this manifest, see `get_mod_id`
+        pub fn mod_id(&self, root: &Path) -> Option<String> {
+            let id = self.id.clone().filter(|id| !id.is_empty());
+
+            id.or_else(|| root.file_name().and_then(|name| name.to_str()).map(String::from))
+        }
+    }
+
+    /// Read the manifest of the mod at the provided root.
+    /// Mods without an info.toml, or with one that can't be read, get a default one. The mod manager is the one reporting invalid files.
+    pub fn get_mod_manifest<P: AsRef<Path>>(root: P) -> ModManifest {
+        std::fs::read_to_string(root.as_ref().join("info.toml"))
+            .ok()
+            .and_then(|info| toml::from_str(&info).ok())
+            .unwrap_or_default()
+    }
+
+    /// Get the identifier of the mod at the provided root: the `id` field of its info.toml if there is one, the folder name otherwise.
+    ///
+    /// Presets and load orders refer to mods through this, so that giving a mod an id lets it survive folder renames.
+    pub fn get_mod_id<P: AsRef<Path>>(root: P) -> Option<String> {
+        let root = root.as_ref();
+
+        get_mod_manifest(root).mod_id(root)
+    }
+
+    /// List every mod folder in the mods directory alongside its identifier
+    pub fn list_mods() -> Vec<(String, PathBuf)> {
+        list_mods_by(|root| get_mod_id(root))
+    }
+
+    /// List every mod folder in the mods directory alongside its identifier, as given by `get_id`
+    pub fn list_mods_by<F: Fn(&Path) -> Option<String>>(get_id: F) -> Vec<(String, PathBuf)> {
+        let mut mods: Vec<(String, PathBuf)> = Vec::new();
+
+        let entries = match std::fs::read_dir(utils::paths::mods()) {
+            Ok(entries) => entries,
+            Err(_) => return mods,
+        };
+
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if !path.is_dir() {
+                continue;
+            }
+
+            if let Some(id) = get_id(&path) {
+                if let Some((_, existing)) = mods.iter().find(|(existing_id, _)| *existing_id == id) {
+                    warn!(
+                        "Mods '{}' and '{}' share the id '{}', only the first one can be referenced by presets.",
+                        existing.display(),
+                        path.display(),
+                        id
+                    );
+                    continue;
+                }
+
+                mods.push((id, path));
+            }
+        }
+
+        mods
+    }
+
+    /// Find the folder of the mod with the provided identifier
+    pub fn find_mod_path(id: &str) -> Option<PathBuf> {
+        list_mods().into_iter().find(|(mod_id, _)| mod_id == id).map(|(_, path)| path)
+    }
+
+    /// Convert every preset, load order and the mod cache from folder path hashes to mod identifiers.
+    /// Hashes that don't match a mod that currently exists are dropped, as there is no way to recover their name.
+    pub(crate) fn migrate_hashed_presets(storage: &mut StorageHolder<ArcStorage>) {
+        let lookup: HashMap<Hash40, String> = list_mods()
+            .into_iter()
+            .filter_map(|(id, path)| path.to_str().map(|path| (Hash40::from(path), id)))
+            .collect();
+
+        let workspace_list: HashMap<String, String> = storage.get_field_json("workspace_list").unwrap_or_default();
+
+        for preset_name in workspace_list.values() {
+            if let Ok(preset) = storage.get_field_json::<HashSet<Hash40>, _>(preset_name) {
+                let preset: HashSet<&String> = preset.iter().filter_map(|hash| lookup.get(hash)).collect();
+                let _ = storage.set_field_json(preset_name, &preset);
+            }
+
+            let order_field = presets::load_order_field(preset_name);
+            if let Ok(order) = storage.get_field_json::<Vec<Hash40>, _>(&order_field) {
+                let order: Vec<&String> = order.iter().filter_map(|hash| lookup.get(hash)).collect();
+                let _ = storage.set_field_json(&order_field, &order);
+            }
+        }
+
+        if let Ok(cache) = storage.get_field_json::<HashSet<Hash40>, _>("mod_cache") {
+            let cache: HashSet<&String> = cache.iter().filter_map(|hash| lookup.get(hash)).collect();
+            let _ = storage.set_field_json("mod_cache", &cache);
+        }
+    }
+}
+
 pub mod workspaces {
     use super::*;
-    use std::collections::HashMap;
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    };
 
+    use serde::{Deserialize, Serialize};
     use skyline_config::ConfigError;
     use thiserror::Error;
 
@@ -129,8 +427,220 @@ pub mod workspaces {
         #[error("a workspace with this name already exists")]
         AlreadyExists,
         #[error("failed to find workspace with name: {0}")]
-        MissingWorkspace(String), // #[error("failed to call from_str for the desired type")]
-                                  // FromStrErr,
+        MissingWorkspace(String),
+        #[error("failed to access the workspace file: {0}")]
+        IoError(#[from] std::io::Error),
+        #[error("the workspace file is not valid: {0}")]
+        FormatError(#[from] serde_json::Error),
+        #[error("the workspace file uses version {0}, which is not supported")]
+        UnsupportedVersion(u32),
+        // #[error("failed to call from_str for the desired type")]
+        // FromStrErr,
+    }
+
+    /// Bump this whenever the layout of exported workspaces changes
+    pub const WORKSPACE_EXPORT_VERSION: u32 = 1;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ExportedMod {
+        /// Identifier of the mod on the console it was exported from
+        pub id: String,
+        /// Used to find the mod when it has no info.toml id, or a different one on the importing console
+        pub folder_name: String,
+        pub enabled: bool,
+        /// Settings the workspace applies to this mod
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub options: HashMap<String, serde_json::Value>,
+    }
+
+    /// A workspace as a single file, meant to be shared between consoles
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct WorkspaceExport {
+        pub version: u32,
+        pub name: String,
+        pub mods: Vec<ExportedMod>,
+        /// Identifiers of the exported mods, from highest to lowest priority
+        pub load_order: Vec<String>,
+    }
+
+    #[derive(Debug)]
+    pub struct WorkspaceImport {
+        /// Name of the workspace that was created
+        pub name: String,
+        /// Mods of the file that could not be found in the mods directory
+        pub missing_mods: Vec<ExportedMod>,
+    }
+
+    impl WorkspaceExport {
+        pub fn from_storage(storage: &StorageHolder<ArcStorage>, name: &str) -> Result<Self, WorkspaceError> {
+            let workspace_list: HashMap<String, String> = storage.get_field_json("workspace_list")?;
+            let preset_name = workspace_list
+                .get(name)
+                .ok_or_else(|| WorkspaceError::MissingWorkspace(name.to_string()))?;
+
+            let preset: HashSet<String> = storage.get_field_json(preset_name)?;
+            let load_order: Vec<String> = storage.get_field_json(presets::load_order_field(preset_name)).unwrap_or_default();
+            let variants: HashMap<String, Vec<String>> = storage.get_field_json(presets::variants_field(preset_name)).unwrap_or_default();
+
+            let local_mods = mods::list_mods();
+
+            // Mods from the load order first so the file reads in priority order, then the enabled ones that are not part of it
+            let mut ids: Vec<&String> = load_order.iter().collect();
+            let mut remaining: Vec<&String> = preset.iter().filter(|id| !load_order.contains(*id)).collect();
+            remaining.sort();
+            ids.extend(remaining);
+
+            let mods = ids
+                .into_iter()
+                .map(|id| {
+                    let folder_name = local_mods
+                        .iter()
+                        .find(|(local_id, _)| local_id == id)
+                        .and_then(|(_, path)| path.file_name())
+                        .and_then(|name| name.to_str())
+                        .map_or_else(|| id.clone(), String::from);
+
+                    let mut options = HashMap::new();
+
+                    if let Some(selected) = variants.get(id) {
+                        options.insert(String::from("variants"), serde_json::json!(selected));
+                    }
+
+                    ExportedMod {
+                        id: id.clone(),
+                        folder_name,
+                        enabled: preset.contains(id),
+                        options,
+                    }
+                })
+                .collect();
+
+            Ok(Self {
+                version: WORKSPACE_EXPORT_VERSION,
+                name: name.to_string(),
+                mods,
+                load_order,
+            })
+        }
+
+        /// Create a new workspace out of this one, looking up mods by identifier first and by folder name second
+        pub fn import_into(&self, storage: &mut StorageHolder<ArcStorage>, name: &str) -> Result<WorkspaceImport, WorkspaceError> {
+            if self.version > WORKSPACE_EXPORT_VERSION {
+                return Err(WorkspaceError::UnsupportedVersion(self.version));
+            }
+
+            let mut workspace_list: HashMap<String, String> = storage.get_field_json("workspace_list")?;
+
+            if workspace_list.contains_key(name) {
+                return Err(WorkspaceError::AlreadyExists);
+            }
+
+            let local_mods = mods::list_mods();
+
+            let mut resolved: HashMap<&str, &str> = HashMap::new();
+            let mut missing_mods = Vec::new();
+
+            for exported in self.mods.iter() {
+                let local = local_mods.iter().find(|(id, _)| *id == exported.id).or_else(|| {
+                    local_mods.iter().find(|(_, path)| {
+                        path.file_name()
+                            .and_then(|folder_name| folder_name.to_str())
+                            .map(|folder_name| folder_name == exported.folder_name)
+                            .unwrap_or(false)
+                    })
+                });
+
+                match local {
+                    Some((id, _)) => {
+                        resolved.insert(exported.id.as_str(), id.as_str());
+                    },
+                    None => missing_mods.push(exported.clone()),
+                }
+            }
+
+            let preset: HashSet<&str> = self
+                .mods
+                .iter()
+                .filter(|exported| exported.enabled)
+                .filter_map(|exported| resolved.get(exported.id.as_str()).copied())
+                .collect();
+
+            let load_order: Vec<&str> = self.load_order.iter().filter_map(|id| resolved.get(id.as_str()).copied()).collect();
+
+            let variants: HashMap<&str, Vec<String>> = self
+                .mods
+                .iter()
+                .filter_map(|exported| {
+                    let id = resolved.get(exported.id.as_str())?;
+                    let selected = serde_json::from_value(exported.options.get("variants")?.clone()).ok()?;
+                    Some((*id, selected))
+                })
+                .collect();
+
+            let preset_name = presets::unused_preset_name(storage, &workspace_list, name);
+            storage.set_field_json(&preset_name, &preset)?;
+            storage.set_field_json(presets::load_order_field(&preset_name), &load_order)?;
+            storage.set_field_json(presets::variants_field(&preset_name), &variants)?;
+
+            workspace_list.insert(name.to_string(), preset_name);
+            storage.set_field_json("workspace_list", &workspace_list)?;
+
+            Ok(WorkspaceImport {
+                name: name.to_string(),
+                missing_mods,
+            })
+        }
+    }
+
+    /// Directory workspaces get exported to and imported from by default
+    pub fn export_dir() -> PathBuf {
+        utils::paths::workspaces().into_std_path_buf()
+    }
+
+    /// Default location a workspace gets exported to
+    pub fn export_path(name: &str) -> PathBuf {
+        let file_name: String = name.chars().map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' }).collect();
+        export_dir().join(format!("{}.json", file_name))
+    }
+
+    /// List the workspace files found in the default export directory
+    pub fn list_exports() -> Vec<PathBuf> {
+        let mut exports: Vec<PathBuf> = std::fs::read_dir(export_dir())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_file() && path.extension().map(|ext| ext == "json").unwrap_or(false))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        exports.sort();
+        exports
+    }
+
+    pub fn write_export(export: &WorkspaceExport, path: &Path) -> Result<(), WorkspaceError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, serde_json::to_string_pretty(export)?)?;
+        Ok(())
+    }
+
+    pub fn read_export(path: &Path) -> Result<WorkspaceExport, WorkspaceError> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn export_workspace(name: &str, path: &Path) -> Result<(), WorkspaceError> {
+        let export = WorkspaceExport::from_storage(&GLOBAL_CONFIG.lock().unwrap(), name)?;
+        write_export(&export, path)
+    }
+
+    /// Import the workspace file at the provided path, under the name it was exported with if none is provided
+    pub fn import_workspace(path: &Path, name: Option<&str>) -> Result<WorkspaceImport, WorkspaceError> {
+        let export = read_export(path)?;
+        let name = name.unwrap_or(&export.name);
+        export.import_into(&mut GLOBAL_CONFIG.lock().unwrap(), name)
     }
 
     pub fn get_list() -> Result<HashMap<String, String>, WorkspaceError> {
@@ -215,7 +725,6 @@ pub mod presets {
     use std::collections::HashSet;
 
     use skyline_config::ConfigError;
-    use smash_arc::Hash40;
     use thiserror::Error;
 
     use super::workspaces::WorkspaceError;
@@ -232,7 +741,7 @@ pub mod presets {
         // FromStrErr,
     }
 
-    pub fn get_active_preset() -> Result<HashSet<Hash40>, PresetError> {
+    pub fn get_active_preset() -> Result<HashSet<String>, PresetError> {
         let preset_name = workspaces::get_active_workspace()?;
         GLOBAL_CONFIG
             .lock()
@@ -241,7 +750,7 @@ pub mod presets {
             .map_err(PresetError::ConfigError)
     }
 
-    pub fn get_preset(workspace_name: &str) -> Result<HashSet<Hash40>, PresetError> {
+    pub fn get_preset(workspace_name: &str) -> Result<HashSet<String>, PresetError> {
         let preset_name = workspaces::get_workspace_by_name(workspace_name)?;
         GLOBAL_CONFIG
             .lock()
@@ -250,7 +759,7 @@ pub mod presets {
             .map_err(PresetError::ConfigError)
     }
 
-    pub fn replace_preset(workspace_name: &str, preset: &HashSet<Hash40>) -> Result<(), PresetError> {
+    pub fn replace_preset(workspace_name: &str, preset: &HashSet<String>) -> Result<(), PresetError> {
         let preset_name = workspaces::get_workspace_by_name(workspace_name)?;
         GLOBAL_CONFIG
             .lock()
@@ -259,7 +768,7 @@ pub mod presets {
             .map_err(PresetError::ConfigError)
     }
 
-    pub fn replace_active_preset(preset: &HashSet<Hash40>) -> Result<(), PresetError> {
+    pub fn replace_active_preset(preset: &HashSet<String>) -> Result<(), PresetError> {
         let preset_name = workspaces::get_active_workspace()?;
         GLOBAL_CONFIG
             .lock()
@@ -267,11 +776,101 @@ pub mod presets {
             .set_field_json(preset_name, preset)
             .map_err(PresetError::ConfigError)
     }
+
+    /// The load order is stored right next to the preset, in a field using the preset's name with a suffix
+    pub fn load_order_field(preset_name: &str) -> String {
+        format!("{}_order", preset_name)
+    }
+
+    /// Get the mods of a workspace sorted from highest to lowest priority. Mods that are missing from it come last.
+    pub fn get_load_order(workspace_name: &str) -> Result<Vec<String>, PresetError> {
+        let preset_name = workspaces::get_workspace_by_name(workspace_name)?;
+        // Workspaces made before load orders existed don't have the field, so don't treat it as an error
+        Ok(GLOBAL_CONFIG
+            .lock()
+            .unwrap()
+            .get_field_json(load_order_field(&preset_name))
+            .unwrap_or_default())
+    }
+
+    pub fn get_active_load_order() -> Result<Vec<String>, PresetError> {
+        let workspace_name = workspaces::get_active_workspace_name()?;
+        get_load_order(&workspace_name)
+    }
+
+    pub fn replace_load_order(workspace_name: &str, order: &[String]) -> Result<(), PresetError> {
+        let preset_name = workspaces::get_workspace_by_name(workspace_name)?;
+        GLOBAL_CONFIG
+            .lock()
+            .unwrap()
+            .set_field_json(load_order_field(&preset_name), &order)
+            .map_err(PresetError::ConfigError)
+    }
+
+    /// The selected variants are stored next to the preset, the same way as the load order
+    pub fn variants_field(preset_name: &str) -> String {
+        format!("{}_variants", preset_name)
+    }
+
+    /// Pick a preset name for a new workspace that no other workspace uses and that has nothing left behind in the storage
+    pub fn unused_preset_name(storage: &StorageHolder<ArcStorage>, workspace_list: &HashMap<String, String>, workspace_name: &str) -> String {
+        (workspace_list.len() + 1..)
+            .map(|index| format!("{}_preset{}", workspace_name, index))
+            .find(|preset_name| {
+                !workspace_list.values().any(|used| used == preset_name)
+                    && [preset_name.clone(), load_order_field(preset_name), variants_field(preset_name)]
+                        .iter()
+                        .all(|field| !storage.get_flag(field))
+            })
+            .unwrap()
+    }
+
+    /// Delete a preset along with its load order and variants, unless another workspace still uses it.
+    /// Fields are files in the storage like flags are, so clearing the flag is what removes them.
+    pub fn remove_preset(
+        storage: &mut StorageHolder<ArcStorage>,
+        workspace_list: &HashMap<String, String>,
+        preset_name: &str,
+    ) -> Result<(), ConfigError> {
+        if workspace_list.values().any(|used| used == preset_name) {
+            return Ok(());
+        }
+
+        storage.set_flag(preset_name, false)?;
+        storage.set_flag(load_order_field(preset_name), false)?;
+        storage.set_flag(variants_field(preset_name), false)
+    }
+
+    /// Get the names of the variants selected for each mod of a workspace.
+    /// Mods that are missing from it use the variants their info.toml marks as default.
+    pub fn get_variants(workspace_name: &str) -> Result<HashMap<String, Vec<String>>, PresetError> {
+        let preset_name = workspaces::get_workspace_by_name(workspace_name)?;
+        Ok(GLOBAL_CONFIG
+            .lock()
+            .unwrap()
+            .get_field_json(variants_field(&preset_name))
+            .unwrap_or_default())
+    }
+
+    pub fn get_active_variants() -> Result<HashMap<String, Vec<String>>, PresetError> {
+        let workspace_name = workspaces::get_active_workspace_name()?;
+        get_variants(&workspace_name)
+    }
+
+    pub fn replace_variants(workspace_name: &str, variants: &HashMap<String, Vec<String>>) -> Result<(), PresetError> {
+        let preset_name = workspaces::get_workspace_by_name(workspace_name)?;
+        GLOBAL_CONFIG
+            .lock()
+            .unwrap()
+            .set_field_json(variants_field(&preset_name), variants)
+            .map_err(PresetError::ConfigError)
+    }
 }
 
 pub struct ArcStorage(std::path::PathBuf);
 
 impl ArcStorage {
+    #[cfg(target_os = "switch")]
     pub fn new() -> Self {
         unsafe {
             nn::account::Initialize();
@@ -288,6 +887,12 @@ impl ArcStorage {
 
         Self(path)
     }
+
+    /// Off-console there are no user accounts, every configuration is stored in the same folder
+    #[cfg(not(target_os = "switch"))]
+    pub fn new() -> Self {
+        Self(PathBuf::from("host"))
+    }
 }
 
 impl ConfigStorage for ArcStorage {
@@ -20,6 +20,7 @@ var activeDescHeight = 0; // The height for the current active description so it
 
 var mods = [];
 var currentMods = [];
+var loadOrder = []; // Mod ids, from highest to lowest priority
 var modSize = 0;
 var pageCount = 0;
 
@@ -66,6 +67,88 @@ function toggleMod() {
     }));
 }
 
+function describeVariants(mod) {
+    if (mod["variants"] == null || mod["variants"].length == 0) {
+        return "";
+    }
+
+    var res = "<br /><br />Variants:";
+    mod["variants"].forEach(variant => {
+        var selected = mod["selected_variants"].includes(variant["name"]) ? "&#x2611;" : "&#x2610;";
+        var description = variant["description"] == null ? "" : ` - ${variant["description"]}`;
+        res += `<br />${selected} ${variant["name"]}${description}`;
+    });
+    return res;
+}
+
+function toggleVariant() {
+    var index = parseInt($(".is-focused").attr("data-mod-index"));
+
+    if (isNaN(index) || mods[index]["variants"] == null || mods[index]["variants"].length == 0) {
+        return;
+    }
+
+    var mod = mods[index];
+    var choices = mod["variants"].map((variant, i) => `${i + 1}. ${mod["selected_variants"].includes(variant["name"]) ? "[x]" : "[ ]"} ${variant["name"]}`);
+    var res = prompt(`Enter the number of the variant to toggle:\n${choices.join("\n")}`, "1");
+    if (res == null || res == undefined) { return; }
+
+    var variant = mod["variants"][parseInt(res) - 1];
+    if (variant == undefined) {
+        alert("No variant with that number exists!");
+        return;
+    }
+
+    var state = !mod["selected_variants"].includes(variant["name"]);
+    if (state) {
+        mod["selected_variants"].push(variant["name"]);
+    } else {
+        mod["selected_variants"] = mod["selected_variants"].filter(name => name != variant["name"]);
+    }
+
+    window.nx.sendMessage(JSON.stringify({
+        "SetVariant": {
+            "id": index,
+            "variant": variant["name"],
+            "state": state
+        }
+    }));
+
+    $("#description").html(mod["description"] + describeVariants(mod));
+    updateCurrentDesc();
+}
+
+function buildLoadOrder() {
+    // Mods that are not part of the load order yet go last, in the order they were listed
+    loadOrder = mods.map(x => x["id"]).sort((a, b) => {
+        var priorityA = mods[a]["priority"] == null ? Number.MAX_SAFE_INTEGER : mods[a]["priority"];
+        var priorityB = mods[b]["priority"] == null ? Number.MAX_SAFE_INTEGER : mods[b]["priority"];
+        return priorityA != priorityB ? priorityA - priorityB : a - b;
+    });
+}
+
+function raisePriority() {
+    var index = parseInt($(".is-focused").attr("data-mod-index"));
+    var position = loadOrder.indexOf(index);
+
+    if (isNaN(index) || position <= 0) {
+        return;
+    }
+
+    loadOrder.splice(position, 1);
+    loadOrder.splice(position - 1, 0, index);
+    loadOrder.forEach((id, priority) => mods[id]["priority"] = priority);
+
+    window.nx.sendMessage(JSON.stringify({
+        "SetLoadOrder": {
+            "ids": loadOrder
+        }
+    }));
+
+    refreshCurrentMods();
+    $(`#btn-mods-${index}`).focus();
+}
+
 function updateCurrentDesc() {
     // Reset current description height
     currentDescHeight = 0;
@@ -173,7 +256,7 @@ function move(source, target) {
         target.classList.add("is-focused");
         target.focus();
         var mod = mods[target.getAttribute("data-mod-index")];
-        $("#description").html(mod["description"]);
+        $("#description").html(mod["description"] + describeVariants(mod));
         $("#version").html(mod["version"]);
         $("#authors").html(mod["authors"]);
         $("#preview").attr("src", `img/${mod['id']}`);
@@ -355,6 +438,8 @@ function updateSort() {
             }
             return 0;
         });
+    } else if (sortType == "priority") {
+        currentMods = JSON.parse(JSON.stringify(currentMods)).sort((a, b) => loadOrder.indexOf(a) - loadOrder.indexOf(b));
     } else if (sortType == "disabled") {
         currentMods = JSON.parse(JSON.stringify(currentMods)).sort((a, b) => {
             if (!mods[a]["is_disabled"] != !mods[b]["is_disabled"]) {
@@ -374,6 +459,26 @@ function updateSort() {
 
 window.nx.addEventListener("message", (e) => {
     var info = JSON.parse(e.data);
+
+    // Mods required by the ones that were just enabled
+    if ("enabled_mods" in info) {
+        info["enabled_mods"].forEach(index => {
+            mods[index]["is_disabled"] = false;
+            $(`#btn-mods-${index} .img-check`).removeClass("hidden");
+        });
+        return;
+    }
+
+    // Mods that were kept disabled because they conflict with enabled ones
+    if ("blocked_mods" in info) {
+        info["blocked_mods"].forEach(index => {
+            mods[index]["is_disabled"] = true;
+            $(`#btn-mods-${index} .img-check`).addClass("hidden");
+        });
+        alert(`These mods can't be enabled together:\n\n${info["conflicts"].join("\n")}`);
+        return;
+    }
+
     if (!("mod_size" in info))
         return;
 
@@ -396,6 +501,7 @@ window.addEventListener("DOMContentLoaded", (e) => {
         }
 
         currentMods = mods.map(x => x["id"]);
+        buildLoadOrder();
         refreshCurrentMods();
     } else {
 
@@ -404,6 +510,7 @@ window.addEventListener("DOMContentLoaded", (e) => {
             url: "mods.json",
             success: (data) => {
                 mods = data["entries"];
+                buildLoadOrder();
                 $("#workspace").html(data["workspace"]);
                 currentMods = mods.map(x => x["id"]);
                 refreshCurrentMods();
@@ -433,7 +540,16 @@ window.addEventListener("DOMContentLoaded", (e) => {
             }
         });
 
-        window.nx.footer.setAssign("X", "", () => {});
+        window.nx.footer.setAssign("X", "", () => {
+            if (currentState == MOD_MENU && document.getElementById('sortOptions').value == "priority") {
+                raisePriority();
+            }
+        });
+        window.nx.footer.setAssign("ZR", "", () => {
+            if (currentState == MOD_MENU) {
+                toggleVariant();
+            }
+        });
         window.nx.footer.setAssign("B", "", () => {
             if (currentState == SUB_MENU) {
                 showModMenu();
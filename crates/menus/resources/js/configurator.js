@@ -43,7 +43,7 @@ window.addEventListener("DOMContentLoaded", (e) => {
     // Code to handle this session wasn't made to detect a closure by button
     window.nx.footer.setAssign("B", "", () => {
         if (getCurrentActiveContainer().attr("id") != "workspaces") {
-            changeDivFromTo('logging', 'workspaces', `0`);
+            changeDivFromTo(getCurrentActiveContainer().attr("id"), 'workspaces', `0`);
         } else {
             submit(`exit`, `true`);
         }
@@ -57,6 +57,8 @@ function getCurrentActiveContainer() {
         return $("#workspaces");
     } else if ($("#logging").is(":visible")) {
         return $("#logging");
+    } else if ($("#prc_merge").is(":visible")) {
+        return $("#prc_merge");
     }
 }
 
@@ -4,6 +4,7 @@ const WORKSPACE_CONTROL = "&#xe000 Select Option";
 var workspaces = [];
 var selected_workspace = 0;
 var active_workspace = "";
+var exports = [];
 
 window.addEventListener("DOMContentLoaded", (e) => {
     if (!isNx) {
@@ -20,6 +21,7 @@ window.addEventListener("DOMContentLoaded", (e) => {
             success: (data) => {
                 workspaces = data["workspaces"];
                 active_workspace = data["active_workspace"];
+                exports = data["exports"];
                 setupWorkspaces();
             }
         });
@@ -153,6 +155,12 @@ function setupWorkspaces() {
             <h2>Create Workspace</h2>
         </div>
     </button>
+    <button onclick="importWorkspace()" class="flex-item">
+        <div class="icon-background"></div>
+        <div class="item-container">
+            <h2>Import Workspace</h2>
+        </div>
+    </button>
     `;
 
     var buttons = document.querySelectorAll('button');
@@ -315,4 +323,74 @@ function editWorkspace() {
         }));
         window.location.href = "http://localhost/quit";
     }
+}
+
+function exportWorkspace() {
+    if (!isNx) {
+        alert("Workspaces can only be exported on the console.");
+        return;
+    }
+
+    // The result is shown once ARCropolis replies
+    window.nx.sendMessage(JSON.stringify({
+        "Export": {
+            "name": workspaces[selected_workspace]
+        }
+    }));
+}
+
+if (isNx) {
+    window.nx.addEventListener("message", (e) => {
+        var info = JSON.parse(e.data);
+
+        if ("export" in info) {
+            var result = info["export"];
+
+            if (result["success"]) {
+                if (!exports.includes(result["file_name"])) {
+                    exports.push(result["file_name"]);
+                }
+                alert(`Workspace ${result["name"]} has been exported to ${result["path"]}.`);
+            } else {
+                alert(`Failed to export workspace ${result["name"]}: ${result["error"]}`);
+            }
+        }
+    });
+}
+
+function importWorkspace() {
+    if (exports.length == 0) {
+        alert("No workspace files were found in sd:/ultimate/arcropolis/workspaces");
+        return;
+    }
+
+    var file = prompt(`Enter the file to import:\n${exports.join("\n")}`, exports[0]);
+    if (file == null || file == undefined) { return; }
+
+    if (!exports.includes(file)) {
+        alert("No workspace file with that name exists!");
+        return;
+    }
+
+    var res = prompt("Name for imported workspace", file.replace(/\.json$/, ""));
+    if (res == null || res == undefined) { return; }
+
+    if (workspaces.includes(res)) {
+        alert("Workspace with that name already exists!");
+        return;
+    }
+
+    workspaces.push(res);
+
+    if (isNx) {
+        window.nx.sendMessage(JSON.stringify({
+            "Import": {
+                "file_name": file,
+                "name": res
+            }
+        }));
+    }
+
+    selected_workspace = workspaces.length - 1;
+    changeDivFromTo('workspaces', 'workspaces');
 }
\ No newline at end of file
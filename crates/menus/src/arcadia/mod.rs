@@ -1,11 +1,14 @@
 // #![feature(proc_macro_hygiene)]
 
-use std::{collections::HashSet, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
-use log::{debug, error};
+use ::config::mods::ModVariant;
+use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 use skyline_web::Webpage;
-use smash_arc::Hash40;
 
 use crate::{config, utils};
 
@@ -17,7 +20,12 @@ pub struct Information {
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Entry {
+    /// Index of the entry in the page, info.toml uses `id` for the mod identifier instead
+    #[serde(skip_deserializing)]
     id: Option<u32>,
+    /// Identifier the presets refer to the mod with
+    #[serde(rename(serialize = "mod_id", deserialize = "id"), default, deserialize_with = "::config::mods::deserialize_mod_id")]
+    mod_id: Option<String>,
     folder_name: Option<String>,
     is_disabled: Option<bool>,
     display_name: Option<String>,
@@ -25,6 +33,17 @@ pub struct Entry {
     version: Option<String>,
     description: Option<String>,
     category: Option<String>,
+    /// Identifiers of the mods that get enabled alongside this one
+    requires: Option<Vec<String>>,
+    /// Identifiers of the mods that can't be enabled alongside this one
+    conflicts_with: Option<Vec<String>>,
+    variants: Option<Vec<ModVariant>>,
+    /// Names of the variants selected in the workspace
+    #[serde(skip_deserializing)]
+    selected_variants: Option<Vec<String>>,
+    /// Position in the workspace's load order, if it is part of it
+    #[serde(skip_deserializing)]
+    priority: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,12 +51,14 @@ pub enum ArcadiaMessage {
     ToggleMod { id: usize, state: bool },
     ChangeAll { state: bool },
     ChangeIndexes { state: bool, indexes: Vec<usize> },
+    SetLoadOrder { ids: Vec<usize> },
+    SetVariant { id: usize, variant: String, state: bool },
     DebugPrint { message: String },
     GetModSize,
     Closure,
 }
 
-pub fn get_mods(presets: &HashSet<Hash40>) -> Vec<Entry> {
+pub fn get_mods(presets: &HashSet<String>, load_order: &[String], variants: &HashMap<String, Vec<String>>) -> Vec<Entry> {
     let mut id: u32 = 0;
     let use_folder_name = ::config::use_folder_name();
     std::fs::read_dir(utils::paths::mods())
@@ -50,7 +71,9 @@ pub fn get_mods(presets: &HashSet<Hash40>) -> Vec<Entry> {
                 return None;
             }
 
-            let disabled = !presets.contains(&Hash40::from(path_to_be_used.to_str().unwrap()));
+            let mod_id = ::config::mods::get_mod_id(&path_to_be_used)?;
+            let disabled = !presets.contains(&mod_id);
+            let priority = load_order.iter().position(|x| *x == mod_id).map(|x| x as u32);
 
             let folder_name = Path::new(&path_to_be_used).file_name().unwrap().to_os_string().into_string().unwrap();
 
@@ -58,8 +81,10 @@ pub fn get_mods(presets: &HashSet<Hash40>) -> Vec<Entry> {
 
             let default_entry = Entry {
                 id: Some(id),
+                mod_id: Some(mod_id.clone()),
                 folder_name: Some(folder_name.clone()),
                 is_disabled: Some(disabled),
+                priority,
                 version: Some("???".to_string()),
                 // description: Some("".to_string()),
                 category: Some("Misc".to_string()),
@@ -69,6 +94,7 @@ pub fn get_mods(presets: &HashSet<Hash40>) -> Vec<Entry> {
             let mod_info = match toml::from_str::<Entry>(&std::fs::read_to_string(info_path).unwrap_or_default()) {
                 Ok(res) => Entry {
                     id: Some(id),
+                    mod_id: Some(mod_id.clone()),
                     folder_name: Some(folder_name.clone()),
                     display_name: if use_folder_name { Some(folder_name) } else { res.display_name.or(Some(folder_name)) },
                     authors: res.authors.or_else(|| Some(String::from("???"))),
@@ -82,6 +108,18 @@ pub fn get_mods(presets: &HashSet<Hash40>) -> Vec<Entry> {
                         }
                     }),
                     description: Some(res.description.unwrap_or_default().replace('\n', "<br />")),
+                    requires: res.requires,
+                    conflicts_with: res.conflicts_with,
+                    selected_variants: Some(variants.get(&mod_id).cloned().unwrap_or_else(|| {
+                        res.variants
+                            .iter()
+                            .flatten()
+                            .filter(|variant| variant.default)
+                            .map(|variant| variant.name.clone())
+                            .collect()
+                    })),
+                    variants: res.variants,
+                    priority,
                 },
                 Err(e) => {
                     skyline_web::dialog_ok::DialogOk::ok(format!("The following info.toml is not valid: \n\n* '{}'\n\nError: {}", folder_name, e,));
@@ -96,6 +134,91 @@ pub fn get_mods(presets: &HashSet<Hash40>) -> Vec<Entry> {
         .collect()
 }
 
+/// Enable the mods required by the entry at the provided index, and the ones they require.
+/// Returns the indexes of the entries that were enabled, so the page can reflect it.
+fn enable_dependencies(entries: &[Entry], presets: &mut HashSet<String>, index: usize) -> Vec<usize> {
+    let mut enabled = Vec::new();
+    let mut pending = vec![index];
+
+    while let Some(index) = pending.pop() {
+        for dependency in entries[index].requires.iter().flatten() {
+            match entries.iter().position(|entry| entry.mod_id.as_ref() == Some(dependency)) {
+                Some(dependency_index) => {
+                    if presets.insert(dependency.clone()) {
+                        debug!("Enabling {} as a dependency of {}", dependency, entries[index].mod_id.as_ref().unwrap());
+                        enabled.push(dependency_index);
+                        pending.push(dependency_index);
+                    }
+                },
+                None => warn!("{} requires {}, which is not installed", entries[index].mod_id.as_ref().unwrap(), dependency),
+            }
+        }
+    }
+
+    enabled
+}
+
+/// Enabled mods that can't be enabled alongside the entry at the provided index, because either of them lists the other in `conflicts_with`
+fn conflicting_mods(entries: &[Entry], presets: &HashSet<String>, index: usize) -> Vec<usize> {
+    let lists = |entry: &Entry, other: &Entry| entry.conflicts_with.iter().flatten().any(|id| other.mod_id.as_ref() == Some(id));
+
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(other, entry)| *other != index && entry.mod_id.as_ref().map(|id| presets.contains(id)).unwrap_or(false))
+        .filter(|(_, entry)| lists(&entries[index], entry) || lists(entry, &entries[index]))
+        .map(|(other, _)| other)
+        .collect()
+}
+
+/// Enable the entry at the provided index and the mods it requires, unless one of them conflicts with a mod that is enabled.
+/// Returns the indexes of the dependencies that were enabled, or the conflicts that kept the mod disabled.
+fn enable_mod(entries: &[Entry], presets: &mut HashSet<String>, index: usize) -> Result<Vec<usize>, Vec<String>> {
+    let name = |index: usize| entries[index].display_name.clone().or_else(|| entries[index].mod_id.clone()).unwrap_or_default();
+
+    let newly_enabled = presets.insert(entries[index].mod_id.clone().unwrap());
+    let dependencies = enable_dependencies(entries, presets, index);
+
+    let mut conflicts = Vec::new();
+    let mut reported = HashSet::new();
+
+    for enabled in std::iter::once(index).chain(dependencies.iter().copied()) {
+        for other in conflicting_mods(entries, presets, enabled) {
+            // Two of the mods that were just enabled can conflict with each other, only report them once
+            if reported.insert((enabled.min(other), enabled.max(other))) {
+                conflicts.push(format!("{} conflicts with {}", name(enabled), name(other)));
+            }
+        }
+    }
+
+    if conflicts.is_empty() {
+        return Ok(dependencies);
+    }
+
+    if newly_enabled {
+        presets.remove(entries[index].mod_id.as_ref().unwrap());
+    }
+
+    for dependency in dependencies {
+        presets.remove(entries[dependency].mod_id.as_ref().unwrap());
+    }
+
+    warn!("Not enabling {}: {}", name(index), conflicts.join(", "));
+
+    Err(conflicts)
+}
+
+/// Tell the page which mods were enabled as dependencies, and which ones were kept disabled because of their conflicts
+fn send_enable_result(session: &skyline_web::WebSession, dependencies: Vec<usize>, blocked: Vec<usize>, conflicts: Vec<String>) {
+    if !dependencies.is_empty() {
+        session.send(format!("{{ \"enabled_mods\": {:?} }}", dependencies).as_str());
+    }
+
+    if !blocked.is_empty() {
+        session.send(&serde_json::json!({ "blocked_mods": blocked, "conflicts": conflicts }).to_string());
+    }
+}
+
 pub fn show_arcadia(workspace: Option<String>) {
     let umm_path = utils::paths::mods();
 
@@ -109,8 +232,14 @@ pub fn show_arcadia(workspace: Option<String>) {
     let presets = ::config::presets::get_preset(&workspace_name).unwrap();
     let mut new_presets = presets.clone();
 
+    let load_order = ::config::presets::get_load_order(&workspace_name).unwrap_or_default();
+    let mut new_load_order = load_order.clone();
+
+    let variants = ::config::presets::get_variants(&workspace_name).unwrap_or_default();
+    let mut new_variants = variants.clone();
+
     let mods: Information = Information {
-        entries: get_mods(&presets),
+        entries: get_mods(&presets, &load_order, &variants),
         workspace: workspace_name.clone(),
     };
 
@@ -155,17 +284,19 @@ pub fn show_arcadia(workspace: Option<String>) {
     while let Ok(message) = session.recv_json::<ArcadiaMessage>() {
         match message {
             ArcadiaMessage::ToggleMod { id, state } => {
-                let path = format!("{}/{}", umm_path, mods.entries[id].folder_name.as_ref().unwrap());
-                let hash = Hash40::from(path.as_str());
-                debug!("Setting {} to {}", path, state);
+                let mod_id = mods.entries[id].mod_id.clone().unwrap();
+                debug!("Setting {} to {}", mod_id, state);
 
                 if state {
-                    new_presets.insert(hash);
+                    match enable_mod(&mods.entries, &mut new_presets, id) {
+                        Ok(dependencies) => send_enable_result(&session, dependencies, Vec::new(), Vec::new()),
+                        Err(conflicts) => send_enable_result(&session, Vec::new(), vec![id], conflicts),
+                    }
                 } else {
-                    new_presets.remove(&hash);
+                    new_presets.remove(&mod_id);
                 }
 
-                debug!("{} has been {}", path, state);
+                debug!("{} has been {}", mod_id, state);
             },
             ArcadiaMessage::ChangeAll { state } => {
                 debug!("Changing all to {}", state);
@@ -173,25 +304,65 @@ pub fn show_arcadia(workspace: Option<String>) {
                 if !state {
                     new_presets.clear();
                 } else {
-                    for item in mods.entries.iter() {
-                        let path = format!("{}/{}", umm_path, item.folder_name.as_ref().unwrap());
-                        let hash = Hash40::from(path.as_str());
+                    let (mut blocked, mut conflicts) = (Vec::new(), Vec::new());
 
-                        new_presets.insert(hash);
+                    // Mods are enabled one by one, so the first of two conflicting mods stays enabled
+                    for idx in 0..mods.entries.len() {
+                        if let Err(mod_conflicts) = enable_mod(&mods.entries, &mut new_presets, idx) {
+                            blocked.push(idx);
+                            conflicts.extend(mod_conflicts);
+                        }
                     }
+
+                    send_enable_result(&session, Vec::new(), blocked, conflicts);
                 }
             },
             ArcadiaMessage::ChangeIndexes { state, indexes } => {
+                let (mut dependencies, mut blocked, mut conflicts) = (Vec::new(), Vec::new(), Vec::new());
+
                 for idx in indexes {
-                    let path = format!("{}/{}", umm_path, mods.entries[idx].folder_name.as_ref().unwrap());
-                    let hash = Hash40::from(path.as_str());
-                    debug!("Setting {} to {}", path, state);
+                    let mod_id = mods.entries[idx].mod_id.clone().unwrap();
+                    debug!("Setting {} to {}", mod_id, state);
 
                     if state {
-                        new_presets.insert(hash);
+                        match enable_mod(&mods.entries, &mut new_presets, idx) {
+                            Ok(enabled) => dependencies.extend(enabled),
+                            Err(mod_conflicts) => {
+                                blocked.push(idx);
+                                conflicts.extend(mod_conflicts);
+                            },
+                        }
                     } else {
-                        new_presets.remove(&hash);
+                        new_presets.remove(&mod_id);
+                    }
+                }
+
+                send_enable_result(&session, dependencies, blocked, conflicts);
+            },
+            ArcadiaMessage::SetLoadOrder { ids } => {
+                debug!("Updating the load order of {} mods", ids.len());
+
+                new_load_order = ids
+                    .iter()
+                    .filter_map(|id| mods.entries.get(*id))
+                    .filter_map(|entry| entry.mod_id.clone())
+                    .collect();
+            },
+            ArcadiaMessage::SetVariant { id, variant, state } => {
+                let entry = &mods.entries[id];
+                debug!("Setting variant {} of {} to {}", variant, entry.mod_id.as_ref().unwrap(), state);
+
+                // Start from what the page showed, as mods missing from the workspace use their default variants
+                let selected = new_variants
+                    .entry(entry.mod_id.clone().unwrap())
+                    .or_insert_with(|| entry.selected_variants.clone().unwrap_or_default());
+
+                if state {
+                    if !selected.contains(&variant) {
+                        selected.push(variant);
                     }
+                } else {
+                    selected.retain(|name| *name != variant);
                 }
             },
             ArcadiaMessage::DebugPrint { message } => {
@@ -211,8 +382,10 @@ pub fn show_arcadia(workspace: Option<String>) {
 
     let active_workspace = ::config::workspaces::get_active_workspace_name().unwrap();
     ::config::presets::replace_preset(&workspace_name, &new_presets).unwrap();
+    ::config::presets::replace_load_order(&workspace_name, &new_load_order).unwrap();
+    ::config::presets::replace_variants(&workspace_name, &new_variants).unwrap();
 
-    if new_presets != presets {
+    if new_presets != presets || new_load_order != load_order || new_variants != variants {
         // Acquire the filesystem so we can check if it's already finished or not (for boot-time mod manager)
         // if let Some(_filesystem) = crate::GLOBAL_FILESYSTEM.try_read() {
             if active_workspace.eq(&workspace_name) && skyline_web::dialog::Dialog::yes_no("Your preset has successfully been updated!<br>Your changes will take effect on the next boot.<br>Would you like to reboot the game to reload your mods?") {
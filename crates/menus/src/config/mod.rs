@@ -64,9 +64,20 @@ pub fn show_config_editor<CS: ConfigStorage>(storage: &mut StorageHolder<CS>) {
         session.send("use_folder_name");
     }
 
+    if storage.get_flag("motion_list_replace_with_diffs") {
+        session.send("motion_list_replace_with_diffs");
+    }
+
+    if storage.get_flag("skip_memory_warning") {
+        session.send("skip_memory_warning");
+    }
+
     let logging: String = storage.get_field("logging_level").unwrap_or(String::from("Info"));
     session.send(&logging);
 
+    let prc_merge: String = storage.get_field("prc_merge_policy").unwrap_or(String::from("last_wins"));
+    session.send(&format!("prc_{}", prc_merge));
+
     while let Ok(msg) = session.recv_json::<ConfigChanged>() {
         match msg.category.as_str() {
             "log" => {
@@ -76,6 +87,12 @@ pub fn show_config_editor<CS: ConfigStorage>(storage: &mut StorageHolder<CS>) {
                 session.send(&msg.value);
                 // info!("Set logger to {}", &msg.value);
             },
+            "prc_merge" => {
+                let curr_value: String = storage.get_field("prc_merge_policy").unwrap_or(String::from("last_wins"));
+                session.send(&format!("prc_{}", curr_value));
+                storage.set_field("prc_merge_policy", &msg.value).unwrap();
+                session.send(&format!("prc_{}", msg.value));
+            },
             // A "true" value is passed for flags, you might be wondering why.
             // If you pass ``null``, the browser closes, because Value is not a String or a Option. I think?
             // You can change it if you feel like it, I just didn't have it within me at this point
@@ -121,6 +138,16 @@ pub fn show_config_editor<CS: ConfigStorage>(storage: &mut StorageHolder<CS>) {
                 // info!("Set use_folder_name flag to {}", curr_value);
                 session.send("use_folder_name");
             },
+            "motion_list_replace_with_diffs" => {
+                let curr_value = !storage.get_flag("motion_list_replace_with_diffs");
+                storage.set_flag("motion_list_replace_with_diffs", curr_value).unwrap();
+                session.send("motion_list_replace_with_diffs");
+            },
+            "skip_memory_warning" => {
+                let curr_value = !storage.get_flag("skip_memory_warning");
+                storage.set_flag("skip_memory_warning", curr_value).unwrap();
+                session.send("skip_memory_warning");
+            },
             _ => break,
         }
     }
@@ -14,7 +14,13 @@ use skyline_web::Webpage;
 
 mod utils;
 
-pub fn show_main_menu() {
+/// Actions picked in the main menu that have to be carried out by ARCropolis itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MainMenuAction {
+    ReloadMods,
+}
+
+pub fn show_main_menu() -> Option<MainMenuAction> {
     let response = std::boxed::Box::new(
         Webpage::new()
             .htdocs_dir("contents")
@@ -40,7 +46,24 @@ pub fn show_main_menu() {
             "http://localhost/config" => {
                 show_config_editor(&mut ::config::GLOBAL_CONFIG.lock().unwrap());
             },
+            "http://localhost/reload" => return Some(MainMenuAction::ReloadMods),
             _ => {},
         },
     }
+
+    None
+}
+
+/// Tell the user how a reload of the mod files went
+pub fn show_reload_result(reloaded: usize, invalidated: usize, skipped: &[String]) {
+    let mut message = format!(
+        "{} modded files were reloaded, {} of them will be read again right away.<br>The others are picked up the next time the game loads them.",
+        reloaded, invalidated
+    );
+
+    if !skipped.is_empty() {
+        message.push_str(&format!("<br><br>These files need a reboot to change:<br>{}", skipped.join("<br>")));
+    }
+
+    skyline_web::dialog_ok::DialogOk::ok(message);
 }
@@ -1,9 +1,12 @@
 use std::collections::{HashMap, HashSet};
 
-use ::config::GLOBAL_CONFIG;
+use ::config::{
+    workspaces::{self, WorkspaceExport},
+    GLOBAL_CONFIG,
+};
+use log::error;
 use serde::{Deserialize, Serialize};
 use skyline_web::Webpage;
-use smash_arc::Hash40;
 
 use crate::config;
 
@@ -11,6 +14,8 @@ use crate::config;
 pub struct Information {
     workspaces: Vec<String>,
     active_workspace: String,
+    /// Workspace files found in the export directory, that can be imported
+    exports: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -21,6 +26,8 @@ pub enum WorkspacesMessage {
     Rename { source_name: String, target_name: String },
     Remove { name: String },
     Duplicate { source_name: String, target_name: String },
+    Export { name: String },
+    Import { file_name: String, name: String },
     ClosureRequest,
 }
 
@@ -33,8 +40,15 @@ pub fn show_workspaces() {
     let info: Information = Information {
         workspaces: workspace_list.iter().map(|(k, _v)| k.clone()).collect(),
         active_workspace: active_workspace.clone(),
+        exports: workspaces::list_exports()
+            .iter()
+            .filter_map(|path| path.file_name().and_then(|name| name.to_str()).map(String::from))
+            .collect(),
     };
 
+    // The browser can't show dialogs over itself, so keep what happened with imports for when it closes
+    let mut transfer_messages: Vec<String> = Vec::new();
+
     let mut workspace_to_edit: Option<String> = None;
 
     let session = Webpage::new()
@@ -53,10 +67,10 @@ pub fn show_workspaces() {
     while let Ok(message) = session.recv_json::<WorkspacesMessage>() {
         match message {
             WorkspacesMessage::Create { name } => {
-                let preset_name = format!("{}_preset{}", name, workspace_list.len() + 1);
+                let preset_name = ::config::presets::unused_preset_name(&storage, &workspace_list, &name);
                 workspace_list.insert(name.clone(), preset_name.clone());
                 storage.set_field_json("workspace_list", &workspace_list).unwrap_or_default();
-                storage.set_field_json(&preset_name, &HashSet::<Hash40>::new()).unwrap();
+                storage.set_field_json(&preset_name, &HashSet::<String>::new()).unwrap();
             },
             WorkspacesMessage::SetActive { name } => {
                 active_workspace = name.clone();
@@ -76,19 +90,80 @@ pub fn show_workspaces() {
                 storage.set_field_json("workspace_list", &workspace_list).unwrap_or_default();
             },
             WorkspacesMessage::Remove { name } => {
-                workspace_list.remove(&name);
-                storage.set_field_json("workspace_list", &workspace_list).unwrap_or_default();
+                if let Some(preset_name) = workspace_list.remove(&name) {
+                    storage.set_field_json("workspace_list", &workspace_list).unwrap_or_default();
+
+                    if let Err(e) = ::config::presets::remove_preset(&mut storage, &workspace_list, &preset_name) {
+                        error!("Failed to remove the preset of workspace {}: {}", name, e);
+                    }
+                }
             },
             WorkspacesMessage::Duplicate { source_name, target_name } => {
                 let source_preset_name = &workspace_list[&source_name];
-                let target_preset_name = format!("{}_preset{}", target_name, workspace_list.len() + 1);
+                let target_preset_name = ::config::presets::unused_preset_name(&storage, &workspace_list, &target_name);
 
-                let presets: HashSet<Hash40> = storage.get_field_json(source_preset_name).unwrap_or_default();
+                let presets: HashSet<String> = storage.get_field_json(source_preset_name).unwrap_or_default();
+                let load_order: Vec<String> = storage
+                    .get_field_json(::config::presets::load_order_field(source_preset_name))
+                    .unwrap_or_default();
+                let variants: HashMap<String, Vec<String>> = storage
+                    .get_field_json(::config::presets::variants_field(source_preset_name))
+                    .unwrap_or_default();
 
                 workspace_list.insert(target_name, target_preset_name.clone());
+                storage
+                    .set_field_json(::config::presets::load_order_field(&target_preset_name), &load_order)
+                    .unwrap();
+                storage
+                    .set_field_json(::config::presets::variants_field(&target_preset_name), &variants)
+                    .unwrap();
                 storage.set_field_json(target_preset_name, &presets).unwrap();
                 storage.set_field_json("workspace_list", &workspace_list).unwrap_or_default();
             },
+            WorkspacesMessage::Export { name } => {
+                let path = workspaces::export_path(&name);
+
+                // The page waits for this reply to tell whether the export worked
+                let reply = match WorkspaceExport::from_storage(&storage, &name).and_then(|export| workspaces::write_export(&export, &path)) {
+                    Ok(_) => serde_json::json!({
+                        "export": {
+                            "success": true,
+                            "name": name,
+                            "path": path.display().to_string(),
+                            "file_name": path.file_name().and_then(|name| name.to_str()).unwrap_or_default(),
+                        }
+                    }),
+                    Err(e) => {
+                        error!("Failed to export workspace {}: {}", name, e);
+                        serde_json::json!({ "export": { "success": false, "name": name, "error": e.to_string() } })
+                    },
+                };
+
+                session.send(&reply.to_string());
+            },
+            WorkspacesMessage::Import { file_name, name } => {
+                let path = workspaces::export_dir().join(&file_name);
+
+                match workspaces::read_export(&path).and_then(|export| export.import_into(&mut storage, &name)) {
+                    Ok(import) => {
+                        // The import wrote the list to the storage, so pick it up to not overwrite it later on
+                        workspace_list = storage.get_field_json("workspace_list").unwrap_or_default();
+
+                        if import.missing_mods.is_empty() {
+                            transfer_messages.push(format!("Workspace {} has been imported from {}", import.name, file_name));
+                        } else {
+                            let missing: Vec<&str> = import.missing_mods.iter().map(|exported| exported.folder_name.as_str()).collect();
+                            transfer_messages.push(format!(
+                                "Workspace {} has been imported from {}, but these mods could not be found:<br>{}",
+                                import.name,
+                                file_name,
+                                missing.join("<br>")
+                            ));
+                        }
+                    },
+                    Err(e) => transfer_messages.push(format!("Failed to import {}: {}", file_name, e)),
+                }
+            },
             WorkspacesMessage::ClosureRequest => {
                 session.wait_for_exit();
                 session.exit();
@@ -105,6 +180,10 @@ pub fn show_workspaces() {
 
     drop(storage);
 
+    for message in transfer_messages {
+        skyline_web::dialog_ok::DialogOk::ok(message);
+    }
+
     if let Some(s) = workspace_to_edit {
         println!("Opening ARCadia from workspaces.rs...");
         crate::arcadia::show_arcadia(Some(s))
@@ -1,8 +1,12 @@
 pub mod callback;
 pub mod event;
 pub mod file;
+#[cfg(target_os = "switch")]
 pub mod utils;
+#[cfg(target_os = "switch")]
 pub mod lua;
+pub mod patch;
+pub mod workspace;
 
 pub use callback::*;
 
@@ -20,7 +24,7 @@ pub struct ApiVersion {
 /// Do your changes only add new APIs in a backwards compatible way: Minor bump
 ///
 /// Are your changes only internal? No version bump
-static API_VERSION: ApiVersion = ApiVersion { major: 1, minor: 9 };
+static API_VERSION: ApiVersion = ApiVersion { major: 1, minor: 14 };
 
 #[no_mangle]
 pub extern "C" fn arcrop_api_version() -> &'static ApiVersion {
@@ -29,6 +33,7 @@ pub extern "C" fn arcrop_api_version() -> &'static ApiVersion {
     &API_VERSION
 }
 
+#[cfg(target_os = "switch")]
 #[no_mangle]
 pub extern "C" fn arcrop_require_api_version(major: u32, minor: u32) {
     if major > API_VERSION.major || (major == API_VERSION.major && minor > API_VERSION.minor) {
@@ -38,12 +43,14 @@ pub extern "C" fn arcrop_require_api_version(major: u32, minor: u32) {
     }
 }
 
+#[cfg(target_os = "switch")]
 fn show_arcrop_update_prompt() -> ! {
     skyline::error::show_error(69, "ARCropolis API error, press Details.\0", "Your ARCropolis version is older than one of your plugins supports, an update is required");
 
     unsafe { skyline::nn::oe::ExitApplication() }
 }
 
+#[cfg(target_os = "switch")]
 fn show_plugin_update_prompt() -> ! {
     skyline::error::show_error(69, "ARCropolis API error, press Details.\0", "Your ARCropolis version is too new for one of your plugins, it must be updated to support this API version");
 
@@ -17,8 +17,8 @@ pub extern "C" fn arcrop_load_file(hash: Hash40, out_buffer: *mut u8, buf_length
 
     let buffer = unsafe { std::slice::from_raw_parts_mut(out_buffer, buf_length) };
 
-    // This function is intended to only be called by an arc api, which means that we have already write locked the thread and cannot read lock it
-    if let Some(size) = unsafe { crate::GLOBAL_FILESYSTEM.get_mut().unwrap().load_into(hash, buffer) } {
+    // Plugins call this from their callbacks, while the filesystem is already locked by this thread
+    if let Some(size) = crate::fs::read_filesystem(|filesystem| filesystem.load_into(hash, buffer)) {
         *out_size = size;
         debug!("arcrop_load_file -> Successfully loaded file. Bytes read: {:#x}", size);
         true
@@ -102,8 +102,76 @@ pub extern "C" fn arcrop_is_mod_enabled(hash: Hash40) -> bool {
         let workspace_name: String = storage.get_field("workspace").unwrap_or_else(|_| "Default".to_string());
         let workspace_list: HashMap<String, String> = storage.get_field_json("workspace_list").unwrap_or_default();
         let preset_name = &workspace_list[&workspace_name];
-        storage.get_field_json(preset_name).unwrap_or_default()
+        let enabled_ids: HashSet<String> = storage.get_field_json(preset_name).unwrap_or_default();
+
+        // Presets store mod identifiers, but plugins still refer to mods through the hash of their folder
+        crate::fs::mod_paths()
+            .iter()
+            .filter(|(id, _)| enabled_ids.contains(*id))
+            .filter_map(|(_, path)| path.to_str().map(Hash40::from))
+            .collect()
     };
 
     preset.contains(&hash)
 }
+
+/// Rescan the enabled mods and pick up the files that can change without rebooting.
+/// The amount of reloaded files and of files that need a reboot are written to the out parameters.
+#[no_mangle]
+pub extern "C" fn arcrop_reload_mods(out_reloaded: &mut usize, out_skipped: &mut usize) -> bool {
+    debug!("arcrop_reload_mods -> Function called");
+
+    match crate::fs::write_filesystem().reload() {
+        Some(report) => {
+            for (hash, reason) in report.skipped.iter() {
+                warn!("arcrop_reload_mods -> '{}' ({:#x}) needs a reboot: {:?}", hashes::find(*hash), hash.0, reason);
+            }
+
+            *out_reloaded = report.reloaded.len();
+            *out_skipped = report.skipped.len();
+            true
+        },
+        None => {
+            *out_reloaded = 0;
+            *out_skipped = 0;
+            false
+        },
+    }
+}
+
+/// Write the memory the enabled mods take on top of the vanilla files to the buffer, as null-terminated JSON.
+///
+/// `out_size` receives the size the JSON needs including the terminator, so a buffer that is too small can be resized and the call retried.
+#[no_mangle]
+pub extern "C" fn arcrop_get_memory_budget(out_buffer: *mut u8, buf_length: usize, out_size: &mut usize) -> bool {
+    debug!("arcrop_get_memory_budget -> Function called");
+
+    let report = match crate::fs::read_filesystem(|filesystem| filesystem.memory_budget()) {
+        Some(report) => report,
+        None => {
+            *out_size = 0;
+            return false;
+        },
+    };
+
+    let json = match serde_json::to_string(&report) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("arcrop_get_memory_budget -> Failed to serialize the report. Reason: {}", e);
+            *out_size = 0;
+            return false;
+        },
+    };
+
+    *out_size = json.len() + 1;
+
+    if buf_length < *out_size {
+        return false;
+    }
+
+    let buffer = unsafe { std::slice::from_raw_parts_mut(out_buffer, buf_length) };
+    buffer[..json.len()].copy_from_slice(json.as_bytes());
+    buffer[json.len()] = 0;
+
+    true
+}
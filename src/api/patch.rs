@@ -0,0 +1,213 @@
+use std::{
+    ffi::{c_char, CStr, CString},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use smash_arc::Hash40;
+
+use crate::{
+    fs::{
+        patch_format::{self, PatchFormat},
+        patch_trace, ApiLoader, ApiLoaderError,
+    },
+    PathExtension,
+};
+
+/// Build a patched file from the file its patches apply to, which is either vanilla or replaced by a mod, and the paths of the patches in load order.
+/// The patched file is written to `out_buffer` and its size to `out_size`.
+pub type PatchFormatCallbackFn = extern "C" fn(
+    hash: u64,
+    base: *const u8,
+    base_size: usize,
+    patch_paths: *const *const c_char,
+    patch_count: usize,
+    out_buffer: *mut u8,
+    buf_length: usize,
+    out_size: &mut usize,
+) -> bool;
+
+/// A patch format registered by a plugin
+struct PluginPatchFormat {
+    name: String,
+    /// Leaked when the format is registered, formats live for the rest of the session
+    suffixes: Vec<&'static str>,
+    target_extension: String,
+    max_size: usize,
+    callback: PatchFormatCallbackFn,
+}
+
+impl PatchFormat for PluginPatchFormat {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        &self.suffixes
+    }
+
+    /// The callback could depend on anything, like the plugin's own settings
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        Some(local.with_extension(&self.target_extension))
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        let hash = local.smash_hash()?;
+        let base = ApiLoader::handle_load_base_file(local)?;
+
+        let patch_paths: Vec<CString> = patches
+            .iter()
+            .filter_map(|path| CString::new(path.to_string_lossy().into_owned()).ok())
+            .collect();
+        let patch_ptrs: Vec<*const c_char> = patch_paths.iter().map(|path| path.as_ptr()).collect();
+
+        let mut buffer = vec![0; self.max_size];
+        let mut out_size = 0;
+
+        if !(self.callback)(
+            hash.0,
+            base.as_ptr(),
+            base.len(),
+            patch_ptrs.as_ptr(),
+            patch_ptrs.len(),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut out_size,
+        ) {
+            return Err(ApiLoaderError::Other(format!("Patch format {} did not patch the file!", self.name)));
+        }
+
+        buffer.truncate(out_size.min(self.max_size));
+
+        Ok(buffer)
+    }
+
+    fn estimate_size(&self, _base_size: usize, _patches: &[PathBuf]) -> Option<usize> {
+        Some(self.max_size)
+    }
+}
+
+/// Register a patch format, so that ARCropolis collects the files ending with one of the suffixes in mods and merges them with the callback.
+///
+/// Patches target the file with the same path and `target_extension` as their extension. The format has to be registered before the mods are discovered,
+/// in the plugin's main function, or it is only used after the mods are reloaded.
+#[no_mangle]
+pub unsafe extern "C" fn arcrop_register_patch_format(
+    name: *const c_char,
+    suffixes: *const *const c_char,
+    suffix_count: usize,
+    target_extension: *const c_char,
+    max_size: usize,
+    callback: PatchFormatCallbackFn,
+) -> bool {
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name.to_string(),
+        Err(_) => {
+            error!("arcrop_register_patch_format -> The name is not valid UTF-8");
+            return false;
+        },
+    };
+
+    let target_extension = match CStr::from_ptr(target_extension).to_str() {
+        Ok(extension) => extension.to_string(),
+        Err(_) => {
+            error!("arcrop_register_patch_format -> The target extension of {} is not valid UTF-8", name);
+            return false;
+        },
+    };
+
+    let mut format_suffixes = Vec::with_capacity(suffix_count);
+
+    for suffix in std::slice::from_raw_parts(suffixes, suffix_count) {
+        match CStr::from_ptr(*suffix).to_str() {
+            Ok(suffix) => format_suffixes.push(&*Box::leak(suffix.to_string().into_boxed_str())),
+            Err(_) => {
+                error!("arcrop_register_patch_format -> A suffix of {} is not valid UTF-8", name);
+                return false;
+            },
+        }
+    }
+
+    debug!("arcrop_register_patch_format -> Registering {} for {:?}", name, format_suffixes);
+
+    let format = PluginPatchFormat {
+        name,
+        suffixes: format_suffixes,
+        target_extension,
+        max_size,
+        callback,
+    };
+
+    match patch_format::register(Arc::new(format)) {
+        Ok(_) => true,
+        Err(e) => {
+            error!("arcrop_register_patch_format -> {}", e);
+            false
+        },
+    }
+}
+
+/// Write the trace of the last time the file was built from its patches to the buffer, as null-terminated JSON.
+///
+/// `out_size` receives the size the JSON needs including the terminator, so a buffer that is too small can be resized and the call retried.
+#[no_mangle]
+pub extern "C" fn arcrop_get_patch_trace(hash: Hash40, out_buffer: *mut u8, buf_length: usize, out_size: &mut usize) -> bool {
+    debug!("arcrop_get_patch_trace -> Hash received: {:#x}", hash.0);
+
+    let trace = match patch_trace::get(hash) {
+        Some(trace) => trace,
+        None => {
+            *out_size = 0;
+            return false;
+        },
+    };
+
+    let json = match serde_json::to_string(&trace) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("arcrop_get_patch_trace -> Failed to serialize the trace. Reason: {}", e);
+            *out_size = 0;
+            return false;
+        },
+    };
+
+    *out_size = json.len() + 1;
+
+    if buf_length < *out_size {
+        return false;
+    }
+
+    let buffer = unsafe { std::slice::from_raw_parts_mut(out_buffer, buf_length) };
+    buffer[..json.len()].copy_from_slice(json.as_bytes());
+    buffer[json.len()] = 0;
+
+    true
+}
+
+/// Write the trace of every patched file to a JSON file. A null path writes it to sd:/ultimate/arcropolis/logs/patch_trace.json.
+#[no_mangle]
+pub unsafe extern "C" fn arcrop_dump_patch_traces(path: *const c_char) -> bool {
+    let path = if path.is_null() {
+        patch_trace::dump_path()
+    } else {
+        match CStr::from_ptr(path).to_str() {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                error!("arcrop_dump_patch_traces -> The path is not valid UTF-8");
+                return false;
+            },
+        }
+    };
+
+    match patch_trace::dump(&path) {
+        Ok(_) => true,
+        Err(e) => {
+            error!("arcrop_dump_patch_traces -> Failed to write {}. Reason: {}", path.display(), e);
+            false
+        },
+    }
+}
@@ -19,5 +19,5 @@ pub extern "C" fn arcrop_show_main_menu() {
     debug!("arcrop_show_main_menu -> Function called");
 
     #[cfg(feature = "ui")]
-    menus::show_main_menu();
+    crate::handle_main_menu_action(menus::show_main_menu());
 }
@@ -0,0 +1,85 @@
+use std::{
+    ffi::{c_char, CStr},
+    path::Path,
+};
+
+/// Get a path from a C string, falling back to the default export location of the workspace if it is null
+unsafe fn path_or_default(path: *const c_char, name: &str) -> Option<std::path::PathBuf> {
+    if path.is_null() {
+        Some(config::workspaces::export_path(name))
+    } else {
+        CStr::from_ptr(path).to_str().ok().map(std::path::PathBuf::from)
+    }
+}
+
+/// Export a workspace to a file. A null path exports it to sd:/ultimate/arcropolis/workspaces.
+#[no_mangle]
+pub unsafe extern "C" fn arcrop_export_workspace(name: *const c_char, path: *const c_char) -> bool {
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name,
+        Err(_) => {
+            error!("arcrop_export_workspace -> The workspace name is not valid UTF-8");
+            return false;
+        },
+    };
+
+    let path = match path_or_default(path, name) {
+        Some(path) => path,
+        None => {
+            error!("arcrop_export_workspace -> The path is not valid UTF-8");
+            return false;
+        },
+    };
+
+    debug!("arcrop_export_workspace -> Exporting workspace {} to {}", name, path.display());
+
+    match config::workspaces::export_workspace(name, &path) {
+        Ok(_) => true,
+        Err(e) => {
+            error!("arcrop_export_workspace -> Failed to export workspace {}. Reason: {}", name, e);
+            false
+        },
+    }
+}
+
+/// Import a workspace file as a new workspace. A null name uses the name the workspace was exported with.
+#[no_mangle]
+pub unsafe extern "C" fn arcrop_import_workspace(path: *const c_char, name: *const c_char) -> bool {
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => Path::new(path),
+        Err(_) => {
+            error!("arcrop_import_workspace -> The path is not valid UTF-8");
+            return false;
+        },
+    };
+
+    let name = if name.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(name).to_str() {
+            Ok(name) => Some(name),
+            Err(_) => {
+                error!("arcrop_import_workspace -> The workspace name is not valid UTF-8");
+                return false;
+            },
+        }
+    };
+
+    debug!("arcrop_import_workspace -> Importing workspace from {}", path.display());
+
+    match config::workspaces::import_workspace(path, name) {
+        Ok(import) => {
+            for missing in import.missing_mods.iter() {
+                warn!(
+                    "arcrop_import_workspace -> Mod '{}' ({}) of workspace {} could not be found",
+                    missing.folder_name, missing.id, import.name
+                );
+            }
+            true
+        },
+        Err(e) => {
+            error!("arcrop_import_workspace -> Failed to import {}. Reason: {}", path.display(), e);
+            false
+        },
+    }
+}
@@ -0,0 +1,111 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use arcropolis::check;
+
+fn print_usage() {
+    eprintln!("Usage: arcrop-check xmsbt <xmsbt files in load order...> [--json]");
+    eprintln!("       arcrop-check motdiff <source motion_list.bin> <modified motion_list.bin> [--out <file.motdiff>]");
+    eprintln!("       arcrop-check bsdiff <base file> <modified file> --out <file.bsdiff>");
+    eprintln!("       arcrop-check delta-header <base file> <patch.xdelta> --out <file.xdelta>");
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+
+    let command = match args.next() {
+        Some(command) => command,
+        None => {
+            print_usage();
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let mut paths = Vec::new();
+    let mut json = false;
+    let mut out = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--out" => out = args.next().map(PathBuf::from),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        print_usage();
+        return ExitCode::FAILURE;
+    }
+
+    match command.as_str() {
+        "xmsbt" => {
+            let report = check::xmsbt(&paths);
+
+            if json {
+                match serde_json::to_string_pretty(&report) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => {
+                        eprintln!("Failed to serialize the report. {:?}", e);
+                        return ExitCode::FAILURE;
+                    },
+                }
+            } else {
+                print!("{}", report);
+            }
+
+            if report.has_errors() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        },
+        "motdiff" if paths.len() == 2 => {
+            let diff = match check::motdiff(&paths[0], &paths[1]) {
+                Ok(diff) => diff,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            match out {
+                Some(out) => {
+                    if let Err(e) = std::fs::write(&out, diff) {
+                        eprintln!("Failed to write '{}'. {:?}", out.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => print!("{}", diff),
+            }
+
+            ExitCode::SUCCESS
+        },
+        "bsdiff" | "delta-header" if paths.len() == 2 => {
+            let out = match out {
+                Some(out) => out,
+                None => {
+                    print_usage();
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            let patch = if command == "bsdiff" {
+                check::bsdiff(&paths[0], &paths[1])
+            } else {
+                check::delta_header(&paths[0], &paths[1])
+            };
+
+            match patch.and_then(|patch| std::fs::write(&out, patch)) {
+                Ok(_) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Failed to make '{}'. {:?}", out.display(), e);
+                    ExitCode::FAILURE
+                },
+            }
+        },
+        _ => {
+            print_usage();
+            ExitCode::FAILURE
+        },
+    }
+}
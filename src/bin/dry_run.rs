@@ -0,0 +1,90 @@
+use std::{path::PathBuf, process::ExitCode, str::FromStr};
+
+use arcropolis::dry_run::{self, DryRunOptions};
+use smash_arc::Region;
+
+fn print_usage() {
+    eprintln!("Usage: arcrop-dry-run <mods directory> <data.arc> [--preset <preset file>] [--load-order <load order file>] [--variants <variants file>] [--region <region>] [--buffer-size <bytes>] [--json]");
+}
+
+fn parse_size(arg: &str) -> Option<usize> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+
+    let mut positional = Vec::new();
+    let mut preset_path = None;
+    let mut load_order_path = None;
+    let mut variants_path = None;
+    let mut region = Region::UsEnglish;
+    let mut buffer_size = None;
+    let mut json = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preset" => preset_path = args.next().map(PathBuf::from),
+            "--load-order" => load_order_path = args.next().map(PathBuf::from),
+            "--variants" => variants_path = args.next().map(PathBuf::from),
+            "--region" => match args.next().as_deref().map(Region::from_str) {
+                Some(Ok(parsed)) => region = parsed,
+                _ => {
+                    eprintln!("Expected a region such as 'us_en' after --region.");
+                    return ExitCode::FAILURE;
+                },
+            },
+            "--buffer-size" => match args.next().as_deref().map(parse_size) {
+                Some(Some(parsed)) => buffer_size = Some(parsed),
+                _ => {
+                    eprintln!("Expected a size in bytes such as '0x1000000' after --buffer-size.");
+                    return ExitCode::FAILURE;
+                },
+            },
+            "--json" => json = true,
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let (mods_path, arc_path) = match positional.as_slice() {
+        [mods_path, arc_path] => (mods_path.clone(), arc_path.clone()),
+        _ => {
+            print_usage();
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let options = DryRunOptions {
+        mods_path,
+        preset_path,
+        arc_path,
+        load_order_path,
+        variants_path,
+        region,
+        buffer_size,
+    };
+
+    match dry_run::run(&options) {
+        Ok(report) if json => match serde_json::to_string_pretty(&report) {
+            Ok(json) => {
+                println!("{}", json);
+                ExitCode::SUCCESS
+            },
+            Err(e) => {
+                eprintln!("Failed to serialize the report. {:?}", e);
+                ExitCode::FAILURE
+            },
+        },
+        Ok(report) => {
+            print!("{}", report);
+            ExitCode::SUCCESS
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,40 @@
+//! Validates and makes patch files on a PC the same way ARCropolis reads them on console, so mods can check them before release.
+
+use std::path::{Path, PathBuf};
+
+use hash40::diff::Diff;
+use thiserror::Error;
+
+pub use crate::fs::loaders::xmsbt::{DuplicateLabel, Severity, XmsbtCheckReport, XmsbtDiagnostic, XmsbtFileReport};
+
+/// Check XMSBT files that patch the same MSBT, in the order they would be applied
+pub fn xmsbt(paths: &[PathBuf]) -> XmsbtCheckReport {
+    crate::fs::loaders::xmsbt::check_files(paths)
+}
+
+#[derive(Debug, Error)]
+pub enum MotdiffError {
+    #[error("Unable to read '{0}'. {1}")]
+    Read(PathBuf, std::io::Error),
+
+    #[error("Unable to write the motion list patch. {0}")]
+    Serialize(#[from] serde_yaml::Error),
+}
+
+/// Make the `.motdiff` patch that turns one `motion_list.bin` into another, so that mods only ship the fields they change
+pub fn motdiff(source: &Path, modified: &Path) -> Result<String, MotdiffError> {
+    let source_list = motion_lib::open(source).map_err(|err| MotdiffError::Read(source.to_path_buf(), err))?;
+    let modified_list = motion_lib::open(modified).map_err(|err| MotdiffError::Read(modified.to_path_buf(), err))?;
+
+    Ok(serde_yaml::to_string(&source_list.diff(&modified_list))?)
+}
+
+/// Make a `.bsdiff` patch that turns the base file into the modified one. The patch records the base, so it is only applied to that file.
+pub fn bsdiff(base: &Path, modified: &Path) -> std::io::Result<Vec<u8>> {
+    crate::fs::loaders::delta::make_bsdiff(&std::fs::read(base)?, &std::fs::read(modified)?)
+}
+
+/// Record the base file in a delta patch made by another tool, like a `.xdelta` made with `xdelta3 -S none`
+pub fn delta_header(base: &Path, patch: &Path) -> std::io::Result<Vec<u8>> {
+    Ok(crate::fs::loaders::delta::add_header(&std::fs::read(base)?, &std::fs::read(patch)?))
+}
@@ -0,0 +1,222 @@
+//! Runs mod discovery and filesystem construction against a data.arc on a PC, and reports what ARCropolis would do on console.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use orbits::{ConflictHandler, ConflictKind};
+use serde::Serialize;
+use smash_arc::{Hash40, Region};
+use thiserror::Error;
+
+use crate::{
+    fs::{
+        budget::BudgetReport, check_mod_constraints, conflicts::ConflictReport, discover_mods, enabled_roots, mod_id, with_variants,
+        CachedFilesystem, ModOperations,
+    },
+    get_path_from_hash, replacement,
+    resource::{self, HostBackend, ResourceBackend},
+    PathExtension,
+};
+
+#[derive(Debug, Error)]
+pub enum DryRunError {
+    #[error("failed to open the data.arc: {0}")]
+    Arc(std::io::Error),
+    #[error("failed to read the preset: {0}")]
+    Preset(#[from] std::io::Error),
+    #[error("failed to parse the preset: {0}")]
+    PresetFormat(#[from] serde_json::Error),
+    #[error("a resource backend was already installed")]
+    BackendInstalled,
+}
+
+pub struct DryRunOptions {
+    pub mods_path: PathBuf,
+    /// A workspace preset, as found in the ARCropolis config directory. Every mod is enabled if there is none.
+    pub preset_path: Option<PathBuf>,
+    pub arc_path: PathBuf,
+    /// The load order of the workspace, mods in directory order are used if there is none.
+    pub load_order_path: Option<PathBuf>,
+    /// The variants selected in the workspace, the default ones are used if there is none.
+    pub variants_path: Option<PathBuf>,
+    pub region: Region,
+    /// Size of the resource service buffer to check the memory budget against, it is only known in game
+    pub buffer_size: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplacedFile {
+    pub path: PathBuf,
+    pub source: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResizedFile {
+    pub path: PathBuf,
+    pub vanilla_size: u32,
+    pub new_size: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DryRunReport {
+    pub replaced_files: Vec<ReplacedFile>,
+    pub added_files: Vec<PathBuf>,
+    pub unshared_files: Vec<PathBuf>,
+    pub patch_files: BTreeMap<PathBuf, Vec<PathBuf>>,
+    pub resized_files: Vec<ResizedFile>,
+    pub memory_budget: BudgetReport,
+    pub conflicts: ConflictReport,
+}
+
+pub fn run(options: &DryRunOptions) -> Result<DryRunReport, DryRunError> {
+    let backend: &'static HostBackend = Box::leak(Box::new(HostBackend::open(&options.arc_path).map_err(DryRunError::Arc)?));
+    resource::install_backend(backend).map_err(|_| DryRunError::BackendInstalled)?;
+    replacement::lookup::initialize(Some(resource::arc()));
+
+    *config::REGION.write().unwrap() = options.region;
+
+    let preset: Option<HashSet<String>> = match options.preset_path.as_ref() {
+        Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?)?),
+        None => None,
+    };
+
+    let load_order: Vec<String> = match options.load_order_path.as_ref() {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+
+    let variants: HashMap<String, Vec<String>> = match options.variants_path.as_ref() {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => HashMap::new(),
+    };
+
+    let filter = |path: &Path| match preset.as_ref() {
+        Some(preset) => mod_id(path).map(|id| preset.contains(&id)).unwrap_or(false),
+        None => path.file_name().and_then(|name| name.to_str()).map(|name| !name.starts_with('.')).unwrap_or(false),
+    };
+
+    let mut roots = enabled_roots(&options.mods_path, filter, &load_order);
+
+    let mut report = DryRunReport::default();
+
+    report.conflicts.refused_mods = check_mod_constraints(&roots);
+    roots.retain(|root| !report.conflicts.refused_mods.iter().any(|refused| refused.root == *root));
+
+    let roots = with_variants(roots, &variants);
+
+    let (launchpad, conflicts) = discover_mods(&roots, ConflictHandler::NoRoot);
+
+    // Run a second pass that keeps the first root to have every conflicting file, like on console
+    if !conflicts.is_empty() {
+        report
+            .conflicts
+            .extend(conflicts.into_iter().filter(|conflict| matches!(conflict, ConflictKind::RootConflict(..))));
+
+        let (_, conflicts) = discover_mods(&roots, ConflictHandler::First);
+        report
+            .conflicts
+            .extend(conflicts.into_iter().filter(|conflict| matches!(conflict, ConflictKind::StandardConflict { .. })));
+    }
+
+    let mut filesystem = CachedFilesystem::make_from_promise(launchpad);
+
+    let ModOperations { added_files, unshared_files } = filesystem.plan_mod_operations();
+    // The added files are in the tables once the mods are processed, they are not replacing or resizing anything though
+    let added_hashes: HashSet<Hash40> = added_files.iter().filter_map(|local| local.smash_hash().ok()).collect();
+
+    filesystem.process_mods();
+    filesystem.reshare_files();
+    filesystem.patch_files();
+
+    report.memory_budget = filesystem.memory_budget(options.buffer_size);
+
+    let patch_targets = filesystem.patch_targets();
+
+    for (hash, local) in filesystem.hash_lookup().iter() {
+        if backend.contains_file(*hash) && !added_hashes.contains(hash) && !patch_targets.contains_key(hash) {
+            if let Some(source) = filesystem.hash(*hash) {
+                report.replaced_files.push(ReplacedFile { path: local.clone(), source });
+            }
+        }
+    }
+
+    report.added_files = added_files;
+    report.unshared_files = unshared_files.into_iter().map(get_path_from_hash).collect();
+    report.patch_files = patch_targets.into_iter().map(|(hash, patches)| (get_path_from_hash(hash), patches)).collect();
+    report.resized_files = backend
+        .patched_sizes()
+        .into_iter()
+        .filter(|(hash, _)| !added_hashes.contains(hash))
+        .map(|(hash, (vanilla_size, new_size))| ResizedFile {
+            path: get_path_from_hash(hash),
+            vanilla_size,
+            new_size,
+        })
+        .collect();
+
+    report.replaced_files.sort_by(|a, b| a.path.cmp(&b.path));
+    report.added_files.sort();
+    report.unshared_files.sort();
+    report.resized_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(report)
+}
+
+impl fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Replaced files ({}):", self.replaced_files.len())?;
+        for file in self.replaced_files.iter() {
+            writeln!(f, "    {} <- {}", file.path.display(), file.source.display())?;
+        }
+
+        writeln!(f, "Added files ({}):", self.added_files.len())?;
+        for path in self.added_files.iter() {
+            writeln!(f, "    {}", path.display())?;
+        }
+
+        writeln!(f, "Unshared files ({}):", self.unshared_files.len())?;
+        for path in self.unshared_files.iter() {
+            writeln!(f, "    {}", path.display())?;
+        }
+
+        writeln!(f, "Patch files ({} targets):", self.patch_files.len())?;
+        for (target, patches) in self.patch_files.iter() {
+            writeln!(f, "    {}", target.display())?;
+            for patch in patches {
+                writeln!(f, "        {}", patch.display())?;
+            }
+        }
+
+        writeln!(f, "Resized FileData entries ({}):", self.resized_files.len())?;
+        for file in self.resized_files.iter() {
+            writeln!(f, "    {} {:#x} -> {:#x}", file.path.display(), file.vanilla_size, file.new_size)?;
+        }
+
+        write!(f, "{}", self.memory_budget)?;
+
+        writeln!(f, "Conflicts ({}):", self.conflicts.conflicts.len())?;
+        for conflict in self.conflicts.conflicts.iter() {
+            match conflict.local.as_ref() {
+                Some(local) => writeln!(f, "    {} is provided by '{}'", local.display(), conflict.winning_root.display())?,
+                None => writeln!(f, "    Mod root rejected for a conflict with '{}'", conflict.winning_root.display())?,
+            }
+            for root in conflict.rejected_roots.iter() {
+                writeln!(f, "        rejected: '{}'", root.display())?;
+            }
+            writeln!(f, "        suggestion: {:?}", conflict.suggestion)?;
+        }
+
+        writeln!(f, "Refused mods ({}):", self.conflicts.refused_mods.len())?;
+        for refused in self.conflicts.refused_mods.iter() {
+            writeln!(f, "    '{}'", refused.root.display())?;
+            for reason in refused.reasons.iter() {
+                writeln!(f, "        it {}", reason)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,11 +1,14 @@
 use std::{
-    cell::UnsafeCell,
+    cell::{Cell, UnsafeCell},
     collections::{HashMap, HashSet},
     fmt,
     io::Write,
-    ops::Deref,
+    ops::{Deref, DerefMut},
     path::{Path, PathBuf},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLockWriteGuard,
+    },
 };
 
 use arc_config::{Config as ModConfig, ToExternal, ToSmashArc};
@@ -22,14 +25,31 @@ use crate::{
     resource, PathExtension,
 };
 
+pub mod budget;
+use budget::{BudgetEntry, BudgetReport};
+pub mod conflicts;
+pub mod direct;
 mod discover;
 mod utils;
 pub use discover::*;
 pub mod loaders;
 pub use loaders::*;
+pub mod patch_cache;
+pub mod patch_format;
+pub mod patch_trace;
+pub mod reload;
+pub use reload::*;
 
 static DEFAULT_CONFIG: &str = include_str!("../resources/override.json");
 static IS_INIT: AtomicBool = AtomicBool::new(false);
+
+/// Smallest amount of bytes reserved on top of the built size of a patched file
+const MIN_PATCH_SLACK: usize = 0x1000;
+
+/// Size reserved for a patched file, with room for it to grow when it is built again after its patches or the merge settings change
+fn with_patch_slack(size: usize) -> usize {
+    size.saturating_add((size / 8).max(MIN_PATCH_SLACK))
+}
 // pub type ApiLoader = StandardLoader; // temporary until an actual ApiLoader is implemented
 
 pub type ArcropolisOrbit = Orbit<ArcLoader, StandardLoader, ApiLoader>;
@@ -47,11 +67,17 @@ pub struct CachedFilesystem {
     config: ModConfig,
     hash_lookup: HashMap<Hash40, PathBuf>,
     hash_size_cache: HashMap<Hash40, usize>,
+    /// Files picked up by a reload, which the orbit doesn't know about
+    reloaded_paths: HashMap<Hash40, PathBuf>,
     incoming_load: Option<Hash40>,
     bytes_remaining: usize,
     current_nus3bank_id: u32,
     nus3banks: HashMap<Hash40, u32>,
     total_size: usize,
+    /// Files that are not in the data.arc, recorded before `process_mods` adds them
+    added_hashes: HashSet<Hash40>,
+    /// Memory taken by every file the mods replace or add, filled by `patch_files`
+    budget_entries: Vec<BudgetEntry>,
 }
 
 impl CachedFilesystem {
@@ -62,67 +88,40 @@ impl CachedFilesystem {
         api_tree: &mut Tree<ApiLoader>,
     ) -> HashSet<Hash40> {
         let mut hashes = HashSet::new();
-        let mut counts = [0u32; 7]; // config, prc, msbt, nus3audio, motionlist, bgm, other
-        let mut durations = [std::time::Duration::ZERO; 7];
         let mut config_paths: Vec<(PathBuf, usize)> = Vec::new();
 
         let collected = launchpad.collected_paths();
         let collected_sizes = launchpad.collected_sizes();
-        for (i, (root, path)) in collected.iter().enumerate() {
+
+        // Roots are discovered from highest to lowest priority, but patches are applied in the order they are inserted.
+        // Go through the roots in reverse so that the patches of the root winning conflicts are applied last, and win as well.
+        let mut root_ranks: HashMap<&Path, usize> = HashMap::new();
+        for (root, _) in collected.iter() {
+            let rank = root_ranks.len();
+            root_ranks.entry(root.as_path()).or_insert(rank);
+        }
+
+        // Only keep the regional variant of each patch that comes first in the fallback chain
+        let rejected_patches = utils::rejected_regional_patches(collected);
+
+        let mut order: Vec<usize> = (0..collected.len()).filter(|i| !rejected_patches.contains(i)).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(root_ranks[collected[i].0.as_path()]));
+
+        for i in order {
+            let (root, path) = &collected[i];
             let size = collected_sizes.get(i).copied().unwrap_or(0);
             if path.ends_with("config.json") {
-                let t = std::time::Instant::now();
                 config_paths.push((root.join(path), size));
-                counts[0] += 1;
-                durations[0] += t.elapsed();
-            // PRC patch files
-            } else if path.has_extension("prcx")
-                || path.has_extension("prcxml")
-                || path.has_extension("stdatx")
-                || path.has_extension("stdatxml")
-                || path.has_extension("stprmx")
-                || path.has_extension("stprmxml")
-            {
-                let t = std::time::Instant::now();
-                if let Some(hash) = utils::add_prc_patch(api_tree, root, path) {
-                    hashes.insert(hash);
-                }
-                counts[1] += 1;
-                durations[1] += t.elapsed();
-            // MSBT patch files
-            } else if path.has_extension("xmsbt") {
-                let t = std::time::Instant::now();
-                if let Some(hash) = utils::add_msbt_patch(api_tree, root, path) {
-                    hashes.insert(hash);
-                }
-                counts[2] += 1;
-                durations[2] += t.elapsed();
-            // NUS3AUDIO patch files
-            } else if path.has_extension("patch3audio") {
-                let t = std::time::Instant::now();
-                if let Some(hash) = utils::add_nus3audio_patch(api_tree, root, path) {
-                    hashes.insert(hash);
-                }
-                counts[3] += 1;
-                durations[3] += t.elapsed();
-            // Motion list patch files
-            } else if path.has_extension("motdiff") || path.ends_with("motion_list.yml") {
-                let t = std::time::Instant::now();
-                if let Some(hash) = utils::add_motionlist_patch(api_tree, root, path) {
+            } else if let Some(format) = patch_format::find(path) {
+                if let Some(hash) = utils::add_patch(api_tree, format.as_ref(), root, path) {
                     hashes.insert(hash);
                 }
-                counts[4] += 1;
-                durations[4] += t.elapsed();
-            // BGM property patch files
-            } else if path.file_name() == Path::new("bgm_property.bin").file_name() {
-                let t = std::time::Instant::now();
-                if let Some(hash) = utils::add_bgm_property_patch(api_tree, root, path) {
-                    hashes.insert(hash);
+
+                if let Some(companion) = format.companion().and_then(patch_format::position).and_then(patch_format::get) {
+                    if let Some(hash) = utils::add_patch(api_tree, companion.as_ref(), root, path) {
+                        hashes.insert(hash);
+                    }
                 }
-                counts[5] += 1;
-                durations[5] += t.elapsed();
-            } else {
-                counts[6] += 1;
             }
         }
 
@@ -223,7 +222,7 @@ impl CachedFilesystem {
 
     /// Use the file information that was generated during file discovery to fill out a GlobalFilesystem struct
     pub fn make_from_promise(launchpad: LaunchPad<StandardLoader>) -> CachedFilesystem {
-        let arc = resource::arc();
+        let backend = resource::backend();
 
         // Load the default config, which we will then join with the other configs
         let mut config = match ModConfig::from_json(DEFAULT_CONFIG) {
@@ -249,16 +248,31 @@ impl CachedFilesystem {
         let t = std::time::Instant::now();
         hashes::add_all(hashed_paths.values().filter_map(|path| path.to_str()));
 
-        // Add the hash files and set the new size to 10x the original files
+        // Add the hash files and reserve what their format estimates they need, or 10x the original files if it can't tell.
+        // Patched files that can be built ahead of time get their exact size later, in `size_patched_files`
         for hash in hashes {
-            if let Ok(data) = arc.get_file_data_from_hash(hash, config::region()) {
+            if let Ok(decomp_size) = backend.decompressed_size(hash, config::region()) {
+                // The patches apply on top of the file replacing the vanilla one, if there is one
+                let base_size = hashed_sizes.get(&hash).copied().unwrap_or(0).max(decomp_size);
+
+                let size = api_tree
+                    .loader
+                    .patches_for(hash)
+                    .and_then(|(index, patches)| patch_format::get(index)?.estimate_size(base_size, patches))
+                    .unwrap_or(decomp_size * 10);
+
                 hashed_paths.insert(hash, get_path_from_hash(hash));
-                hashed_sizes.insert(hash, (data.decomp_size as usize) * 10);
+                hashed_sizes.insert(hash, size);
             }
         }
 
         // Add all of the NUS3BANKs that our NUS3AUDIOs depend on to the API tree
         for dep in nus3audio_deps {
+            // Banks whose tones are patched are already loaded through their patch format
+            if dep.smash_hash().map(|hash| hashed_sizes.contains_key(&hash)).unwrap_or(false) {
+                continue;
+            }
+
             let hash = utils::add_file_to_api_tree(&mut api_tree, "api:/patch-nus3bank", &dep, ApiCallback::None);
             if let Some(hash) = hash {
                 hashed_paths.insert(hash, dep);
@@ -287,24 +301,27 @@ impl CachedFilesystem {
 
         // Construct a CachedFilesystem
         CachedFilesystem {
-            loader: launchpad.launch(ArcLoader(arc), api_tree),
+            loader: launchpad.launch(ArcLoader, api_tree),
             config,
             hash_lookup: hashed_paths,
             hash_size_cache: hashed_sizes,
+            reloaded_paths: HashMap::new(),
             incoming_load: None,
             bytes_remaining: 0,
             current_nus3bank_id: 7420,
             nus3banks: HashMap::new(),
             total_size: 0,
+            added_hashes: HashSet::new(),
+            budget_entries: Vec::new(),
         }
     }
 
     /// Patches a file in the LoadedArc
     fn patch_file(&self, hash: Hash40, size: usize) -> Option<usize> {
-        let arc = resource::arc_mut();
+        let backend = resource::backend();
         let region = config::region();
-        let decomp_size = match arc.get_file_data_from_hash(hash, region) {
-            Ok(data) => data.decomp_size as usize,
+        let decomp_size = match backend.decompressed_size(hash, region) {
+            Ok(size) => size,
             Err(_) => {
                 warn!(
                     "Failed to patch '{}' ({:#x}) filesize! It should be {:#x}.",
@@ -317,7 +334,7 @@ impl CachedFilesystem {
         };
 
         if size > decomp_size {
-            match arc.patch_filedata(hash, size as u32, region) {
+            match backend.patch_decompressed_size(hash, size as u32, region) {
                 Ok(old_size) => {
                     // info!(
                     //     "File '{}' ({:#x}) has a new decompressed filesize! {:#x} -> {:#x}",
@@ -326,7 +343,7 @@ impl CachedFilesystem {
                     //     old_size.red(),
                     //     size.green()
                     // );
-                    Some(old_size as usize)
+                    Some(old_size)
                 },
                 Err(_) => None,
             }
@@ -342,6 +359,10 @@ impl CachedFilesystem {
 
     // Get the "actual path" for a file hash
     pub fn hash(&self, hash: Hash40) -> Option<PathBuf> {
+        if let Some(path) = self.reloaded_paths.get(&hash) {
+            return Some(path.clone());
+        }
+
         self.local_hash(hash).and_then(|x| self.loader.query_actual_path(x))
     }
 
@@ -358,12 +379,22 @@ impl CachedFilesystem {
             return None;
         };
 
+        if let Some(full_path) = self.reloaded_paths.get(&hash) {
+            return match std::fs::read(full_path) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    error!("Failed to load data for {}. Reason: {:?}", full_path.display(), e);
+                    None
+                },
+            };
+        }
+
         match self.loader.load(path) {
             Ok(data) => Some(data),
             Err(Error::Virtual(ApiLoaderError::NoVirtFile)) => {
                 if let Ok(data) = self.loader.load_patch(path) {
                     Some(data)
-                } else if let Ok(data) = ArcLoader(resource::arc()).load_path(Path::new(""), path) {
+                } else if let Ok(data) = ArcLoader.load_path(Path::new(""), path) {
                     Some(data)
                 } else {
                     error!("Failed to load data for {} because all load paths failed.", path.display());
@@ -377,14 +408,43 @@ impl CachedFilesystem {
         }
     }
 
+    // Path on the SD card of a file that can be read straight into a buffer, if it is loaded as is
+    fn physical_path(&self, hash: Hash40) -> Option<PathBuf> {
+        if let Some(full_path) = self.reloaded_paths.get(&hash) {
+            return Some(full_path.clone());
+        }
+
+        self.hash_lookup.get(&hash).and_then(|local| direct::physical_path(&self.loader, local))
+    }
+
     // Load the file data from the Orbits filesystem into a pre-allocated buffer
     pub fn load_into(&self, hash: Hash40, mut buffer: &mut [u8]) -> Option<usize> {
+        // Files from the SD card skip the intermediate Vec, only built and vanilla files are loaded whole first
+        if let Some(full_path) = self.physical_path(hash) {
+            return match direct::read_into(&full_path, buffer) {
+                Ok(size) => Some(size),
+                Err(e) => {
+                    error!(
+                        "Failed to load '{}' ({:#x}) from {}. Reason: {}",
+                        hashes::find(hash),
+                        hash.0,
+                        full_path.display(),
+                        e
+                    );
+                    None
+                },
+            };
+        }
+
         if let Some(data) = self.load(hash) {
             if buffer.len() < data.len() {
+                // Patched files that grew past the size reserved at boot can't be loaded until the FileData is resized on the next boot
                 error!(
-                    "The size of the file data is larger than the size of the provided buffer when loading file '{}' ({:#x}).",
+                    "The file '{}' ({:#x}) is {:#x} bytes, more than the {:#x} bytes reserved for it. Restart the game to load it.",
                     hashes::find(hash),
-                    hash.0
+                    hash.0,
+                    data.len(),
+                    buffer.len()
                 );
                 None
             } else {
@@ -430,35 +490,85 @@ impl CachedFilesystem {
         }
     }
 
+    // Replace the estimated sizes of the patched files with the size they have once built, plus some slack.
+    // Built files are kept in the patch cache, so they are only built again when their patches change
+    pub fn size_patched_files(&mut self) {
+        let t = std::time::Instant::now();
+        let targets: Vec<Hash40> = self.patch_targets().into_keys().collect();
+        let mut sized = 0;
+
+        for hash in targets {
+            let local = match self.hash_lookup.get(&hash) {
+                Some(local) => local.clone(),
+                None => continue,
+            };
+
+            match self.loader.virt().loader.patched_size(hash, &local) {
+                Ok(Some(size)) => {
+                    self.hash_size_cache.insert(hash, with_patch_slack(size));
+                    sized += 1;
+                },
+                Ok(None) => {},
+                Err(e) => warn!(
+                    "Could not build '{}' to know its size, {:#x} bytes are reserved for it instead. Reason: {:?}",
+                    local.display(),
+                    self.hash_size_cache.get(&hash).copied().unwrap_or(0),
+                    e
+                ),
+            }
+        }
+
+        info!("Sized {} patched files in {:?}.", sized, t.elapsed());
+    }
+
     // Patch all files in the hash size cache
     pub fn patch_files(&mut self) {
+        let backend = resource::backend();
         let mut hash_cache = HashMap::new();
         let mut sum_size = 0;
+        let mut budget_entries = Vec::new();
         std::mem::swap(&mut hash_cache, &mut self.hash_size_cache);
         for (hash, size) in hash_cache.iter_mut() {
             sum_size += *size;
+
+            // Stream files are read from the SD card as they play, they don't go through the resource service
+            if let Some(local) = self.hash_lookup.get(hash).filter(|local| !local.is_stream()) {
+                let vanilla_size = if self.added_hashes.contains(hash) {
+                    0
+                } else {
+                    backend.decompressed_size(*hash, config::region()).unwrap_or(0)
+                };
+
+                budget_entries.push(BudgetEntry::new(*hash, local.clone(), vanilla_size, *size));
+            }
+
             if let Some(old_size) = self.patch_file(*hash, *size) {
                 *size = old_size;
             }
         }
         self.hash_size_cache = hash_cache;
         self.total_size = sum_size;
+        self.budget_entries = budget_entries;
+    }
+
+    /// Check the memory the mods take on top of the vanilla files against the buffer of the resource service, if it is known
+    pub fn memory_budget(&self, buffer_size: Option<usize>) -> BudgetReport {
+        BudgetReport::new(self.budget_entries.clone(), buffer_size)
     }
 
     // Reshares all hashes that still need to be shared, so that we don't get fake one-slot behavior
     pub fn reshare_files(&mut self) {
-        let arc = resource::arc();
-        let file_paths = arc.get_file_paths();
+        let backend = resource::backend();
 
         // Collect only the entries that need remapping
         let remaps: Vec<(Hash40, Hash40)> = self
             .hash_lookup
             .keys()
             .filter_map(|&hash| {
-                arc.get_file_info_from_hash(hash).ok().and_then(|info| {
-                    let canonical = file_paths[info.file_path_index].path.hash40();
-                    if canonical != hash { Some((hash, canonical)) } else { None }
-                })
+                backend
+                    .canonical_hash(hash)
+                    .ok()
+                    .and_then(|canonical| if canonical != hash { Some((hash, canonical)) } else { None })
             })
             .collect();
 
@@ -469,8 +579,50 @@ impl CachedFilesystem {
         }
     }
 
+    /// Figure out which files have to be added to the data.arc and which ones have to be unshared, without touching the tables.
+    pub fn plan_mod_operations(&self) -> ModOperations {
+        let backend = resource::backend();
+        let mut added_files = Vec::new();
+
+        self.loader.walk_patch(|node, ty| {
+            if node.get_local().is_stream() || !ty.is_file() {
+                return;
+            }
+
+            if let Ok(hash) = node.get_local().smash_hash() {
+                if !backend.contains_file(hash) {
+                    added_files.push(node.get_local().to_path_buf());
+                }
+            }
+        });
+
+        // Don't unshare any files in the unshare blacklist (nus3audio handled during filesystem finish)
+        let unshared_files = self
+            .hash_lookup
+            .keys()
+            .filter(|hash| !self.config.unshare_blacklist.contains(&hash.to_external()))
+            .copied()
+            .collect();
+
+        ModOperations { added_files, unshared_files }
+    }
+
     /// Goes through and performs the required file manipulation in order to load mods
     pub fn process_mods(&mut self) {
+        let ModOperations { added_files, unshared_files } = self.plan_mod_operations();
+
+        self.added_hashes = added_files.iter().filter_map(|local| local.smash_hash().ok()).collect();
+
+        // The table edits need the tables in memory, a backend without any stops at the plan
+        if !resource::initialized() {
+            info!(
+                "Skipping table edits for {} added file(s) and {} unshared file(s) as no data.arc tables are loaded.",
+                added_files.len(),
+                unshared_files.len()
+            );
+            return;
+        }
+
         let mut context = LoadedArc::make_addition_context();
         let mut search_context = LoadedSearchSection::make_context();
 
@@ -497,36 +649,15 @@ impl CachedFilesystem {
         }
 
         // Go through and add any files that were not found in the data.arc
-        self.loader.walk_patch(|node, ty| {
-            if node.get_local().is_stream() || !ty.is_file() {
-                return;
+        for local in added_files.iter() {
+            // The new dir infos might have brought some of them in already
+            if local.smash_hash().map(|hash| context.contains_file(hash)).unwrap_or(true) {
+                continue;
             }
 
-            let _hash = if let Ok(hash) = node.get_local().smash_hash() {
-                if context.contains_file(hash) {
-                    return;
-                }
-                hash
-            } else {
-                return;
-            };
-
-            replacement::addition::add_file(&mut context, node.get_local());
-            replacement::addition::add_searchable_file_recursive(&mut search_context, node.get_local());
-        });
-
-        // Don't unshare any files in the unshare blacklist (nus3audio handled during filesystem finish)
-        let files: Vec<Hash40> = self
-            .hash_lookup
-            .iter()
-            .filter_map(|(hash, _path)| {
-                if self.config.unshare_blacklist.contains(&hash.to_external()) {
-                    None
-                } else {
-                    Some(*hash)
-                }
-            })
-            .collect();
+            replacement::addition::add_file(&mut context, local);
+            replacement::addition::add_searchable_file_recursive(&mut search_context, local);
+        }
 
         // Acquire both lookup table locks once for the entire sharing/unsharing phase
         replacement::lookup::with_lookups(|unshare_lut, share_lut| {
@@ -550,7 +681,7 @@ impl CachedFilesystem {
             // Reshare any files that depend on files in file groups, as we need to get rid of those else we crash.
             replacement::unshare::reshare_file_groups(&mut context);
 
-            replacement::unshare::unshare_files(&mut context, hash_ignore, files.into_iter(), unshare_lut, share_lut);
+            replacement::unshare::unshare_files(&mut context, hash_ignore, unshared_files.into_iter(), unshare_lut, share_lut);
 
             // Add new shared files to added files
             for (hash, new_file_set) in self.config.share_to_added.iter() {
@@ -612,6 +743,62 @@ impl CachedFilesystem {
     pub fn get_sum_size(&self) -> usize {
         self.total_size
     }
+
+    /// Every hash the filesystem knows about, alongside the local path it will be loaded from
+    pub fn hash_lookup(&self) -> &HashMap<Hash40, PathBuf> {
+        &self.hash_lookup
+    }
+
+    /// Patch files registered in the API loader, grouped by the hash of the file they target
+    pub fn patch_targets(&self) -> HashMap<Hash40, Vec<PathBuf>> {
+        self.loader.virt().loader.patch_targets()
+    }
+}
+
+thread_local! {
+    /// Whether the current thread holds the write lock of the global filesystem
+    static HOLDS_WRITE_LOCK: Cell<bool> = Cell::new(false);
+}
+
+/// Write lock on the global filesystem. Code running while it is held, like patch formats building a file or plugin callbacks,
+/// reads the filesystem through [`read_filesystem`] instead of waiting on the lock.
+pub struct FilesystemWriteGuard(RwLockWriteGuard<'static, GlobalFilesystem>);
+
+impl Deref for FilesystemWriteGuard {
+    type Target = GlobalFilesystem;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for FilesystemWriteGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for FilesystemWriteGuard {
+    fn drop(&mut self) {
+        HOLDS_WRITE_LOCK.with(|held| held.set(false));
+    }
+}
+
+pub fn write_filesystem() -> FilesystemWriteGuard {
+    let guard = unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap() };
+    HOLDS_WRITE_LOCK.with(|held| held.set(true));
+
+    FilesystemWriteGuard(guard)
+}
+
+/// Read the global filesystem. If the current thread holds the write lock, the filesystem is read without locking it again,
+/// as no other thread can change it until the lock is released. Otherwise this waits for a reload to be done with it.
+pub fn read_filesystem<R, F: FnOnce(&GlobalFilesystem) -> R>(f: F) -> R {
+    if HOLDS_WRITE_LOCK.with(Cell::get) {
+        f(unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() })
+    } else {
+        f(&unsafe { crate::GLOBAL_FILESYSTEM.read() }.unwrap())
+    }
 }
 
 pub enum GlobalFilesystem {
@@ -620,6 +807,15 @@ pub enum GlobalFilesystem {
     Initialized(Box<CachedFilesystem>),
 }
 
+/// The file table edits `process_mods` has to perform for the current set of mods
+#[derive(Debug, Default)]
+pub struct ModOperations {
+    /// Local paths of the files that do not exist in the data.arc
+    pub added_files: Vec<PathBuf>,
+    /// Hashes of the files that need to be unshared from their original FileInfo
+    pub unshared_files: Vec<Hash40>,
+}
+
 struct ApiCallResult {
     hash: Hash40,
     path: PathBuf,
@@ -732,6 +928,13 @@ impl GlobalFilesystem {
         }
     }
 
+    pub fn size_patched_files(&mut self) {
+        match self {
+            Self::Initialized(fs) => fs.size_patched_files(),
+            _ => error!("Cannot size the patched files because the filesystem is not initialized!"),
+        }
+    }
+
     pub fn patch_files(&mut self) {
         match self {
             Self::Initialized(fs) => fs.patch_files(),
@@ -800,4 +1003,32 @@ impl GlobalFilesystem {
             _ => None,
         }
     }
+
+    pub fn memory_budget(&self) -> Option<BudgetReport> {
+        match self {
+            #[cfg(target_os = "switch")]
+            Self::Initialized(fs) => Some(fs.memory_budget(Some(resource::res_service().buffer_size))),
+            #[cfg(not(target_os = "switch"))]
+            Self::Initialized(fs) => Some(fs.memory_budget(None)),
+            _ => None,
+        }
+    }
+
+    pub fn reload(&mut self) -> Option<ReloadReport> {
+        #[cfg_attr(not(target_os = "switch"), allow(unused_mut))]
+        let mut report = match self {
+            Self::Initialized(fs) => fs.reload(),
+            _ => {
+                error!("Cannot reload the mods because the filesystem is not initialized!");
+                return None;
+            },
+        };
+
+        #[cfg(target_os = "switch")]
+        if resource::backend().is_game() {
+            reload::refresh_loaded_files(self, &mut report);
+        }
+
+        Some(report)
+    }
 }
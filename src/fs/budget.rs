@@ -0,0 +1,122 @@
+use std::{fmt, path::PathBuf};
+
+use serde::Serialize;
+use smash_arc::Hash40;
+
+/// How many files are listed as the ones taking the most memory
+const TOP_OFFENDERS: usize = 10;
+
+/// Memory a file of the mods takes compared to the vanilla file
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetEntry {
+    pub hash: u64,
+    pub path: PathBuf,
+    /// 0 for the files the mods add
+    pub vanilla_size: usize,
+    pub size: usize,
+    /// Bytes the file takes on top of the vanilla one
+    pub added_bytes: usize,
+}
+
+impl BudgetEntry {
+    pub fn new(hash: Hash40, path: PathBuf, vanilla_size: usize, size: usize) -> Self {
+        Self {
+            hash: hash.0,
+            path,
+            vanilla_size,
+            size,
+            added_bytes: size.saturating_sub(vanilla_size),
+        }
+    }
+}
+
+/// Memory the enabled mods take on top of the vanilla game, checked against the buffer of the resource service
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BudgetReport {
+    /// Size of the resource service buffer, not known outside of the game
+    pub buffer_size: Option<usize>,
+    pub added_bytes: usize,
+    pub added_files: usize,
+    pub resized_files: usize,
+    /// Files adding the most bytes, biggest first
+    pub top_offenders: Vec<BudgetEntry>,
+    /// Files that don't fit in the buffer on their own
+    pub oversized_files: Vec<BudgetEntry>,
+}
+
+impl BudgetReport {
+    pub fn new(mut entries: Vec<BudgetEntry>, buffer_size: Option<usize>) -> Self {
+        entries.retain(|entry| entry.added_bytes > 0);
+        entries.sort_by(|a, b| b.added_bytes.cmp(&a.added_bytes).then_with(|| a.path.cmp(&b.path)));
+
+        let oversized_files = match buffer_size {
+            Some(buffer_size) => entries.iter().filter(|entry| entry.size > buffer_size).cloned().collect(),
+            None => Vec::new(),
+        };
+
+        Self {
+            buffer_size,
+            added_bytes: entries.iter().map(|entry| entry.added_bytes).sum(),
+            added_files: entries.iter().filter(|entry| entry.vanilla_size == 0).count(),
+            resized_files: entries.iter().filter(|entry| entry.vanilla_size != 0).count(),
+            top_offenders: entries.into_iter().take(TOP_OFFENDERS).collect(),
+            oversized_files,
+        }
+    }
+
+    /// Whether the mods add more than the buffer can hold, or have files that don't fit in it
+    pub fn is_over_budget(&self) -> bool {
+        match self.buffer_size {
+            Some(buffer_size) => self.added_bytes > buffer_size || !self.oversized_files.is_empty(),
+            None => false,
+        }
+    }
+
+    /// Message shown at boot when the mods are over budget
+    pub fn warning(&self) -> String {
+        let mut message = format!(
+            "The enabled mods add {:.1} MB on top of the vanilla files, the game's resource buffer is {:.1} MB. This can crash the game when loading big stages or many fighters at once.\n\nFiles adding the most:\n",
+            megabytes(self.added_bytes),
+            megabytes(self.buffer_size.unwrap_or(0))
+        );
+
+        for entry in self.top_offenders.iter() {
+            message.push_str(&format!("{} (+{:.1} MB)\n", entry.path.display(), megabytes(entry.added_bytes)));
+        }
+
+        if !self.oversized_files.is_empty() {
+            message.push_str(&format!("\n{} files are bigger than the buffer on their own.\n", self.oversized_files.len()));
+        }
+
+        message.push_str("\nThis warning can be turned off in the ARCropolis configuration.");
+
+        message
+    }
+}
+
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+impl fmt::Display for BudgetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.buffer_size {
+            Some(buffer_size) => writeln!(f, "Memory budget: {:#x} bytes added, buffer size {:#x}", self.added_bytes, buffer_size)?,
+            None => writeln!(f, "Memory budget: {:#x} bytes added, buffer size unknown", self.added_bytes)?,
+        }
+
+        writeln!(f, "    {} added files, {} resized files", self.added_files, self.resized_files)?;
+
+        writeln!(f, "    Files adding the most ({}):", self.top_offenders.len())?;
+        for entry in self.top_offenders.iter() {
+            writeln!(f, "        {} {:#x} -> {:#x} (+{:#x})", entry.path.display(), entry.vanilla_size, entry.size, entry.added_bytes)?;
+        }
+
+        writeln!(f, "    Files bigger than the buffer ({}):", self.oversized_files.len())?;
+        for entry in self.oversized_files.iter() {
+            writeln!(f, "        {} {:#x}", entry.path.display(), entry.size)?;
+        }
+
+        Ok(())
+    }
+}
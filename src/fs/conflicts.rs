@@ -0,0 +1,173 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use orbits::ConflictKind;
+use serde::Serialize;
+
+use super::patch_format;
+use crate::PathExtension;
+
+/// Bump this whenever the layout of the report changes, so mod managers reading it can tell
+pub const CONFLICT_REPORT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ConflictType {
+    /// Two roots provide the same file
+    StandardConflict,
+    /// A whole root was rejected because one of its files is already provided
+    RootConflict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action")]
+pub enum Resolution {
+    /// The file has a patch format, so the mods could ship patches that get merged instead of full files
+    ConvertToPatch { patch_extension: String },
+    /// The file belongs to a costume slot, so one of the mods can be moved to another slot
+    Reslot,
+    /// Nothing can be merged, one of the mods has to go
+    DisableMod { root: PathBuf },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub kind: ConflictType,
+    /// The conflicting file, relative to the roots. Missing for root conflicts.
+    pub local: Option<PathBuf>,
+    /// Hash40 of the local path, formatted like in the logs
+    pub hash: Option<String>,
+    /// The root that ended up providing the file
+    pub winning_root: PathBuf,
+    pub rejected_roots: Vec<PathBuf>,
+    /// Whether the file could have been shipped as a patch and merged with the others
+    pub mergeable: bool,
+    pub suggestion: Resolution,
+}
+
+/// A constraint from a mod's info.toml that is not satisfied by the enabled mods
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason")]
+pub enum UnmetConstraint {
+    /// A mod listed in `requires` is not enabled, or was refused itself
+    MissingDependency { dependency: String },
+    /// A mod listed in `conflicts_with` is enabled
+    Incompatible { other: String },
+    /// `min_arcropolis_version` is newer than the running version
+    ArcropolisTooOld { required: String },
+}
+
+impl fmt::Display for UnmetConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingDependency { dependency } => write!(f, "requires '{}', which is not enabled", dependency),
+            Self::Incompatible { other } => write!(f, "is incompatible with '{}'", other),
+            Self::ArcropolisTooOld { required } => write!(f, "requires ARCropolis {} or newer", required),
+        }
+    }
+}
+
+/// A mod that was not loaded because of its info.toml constraints
+#[derive(Debug, Clone, Serialize)]
+pub struct RefusedMod {
+    pub id: String,
+    pub root: PathBuf,
+    pub reasons: Vec<UnmetConstraint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConflictReport {
+    pub version: u32,
+    pub conflicts: Vec<Conflict>,
+    pub refused_mods: Vec<RefusedMod>,
+}
+
+impl Default for ConflictReport {
+    fn default() -> Self {
+        Self {
+            version: CONFLICT_REPORT_VERSION,
+            conflicts: Vec::new(),
+            refused_mods: Vec::new(),
+        }
+    }
+}
+
+/// Costume slots show up as a `cXX` directory somewhere in the path
+fn is_costume_slot(local: &Path) -> bool {
+    local.components().any(|component| {
+        component
+            .as_os_str()
+            .to_str()
+            .map(|name| name.len() == 3 && name.starts_with('c') && name[1..].chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false)
+    })
+}
+
+impl ConflictReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty() && self.refused_mods.is_empty()
+    }
+
+    pub fn add(&mut self, conflict: ConflictKind) {
+        match conflict {
+            ConflictKind::StandardConflict {
+                error_root,
+                source_root,
+                local,
+            } => {
+                if let Some(existing) = self
+                    .conflicts
+                    .iter_mut()
+                    .find(|x| x.kind == ConflictType::StandardConflict && x.local.as_deref() == Some(local.as_path()))
+                {
+                    existing.rejected_roots.push(error_root);
+                    return;
+                }
+
+                let patch_extension = patch_format::patch_suffix_for(&local);
+
+                let mergeable = patch_extension.is_some();
+
+                let suggestion = if let Some(patch_extension) = patch_extension {
+                    Resolution::ConvertToPatch { patch_extension }
+                } else if is_costume_slot(&local) {
+                    Resolution::Reslot
+                } else {
+                    Resolution::DisableMod { root: error_root.clone() }
+                };
+
+                self.conflicts.push(Conflict {
+                    kind: ConflictType::StandardConflict,
+                    hash: local.smash_hash().ok().map(|hash| format!("{:#x}", hash.0)),
+                    local: Some(local),
+                    winning_root: source_root,
+                    rejected_roots: vec![error_root],
+                    mergeable,
+                    suggestion,
+                });
+            },
+            ConflictKind::RootConflict(root_path, kept) => self.conflicts.push(Conflict {
+                kind: ConflictType::RootConflict,
+                local: None,
+                hash: None,
+                winning_root: kept,
+                rejected_roots: vec![root_path.clone()],
+                mergeable: false,
+                suggestion: Resolution::DisableMod { root: root_path },
+            }),
+        }
+    }
+}
+
+impl Extend<ConflictKind> for ConflictReport {
+    fn extend<T: IntoIterator<Item = ConflictKind>>(&mut self, iter: T) {
+        for conflict in iter {
+            self.add(conflict);
+        }
+    }
+}
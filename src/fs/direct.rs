@@ -0,0 +1,56 @@
+//! Reads of mod files that go straight from the SD card into the caller's buffer.
+//!
+//! Only files that are loaded as they are on the SD card can be read this way. Files built by the API loader or read from the data.arc
+//! have to be loaded whole first.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use super::ArcropolisOrbit;
+
+/// Path on the SD card of a mod file that is loaded as is, without going through a callback, a patch or the data.arc
+pub fn physical_path(orbit: &ArcropolisOrbit, local: &Path) -> Option<PathBuf> {
+    if orbit.virt().loader.is_virtual(local) {
+        return None;
+    }
+
+    orbit.get_patch_entry_type(local).ok()?;
+    orbit.query_actual_path(local)
+}
+
+/// Read as much of the file as fits in the buffer, starting at the offset. Returns how many bytes were read, 0 past the end of the file.
+pub fn read_range(path: &Path, offset: usize, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset as u64))?;
+
+    let mut read = 0;
+
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..])? {
+            0 => break,
+            count => read += count,
+        }
+    }
+
+    Ok(read)
+}
+
+/// Read the whole file into the buffer, failing if it doesn't fit. Returns the size of the file.
+pub fn read_into(path: &Path, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len() as usize;
+
+    if size > buffer.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("the file has {:#x} bytes but the buffer only holds {:#x}", size, buffer.len()),
+        ));
+    }
+
+    file.read_exact(&mut buffer[..size])?;
+
+    Ok(size)
+}
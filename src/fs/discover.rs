@@ -1,14 +1,66 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     path::{Path, PathBuf},
+    sync::{Arc, LazyLock, RwLock},
 };
+#[cfg(target_os = "switch")]
+use std::collections::HashSet;
 
 use camino::Utf8Path;
-use orbits::{ConflictHandler, ConflictKind, FileLoader, LaunchPad, StandardLoader, Tree};
+use config::mods::ModManifest;
+use orbits::{ConflictHandler, ConflictKind, LaunchPad, StandardLoader};
+#[cfg(target_os = "switch")]
+use orbits::{FileLoader, Tree};
+use semver::Version;
+#[cfg(target_os = "switch")]
 use skyline::nn::{self, ro::*};
-use smash_arc::Hash40;
-use crate::{chainloader::*, utils};
 
+use super::{
+    conflicts::{RefusedMod, UnmetConstraint},
+    patch_format,
+};
+#[cfg(target_os = "switch")]
+use super::conflicts::ConflictReport;
+use crate::utils;
+#[cfg(target_os = "switch")]
+use crate::chainloader::*;
+
+/// Folder of every mod by its identifier, as found by the last discovery. Reading every info.toml is too slow to do on each lookup.
+static MOD_PATHS: LazyLock<RwLock<HashMap<String, PathBuf>>> =
+    LazyLock::new(|| RwLock::new(config::mods::list_mods_by(mod_id).into_iter().collect()));
+
+/// The info.toml of every root read since the last discovery or reload. Listing, sorting, checking and mounting the roots all need it.
+static MOD_MANIFESTS: LazyLock<RwLock<HashMap<PathBuf, Arc<ModManifest>>>> = LazyLock::new(Default::default);
+
+/// Read the identifiers and manifests of the mods in the mods directory again
+pub fn refresh_mod_paths() {
+    MOD_MANIFESTS.write().unwrap().clear();
+    *MOD_PATHS.write().unwrap() = config::mods::list_mods_by(mod_id).into_iter().collect();
+}
+
+/// Manifest of the mod at the root, only read from the SD card the first time it is needed since the last refresh
+pub fn mod_manifest(root: &Path) -> Arc<ModManifest> {
+    if let Some(manifest) = MOD_MANIFESTS.read().unwrap().get(root) {
+        return manifest.clone();
+    }
+
+    let manifest = Arc::new(config::mods::get_mod_manifest(root));
+    MOD_MANIFESTS.write().unwrap().insert(root.to_path_buf(), manifest.clone());
+
+    manifest
+}
+
+/// Identifier of the mod at the root, from its cached manifest
+pub fn mod_id(root: &Path) -> Option<String> {
+    mod_manifest(root).mod_id(root)
+}
+
+/// Folder of every mod by its identifier, as of the last discovery or reload
+pub fn mod_paths() -> std::sync::RwLockReadGuard<'static, HashMap<String, PathBuf>> {
+    MOD_PATHS.read().unwrap()
+}
+
+#[cfg(target_os = "switch")]
 pub fn perform_discovery() -> LaunchPad<StandardLoader> {
     let is_emulator = utils::env::is_emulator();
 
@@ -16,8 +68,6 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
         info!("Emulator usage detected in perform_discovery, reverting to old behavior.");
     }
 
-    let mods_path = utils::paths::mods();
-
     let legacy_discovery = config::legacy_discovery();
 
     let mut presets = config::presets::get_active_preset().unwrap();
@@ -25,31 +75,21 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
     // Emulators can't use presets, so don't run this logic
     if !is_emulator && !legacy_discovery {
         // Get the mod cache from last run
-        let mod_cache: HashSet<Hash40> = config::get_mod_cache().unwrap_or_default();
+        let mod_cache: HashSet<String> = config::get_mod_cache().unwrap_or_default();
 
         // Inspect the list of mods to see if some are new ones
-        let new_cache: HashSet<Hash40> = std::fs::read_dir(&mods_path)
-            .unwrap()
-            .filter_map(|path| {
-                let path = PathBuf::from(&mods_path).join(path.unwrap().path());
+        refresh_mod_paths();
+        let new_cache: HashSet<String> = mod_paths().keys().cloned().collect();
 
-                if path.is_file() {
-                    None
-                } else {
-                    Some(Hash40::from(path.to_str().unwrap()))
-                }
-            })
-            .collect();
-
-        let new_mods: HashSet<&Hash40> = new_cache
+        let new_mods: HashSet<&String> = new_cache
             .iter()
-            .filter(|cached_mod| !mod_cache.contains(cached_mod) && !presets.contains(cached_mod))
+            .filter(|cached_mod| !mod_cache.contains(*cached_mod) && !presets.contains(*cached_mod))
             .collect();
 
-        // We found hashes that weren't in the cache
+        // We found mods that weren't in the cache
         if !new_mods.is_empty() {
             // Add the new mods to the presets file
-            presets.extend(new_mods);
+            presets.extend(new_mods.into_iter().cloned());
             // Save it back
             config::presets::replace_active_preset(&presets).unwrap();
         }
@@ -61,92 +101,31 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
     #[cfg(feature = "ui")]
     crate::check_input_on_boot();
 
-    // If the user edited their mods again, we'll have to reload them here. This is obviously bad and inefficient but it wouldn't be ARCropolis if it wasn't.
-    // Consider loading the active presets in a static RwLock so everything can manipulate them without reloading
-    let presets = config::presets::get_active_preset().unwrap();
+    // The menu can change the active mods, so only look for them once it is closed
+    let (roots, refused_mods) = active_roots();
 
-    let filter = |path: &Path| {
-        // If we're not running on emulator
-        if !is_emulator && !legacy_discovery {
-            // If it's not in the presets, don't load
-            presets.contains(&Hash40::from(path.to_str().unwrap()))
-        } else {
-            // Legacy filter, load the mod except if it has a period at the start of the name
+    let mut report = ConflictReport::new();
 
-            Utf8Path::from_path(path)
-                .unwrap()
-                .file_name()
-                .map(|name| !name.starts_with('.'))
-                .unwrap_or(false)
-        }
-    };
-
-    let ignore = |path: &Path| {
-        let name = if let Some(name) = path.file_name().and_then(|x| x.to_str()) { name } else { return false };
-
-        let is_root = path.parent().map(|parent| parent.as_os_str().is_empty()).unwrap_or(true);
-
-        let is_dot = name.starts_with('.');
-
-        let is_out_of_region = if let Some(index) = name.find('+') {
-            let (_, end) = name.split_at(index + 1);
-            !end.starts_with(&config::region().to_string())
-        } else {
-            false
-        };
-
-        is_root || is_dot || is_out_of_region
-    };
+    report.refused_mods = refused_mods;
 
-    let collect = |x: &Path| {
-        match x.file_name() {
-            Some(name) if let Some(name) = name.to_str() => {
-                static RESERVED_NAMES: &[&str] = &[
-                    "config.json",
-                    "plugin.nro",
-                    "bgm_property.bin"
-                ];
-                static PATCH_EXTENSIONS: &[&str] = &[
-                    "prcx",
-                    "prcxml",
-                    "stdatx",
-                    "stdatxml",
-                    "stprmx",
-                    "stprmxml",
-
-                    "xmsbt",
-
-                    "patch3audio",
-
-                    "motdiff",
-
-                    "yml"
-                ];
-                RESERVED_NAMES.contains(&name) || {
-                    let is_out_of_region = if let Some(index) = name.find('+') {
-                        let (_, end) = name.split_at(index + 1);
-                        !end.starts_with(&config::region().to_string())
-                    } else {
-                        false
-                    };
-                    PATCH_EXTENSIONS.iter().any(|x| name.ends_with(x)) && !is_out_of_region
-                }
-            },
-            _ => false
+    for refused in report.refused_mods.iter() {
+        for reason in refused.reasons.iter() {
+            warn!("Mod '{}' was refused during discovery, it {}.", refused.root.display(), reason);
         }
-    };
-
-    let mut launchpad = LaunchPad::new(StandardLoader, ConflictHandler::NoRoot);
-
-    launchpad.collecting(collect);
-    launchpad.ignoring(ignore);
+    }
 
-    let conflicts = launchpad.discover_roots(&mods_path, 1, filter);
+    let (launchpad, conflicts) = discover_mods(&roots, ConflictHandler::NoRoot);
 
-    let has_conflicts = !conflicts.is_empty();
+    // Removed the prompt for checking conflicts, since we shouldn't have to run this twice to begin with and this needs fixing.
+    let file_conflicts = if !conflicts.is_empty() {
+        // Rejected roots don't tell which files are conflicting, so walk again while keeping the first file found to get all of them
+        discover_mods(&roots, ConflictHandler::First).1
+    } else {
+        Vec::new()
+    };
 
     for conflict in conflicts.into_iter() {
-        match conflict {
+        match &conflict {
             ConflictKind::StandardConflict {
                 error_root,
                 source_root,
@@ -154,7 +133,7 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
             } => {
                 warn!(
                     "File '{}' was rejected for file '{}' during discovery.",
-                    error_root.join(&local).display(),
+                    error_root.join(local).display(),
                     source_root.join(local).display()
                 )
             },
@@ -163,64 +142,52 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
                     "Mod root '{}' was rejected for a file conflict with '{}' during discovery.",
                     root_path.display(),
                     kept.display()
-                )
+                );
+                report.add(conflict);
             },
         }
     }
 
-    // Removed the prompt for checking conflicts, since we shouldn't have to run this twice to begin with and this needs fixing.
-    if has_conflicts {
-        let mut launchpad = LaunchPad::new(StandardLoader, ConflictHandler::First);
-
-        launchpad.collecting(collect);
-        launchpad.ignoring(ignore);
-
-        let conflicts = launchpad.discover_roots(utils::paths::mods(), 1, filter);
-
-        let mut conflict_map: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
-
-        for conflict in conflicts.into_iter() {
-            if let ConflictKind::StandardConflict {
-                error_root,
-                local,
-                source_root,
-            } = conflict
-            {
-                if let Some(conflicting_mods) = conflict_map.get_mut(&local) {
-                    conflicting_mods.push(error_root);
-                } else {
-                    conflict_map.insert(local, vec![source_root, error_root]);
-                }
-            }
-        }
+    report.extend(file_conflicts.into_iter().filter(|conflict| matches!(conflict, ConflictKind::StandardConflict { .. })));
 
-        let should_log = match serde_json::to_string_pretty(&conflict_map) {
+    if !report.is_empty() {
+        let should_log = match serde_json::to_string_pretty(&report) {
             Ok(json) => match std::fs::write("sd:/ultimate/arcropolis/conflicts.json", json.as_bytes()) {
                 Ok(_) => {
-                    crate::dialog_error("Conflict file created at sd:/ultimate/arcropolis/conflicts.json. Please open this file in a text editor to preview what mods are conflicting with one another and take the necessary changes to resolve them by either reslotting or removing these mods.");
+                    crate::dialog_error("Conflict file created at sd:/ultimate/arcropolis/conflicts.json. Please open this file in a text editor to preview what mods are conflicting with one another or could not be loaded, and take the necessary changes to resolve them by either reslotting or removing these mods.");
                     false
                 },
                 Err(e) => {
                     crate::dialog_error(format!(
-                        "Failed to write conflict map to sd:/ultimate/arcropolis/conflicts.json<br>{:?}",
+                        "Failed to write conflict report to sd:/ultimate/arcropolis/conflicts.json<br>{:?}",
                         e
                     ));
                     true
                 },
             },
             Err(e) => {
-                crate::dialog_error(format!("Failed to serialize conflict map to JSON. {:?}", e));
+                crate::dialog_error(format!("Failed to serialize conflict report to JSON. {:?}", e));
                 true
             },
         };
 
         if should_log {
-            for (local, roots) in conflict_map {
-                error!("The file {} is used by the following roots:", local.display());
-                for root in roots {
+            for conflict in report.conflicts.iter() {
+                if let Some(local) = conflict.local.as_ref() {
+                    error!("The file {} is provided by {} and rejected from the following roots:", local.display(), conflict.winning_root.display());
+                } else {
+                    error!("The following roots were rejected for a conflict with {}:", conflict.winning_root.display());
+                }
+                for root in conflict.rejected_roots.iter() {
                     error!("{}", root.display());
                 }
             }
+            for refused in report.refused_mods.iter() {
+                error!("The mod {} was not loaded because of its info.toml:", refused.root.display());
+                for reason in refused.reasons.iter() {
+                    error!("It {}", reason);
+                }
+            }
         }
     }
 
@@ -240,6 +207,264 @@ pub fn perform_discovery() -> LaunchPad<StandardLoader> {
     launchpad
 }
 
+/// Get the roots that discovery walks for the active workspace, from highest to lowest priority with their variants,
+/// alongside the mods that were refused because of their info.toml constraints.
+pub fn active_roots() -> (Vec<PathBuf>, Vec<RefusedMod>) {
+    let is_emulator = utils::env::is_emulator();
+    let legacy_discovery = config::legacy_discovery();
+
+    // If the user edited their mods again, we'll have to reload them here. This is obviously bad and inefficient but it wouldn't be ARCropolis if it wasn't.
+    // Consider loading the active presets in a static RwLock so everything can manipulate them without reloading
+    let presets = config::presets::get_active_preset().unwrap();
+
+    let (load_order, variants) = if !is_emulator && !legacy_discovery {
+        (
+            config::presets::get_active_load_order().unwrap_or_default(),
+            config::presets::get_active_variants().unwrap_or_default(),
+        )
+    } else {
+        (Vec::new(), HashMap::new())
+    };
+
+    let filter = |path: &Path| {
+        // If we're not running on emulator
+        if !is_emulator && !legacy_discovery {
+            // If it's not in the presets, don't load
+            mod_id(path).map(|id| presets.contains(&id)).unwrap_or(false)
+        } else {
+            // Legacy filter, load the mod except if it has a period at the start of the name
+
+            Utf8Path::from_path(path)
+                .unwrap()
+                .file_name()
+                .map(|name| !name.starts_with('.'))
+                .unwrap_or(false)
+        }
+    };
+
+    let mut roots = enabled_roots(utils::paths::mods(), filter, &load_order);
+
+    let refused_mods = check_mod_constraints(&roots);
+
+    roots.retain(|root| !refused_mods.iter().any(|refused| refused.root == *root));
+
+    (with_variants(roots, &variants), refused_mods)
+}
+
+/// Ignore the top level of every root, dotfiles and files meant for another region
+fn ignore_path(path: &Path) -> bool {
+    let name = if let Some(name) = path.file_name().and_then(|x| x.to_str()) { name } else { return false };
+
+    let is_root = path.parent().map(|parent| parent.as_os_str().is_empty()).unwrap_or(true);
+
+    let is_dot = name.starts_with('.');
+
+    // Patches for other regions are still collected, since they can be used as a fallback for the current region
+    let is_out_of_region = if let Some(index) = name.find('+') {
+        let (_, end) = name.split_at(index + 1);
+        !end.starts_with(&config::region().to_string()) && !patch_format::has_region_fallback(name)
+    } else {
+        false
+    };
+
+    is_root || is_dot || is_out_of_region
+}
+
+/// Collect the paths of plugins, configs and patch files so they can be processed after discovery
+fn collect_path(x: &Path) -> bool {
+    match x.file_name() {
+        Some(name) if let Some(name) = name.to_str() => {
+            static RESERVED_NAMES: &[&str] = &[
+                "config.json",
+                "plugin.nro"
+            ];
+            RESERVED_NAMES.contains(&name) || {
+                let is_out_of_region = if let Some(index) = name.find('+') {
+                    let (_, end) = name.split_at(index + 1);
+                    !end.starts_with(&config::region().to_string()) && !patch_format::has_region_fallback(name)
+                } else {
+                    false
+                };
+                patch_format::is_patch_file(name) && !is_out_of_region
+            }
+        },
+        _ => false
+    }
+}
+
+/// Position of a root in the load order, roots that are not part of it go after every other one
+fn load_order_rank(load_order: &[String], root: &Path) -> usize {
+    mod_id(root)
+        .and_then(|id| load_order.iter().position(|x| *x == id))
+        .unwrap_or(usize::MAX)
+}
+
+/// List the roots in the mods directory that pass the filter, from highest to lowest priority
+pub fn enabled_roots<P: AsRef<Path>, F: Fn(&Path) -> bool>(mods_path: P, filter: F, load_order: &[String]) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = match std::fs::read_dir(mods_path.as_ref()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir() && filter(path))
+            .collect(),
+        Err(e) => {
+            error!("Failed to read the mods directory '{}'. Reason: {:?}", mods_path.as_ref().display(), e);
+            Vec::new()
+        },
+    };
+
+    // Stable sort, so roots missing from the load order keep the directory order like before
+    roots.sort_by_cached_key(|root| load_order_rank(load_order, root));
+
+    roots
+}
+
+/// Check the info.toml constraints of roots sorted from highest to lowest priority, and get the mods that can't be loaded.
+///
+/// Refusing a mod can break the dependencies of another one, so this goes on until nothing changes.
+/// When two enabled mods are incompatible, the one with the lowest priority is refused.
+pub fn check_mod_constraints(roots: &[PathBuf]) -> Vec<RefusedMod> {
+    let arcropolis_version = utils::env::get_arcropolis_version();
+
+    let mods: Vec<(&PathBuf, String, Arc<ModManifest>)> = roots
+        .iter()
+        .filter_map(|root| {
+            let manifest = mod_manifest(root);
+            manifest.mod_id(root).map(|id| (root, id, manifest))
+        })
+        .collect();
+
+    let mut reasons: Vec<Vec<UnmetConstraint>> = mods
+        .iter()
+        .map(|(root, _, manifest)| match manifest.min_arcropolis_version.as_deref().map(Version::parse) {
+            Some(Ok(required)) if required > arcropolis_version => vec![UnmetConstraint::ArcropolisTooOld {
+                required: required.to_string(),
+            }],
+            Some(Err(e)) => {
+                warn!("Mod '{}' has an invalid min_arcropolis_version, ignoring it. Reason: {}", root.display(), e);
+                Vec::new()
+            },
+            _ => Vec::new(),
+        })
+        .collect();
+
+    loop {
+        let mut changed = false;
+
+        for index in (0..mods.len()).rev() {
+            if !reasons[index].is_empty() {
+                continue;
+            }
+
+            let is_accepted = |id: &str| {
+                mods.iter()
+                    .zip(reasons.iter())
+                    .enumerate()
+                    .any(|(other_index, ((_, other_id, _), other_reasons))| other_index != index && other_id == id && other_reasons.is_empty())
+            };
+
+            let (_, _, manifest) = &mods[index];
+
+            let mut unmet: Vec<UnmetConstraint> = manifest
+                .requires
+                .iter()
+                .filter(|dependency| !is_accepted(dependency))
+                .map(|dependency| UnmetConstraint::MissingDependency { dependency: dependency.clone() })
+                .collect();
+
+            unmet.extend(
+                manifest
+                    .conflicts_with
+                    .iter()
+                    .filter(|other| is_accepted(other))
+                    .map(|other| UnmetConstraint::Incompatible { other: other.clone() }),
+            );
+
+            if !unmet.is_empty() {
+                reasons[index] = unmet;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    mods.into_iter()
+        .zip(reasons)
+        .filter(|(_, reasons)| !reasons.is_empty())
+        .map(|((root, id, _), reasons)| RefusedMod {
+            id,
+            root: root.clone(),
+            reasons,
+        })
+        .collect()
+}
+
+/// Insert the selected variants of every root right after it, so that they share its priority.
+/// Mods missing from the selection get the variants their info.toml selects by default.
+pub fn with_variants(roots: Vec<PathBuf>, selection: &HashMap<String, Vec<String>>) -> Vec<PathBuf> {
+    let mut mounted = Vec::with_capacity(roots.len());
+
+    for root in roots {
+        let manifest = mod_manifest(&root);
+        let selected = manifest.mod_id(&root).and_then(|id| selection.get(&id));
+
+        let variants: Vec<PathBuf> = manifest
+            .variants
+            .iter()
+            .filter(|variant| match selected {
+                Some(selected) => selected.contains(&variant.name),
+                None => variant.default,
+            })
+            .filter_map(|variant| {
+                if !variant.is_valid() {
+                    warn!(
+                        "Variant '{}' of mod '{}' is not inside of a folder starting with a period, ignoring it.",
+                        variant.name,
+                        root.display()
+                    );
+                    return None;
+                }
+
+                let path = root.join(&variant.path);
+
+                if path.is_dir() {
+                    info!("Mounting variant '{}' of mod '{}'.", variant.name, root.display());
+                    Some(path)
+                } else {
+                    warn!("Variant '{}' of mod '{}' could not be found at '{}'.", variant.name, root.display(), path.display());
+                    None
+                }
+            })
+            .collect();
+
+        mounted.push(root);
+        mounted.extend(variants);
+    }
+
+    mounted
+}
+
+/// Walk the provided roots, without chainloading anything.
+///
+/// Roots are discovered in order, so the first one to provide a file is the one that wins a conflict.
+pub fn discover_mods(roots: &[PathBuf], handler: ConflictHandler) -> (LaunchPad<StandardLoader>, Vec<ConflictKind>) {
+    let mut launchpad = LaunchPad::new(StandardLoader, handler);
+
+    launchpad.collecting(collect_path);
+    launchpad.ignoring(ignore_path);
+
+    let mut conflicts = Vec::new();
+
+    for root in roots {
+        conflicts.extend(launchpad.discover_in_root(root));
+    }
+
+    (launchpad, conflicts)
+}
+
+#[cfg(target_os = "switch")]
 fn mount_prebuilt_nrr<A: FileLoader>(tree: &Tree<A>) -> Result<Option<RegistrationInfo>, NrrRegistrationFailedError>
 where
     <A as FileLoader>::ErrorType: std::fmt::Debug,
@@ -260,6 +485,7 @@ where
     fighter_nro_nrr.register()
 }
 
+#[cfg(target_os = "switch")]
 pub fn load_and_run_plugins(plugins: &[(PathBuf, PathBuf)]) {
     let mut plugin_nrr = NrrBuilder::new();
 
@@ -1,45 +1,19 @@
-use std::{
-    collections::VecDeque,
-    fs::{self, File},
-    io::{Cursor, Read},
-};
-
-use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
-use hash40::diff::Diff;
-use msbt::{builder::MsbtBuilder, Msbt};
-use nus3audio::*;
-use serde::*;
-use serde_xml_rs;
-use serde_yaml::from_str;
-use smash_bgm_property::BgmPropertyFile;
-use xml::common::Position;
-
-use super::*;
-
-#[derive(Debug, Deserialize)]
-pub struct Xmsbt {
-    #[serde(rename = "entry")]
-    entries: Vec<Entry>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct Entry {
-    label: String,
-    base64: Option<bool>,
-    #[serde(rename = "text")]
-    text: Text,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct Text {
-    #[serde(rename = "$value")]
-    value: String,
-}
-
-pub enum TextType {
-    Text(String),
-    Data(Vec<u8>),
-}
+use std::collections::VecDeque;
+
+use super::{patch_trace::PatchBase, *};
+
+mod bgm_property_patch;
+pub mod delta;
+pub mod formats;
+mod motion_merge;
+mod msbt_file;
+use msbt_file::MsbtFile;
+mod nus3audio_patch;
+mod nus3bank_patch;
+mod prc_merge;
+mod vcdiff;
+pub mod xmsbt;
+use xmsbt::XmsbtEntry;
 
 #[derive(Debug, Error)]
 pub enum ApiLoaderError {
@@ -68,11 +42,8 @@ pub enum ApiLoaderError {
 #[derive(Debug, Clone, Copy)]
 enum ApiLoadType {
     Nus3bankPatch,
-    PrcPatch,
-    MsbtPatch,
-    Nus3audioPatch,
-    MotionlistPatch,
-    BgmPropertyPatch,
+    /// A file built by the patch format at this index of the registry
+    Patch(usize),
     Generic,
     Stream,
     Extension,
@@ -82,16 +53,8 @@ impl ApiLoadType {
     pub fn from_root(root: &Path) -> Result<Self, ApiLoaderError> {
         if root.ends_with("patch-nus3bank") {
             Ok(ApiLoadType::Nus3bankPatch)
-        } else if root.ends_with("patch-prc") {
-            Ok(ApiLoadType::PrcPatch)
-        } else if root.ends_with("patch-msbt") {
-            Ok(ApiLoadType::MsbtPatch)
-        } else if root.ends_with("patch-nus3audio") {
-            Ok(ApiLoadType::Nus3audioPatch)
-        } else if root.ends_with("patch-motionlist") {
-            Ok(ApiLoadType::MotionlistPatch)
-        } else if root.ends_with("patch-bgm_property") {
-            Ok(ApiLoadType::BgmPropertyPatch)
+        } else if let Some(index) = patch_format::position_for_root(root) {
+            Ok(ApiLoadType::Patch(index))
         } else if root.ends_with("generic-cb") {
             Ok(ApiLoadType::Generic)
         } else if root.ends_with("stream-cb") {
@@ -109,13 +72,9 @@ impl ApiLoadType {
 
     pub fn get_file_size(self, local: &Path) -> Option<usize> {
         match self {
-            ApiLoadType::Nus3bankPatch => {
-                let arc = resource::arc();
-                crate::get_smash_hash(local)
-                    .ok()
-                    .and_then(|hash| arc.get_file_data_from_hash(hash, config::region()).ok())
-                    .map(|x| x.decomp_size as usize)
-            },
+            ApiLoadType::Nus3bankPatch => crate::get_smash_hash(local)
+                .ok()
+                .and_then(|hash| resource::backend().decompressed_size(hash, config::region()).ok()),
             _ => None,
         }
     }
@@ -123,9 +82,8 @@ impl ApiLoadType {
     pub fn get_path_type(self, local: &Path) -> Result<FileEntryType, ApiLoaderError> {
         match self {
             ApiLoadType::Nus3bankPatch => {
-                let search = resource::search();
                 let hash = crate::get_smash_hash(local)?;
-                if search.get_path_list_entry_from_hash(hash)?.is_directory() {
+                if resource::backend().is_directory(hash)? {
                     Ok(FileEntryType::Directory)
                 } else {
                     Ok(FileEntryType::File)
@@ -136,288 +94,44 @@ impl ApiLoadType {
     }
 
     pub fn load_path(self, local: &Path, usr_fn: ApiCallback) -> Result<(usize, Vec<u8>), ApiLoaderError> {
-        println!("[ARCropolis::loader] Patching {:#?}", local.as_os_str());
+        debug!("Loading {} through the API loader", local.display());
 
         match self {
             ApiLoadType::Nus3bankPatch => {
                 let data = ApiLoader::handle_load_vanilla_file(local)?;
                 Ok((data.len(), data))
             },
-            ApiLoadType::PrcPatch => {
-                let patches = if let Some(patches) = ApiLoader::get_prc_patches_for_hash(local.smash_hash()?) {
-                    patches
-                } else {
-                    return Err(ApiLoaderError::Other("[ARCropolis::loader] No patches found for file of type PRC!".to_string()));
-                };
-
-                let data = ApiLoader::handle_load_base_file(local)?;
-                let mut param_data = prcx::read_stream(&mut Cursor::new(data))
-                    .map_err(|_| ApiLoaderError::Other("Unable to parse param data!".to_string()))?;
-
-                for patch_path in patches.iter() {
-                    let patch = if let Ok(patch) = prcx::open(patch_path) {
-                        patch
-                    } else {
-                        let file = File::open(patch_path)?;
-                        let mut reader = std::io::BufReader::new(file);
-
-                        prcx::read_xml(&mut reader).map_err(|_| ApiLoaderError::Other("Unable to parse param patch data!".to_string()))?
-                    };
-
-                    prcx::apply_patch(&patch, &mut param_data).map_err(|_| ApiLoaderError::Other("Unable to patch param data!".to_string()))?;
-                }
-
-                let mut writer = Cursor::new(Vec::new());
-                prcx::write_stream(&mut writer, &param_data)?;
-                let data = writer.into_inner();
-                Ok((data.len(), data))
-            },
-            ApiLoadType::MsbtPatch => {
-                let patches = if let Some(patches) = ApiLoader::get_msbt_patches_for_hash(local.smash_hash()?) {
-                    patches
-                } else {
-                    return Err(ApiLoaderError::Other("No patches found for file of type MSBT!".to_string()));
-                };
-
-                let mut labels: HashMap<String, TextType> = HashMap::new();
-
-                for patch_path in patches.iter() {
-                    let mut reader = Cursor::new(fs::read(patch_path)?);
-                    let xmsbt: Xmsbt = match serde_xml_rs::from_reader(&mut reader) {
-                        Ok(xmsbt) => xmsbt,
-                        Err(err) => {
-                            match err {
-                                serde_xml_rs::Error::Syntax { source }  => {
-                                    let position = source.position();
-                                    warn!("XMSBT file `{}` could not be read due to the following syntax error at line {}, column {}: `{}`, skipping.", patch_path.display(), position.row + 1, position.column, source.msg())
-                                },
-                                _ => warn!("XMSBT file `{}` is malformed, skipping.", patch_path.display()),
-                            }
-
-                            continue;
-                        },
-                    };
-
-                    for entry in &xmsbt.entries {
-                        if entry.base64.unwrap_or(false) {
-                            match BASE64_STANDARD.decode::<String>(entry.text.value.to_owned()) {
-                                Ok(mut decoded) => {
-                                    // Pushing these 0s to ensure that the end of the text is marked clearly
-                                    decoded.push(0);
-                                    decoded.push(0);
-                                    labels.insert(entry.label.to_owned(), TextType::Data(decoded));
-                                },
-                                Err(err) => error!("XMSBT Label {} could not be base64 decoded. Reason: {}", entry.label, err),
-                            }
-                        } else {
-                            labels.insert(entry.label.to_owned(), TextType::Text(entry.text.value.to_owned()));
-                        }
-                    }
-                }
-
-                let data = ApiLoader::handle_load_base_file(local)?;
-                let mut msbt = Msbt::from_reader(Cursor::new(&data)).unwrap();
-
-                for lbl in msbt.lbl1_mut().unwrap().labels_mut() {
-                    let lbl_name = &lbl.name().to_owned();
-
-                    if labels.contains_key(lbl_name) {
-                        let text_data = match &labels[lbl_name] {
-                            TextType::Text(text) => {
-                                let mut str_val: Vec<u16> = text.encode_utf16().collect();
-                                str_val.push(0);
-
-                                let slice_u8: &[u8] = unsafe {
-                                    std::slice::from_raw_parts(
-                                        str_val.as_ptr() as *const u8,
-                                        str_val.len() * std::mem::size_of::<u16>(),
-                                    )
-                                };
-
-                                slice_u8
-                            },
-                            TextType::Data(data) => &data,
-                        };
+            ApiLoadType::Patch(index) => {
+                let format = patch_format::get(index).ok_or_else(|| ApiLoaderError::Other("Unknown patch format!".to_string()))?;
 
-                        lbl.set_value_raw(text_data).unwrap();
-                        labels.remove(lbl_name);
-                    }
-                }
-
-                let mut builder = MsbtBuilder::from(msbt);
-
-                for lbl in labels {
-                    let text_data = match &lbl.1 {
-                        TextType::Text(text) => {
-                            let mut str_val: Vec<u16> = text
-                                    .encode_utf16()
-                                    .collect();
-
-                            str_val.push(0);
-
-                            let slice_u8: &[u8] = unsafe {
-                                std::slice::from_raw_parts(
-                                    str_val.as_ptr() as *const u8,
-                                    str_val.len() * std::mem::size_of::<u16>(),
-                                )
-                            };
-
-                            slice_u8
-                        },
-                        TextType::Data(data) => &data,
-                    };
-
-                    builder = builder.add_label(lbl.0, text_data);
-                }
-
-                let out_msbt = builder.build();
-                let mut cursor = Cursor::new(Vec::new());
-                out_msbt.write_to(&mut cursor).unwrap();
-                let data = cursor.into_inner();
-                Ok((data.len(), data))
-            },
-            ApiLoadType::Nus3audioPatch => {
-                let patches = if let Some(patches) = ApiLoader::get_nus3audio_patches_for_hash(local.smash_hash()?) {
-                    patches
-                } else {
-                    return Err(ApiLoaderError::Other("No patches found for file of type NUS3AUDIO!".to_string()));
-                };
-
-                // Initialize the `original_file` variable, which parses the pre patch file into the nus3audio type
-                let mut original_file = Nus3audioFile::from_bytes(&ApiLoader::handle_load_base_file(local).unwrap()[..]);
-
-                // This is a little weird imo, but it's the only good solution I could come up with
-                // Basically what it's doing past this point is:
-                //     ~ looping through the original file's audiofiles to get their names and insert the name
-                //     and itself into the HashMap
-                //     ~ looping through the patches, and then applying them to the HashMap
-                //     ~ setting the base file's AudioFile vec to the values of the HashMap
-                let mut known_audiofiles: HashMap<String, AudioFile> = original_file.files.iter().map(|audio_file| (audio_file.name.clone(), audio_file.clone())).collect();
-
-                // Iterate through the patches
-                for patch_path in patches.iter() {
-                    // Reads the patch file data and parses it into the nus3audio type
-                    let patch_data = &std::fs::read(patch_path).unwrap()[..];
-                    let modified_file = Nus3audioFile::from_bytes(patch_data);
-
-                    // Iterate through the AudioFiles of the modified file
-                    for mut audio_file in modified_file.files {
-                        // Check if the known AudioFiles HashMap contains the name of the current AudioFile
-                        if known_audiofiles.contains_key(&audio_file.name) {
-                            // If it does, set the already made AudioFile's data to the modified one/
-                            println!("Found {}! Patching...", &audio_file.name);
-                            known_audiofiles.get_mut(&audio_file.name).unwrap().data = audio_file.data.clone();
-                        }
-                        else {
-                            // If it doesn't, insert it into the known_audiofiles HashMap
-                            println!("Not found {}! Adding...", &audio_file.name);
-                            audio_file.id = (known_audiofiles.len() + 1) as u32;
-                            known_audiofiles.try_insert(audio_file.name.clone(), audio_file.clone()).unwrap();
-                        }
-                    }
-                }
-
-                // Initialize the `new_audio_files` Vec, which takes in the values of the known_audiofiles HashMap
-                let mut new_audio_files: Vec<AudioFile> = known_audiofiles.iter().map(|(_, audio_file)| audio_file.clone()).collect();
-
-                // Sort the new_audio_files vec by ID, because if we don't, the game loads the wrong AudioFiles on request.
-                new_audio_files.sort_by(|a, b| a.id.cmp(&b.id));
-
-                // Set the original file's AudioFile vec to the new_audio_files vec
-                original_file.files = new_audio_files;
-
-                let mut contents: Vec<u8> = Vec::new();
-
-                // Write the contents of the original file to the contents vec
-                original_file.write(&mut contents);
-
-                // Return the length of the contents and the contents
-                Ok((contents.len(), contents))
+                let hash = local.smash_hash()?;
 
-            },
-            ApiLoadType::MotionlistPatch => {
-                let patches = if let Some(patches) = ApiLoader::get_motionlist_patches_for_hash(local.smash_hash()?) {
+                let patches = if let Some(patches) = ApiLoader::get_patches_for_hash(format.name(), hash) {
                     patches
                 } else {
-                    return Err(ApiLoaderError::Other("[ARCropolis::loader] No patches found for files motion_list.bin!".to_string()));
+                    return Err(ApiLoaderError::Other(format!("No patches found for file of type {}!", format.name())));
                 };
 
-                let mut yml_patches = Vec::new();
-                let mut diff_patches = Vec::new();
-
-                for patch_path in patches.iter() {
-                    if patch_path.has_extension("motdiff") {
-                        diff_patches.push(patch_path.clone());
-                    } else if patch_path.ends_with("motion_list.yml") {
-                        yml_patches.push(patch_path.clone());
-                    } else {
-                        return Err(ApiLoaderError::Other("This isn't a motion list patch file!".to_string()));
-                    }
-                }
-
-                let data = ApiLoader::handle_load_base_file(local)?;
-                let mut reader = Cursor::new(data);
-                let mut motion_list = motion_lib::read_stream(&mut reader)?;
-
-                if !yml_patches.is_empty() {
-                    println!("[ARCropolis::loader] motion_list.yml file(s) found!");
-                    let mut full_patches = 0;
-
-                    for full_patch in yml_patches.iter() {
-                        println!("[ARCropolis::loader] Replacing motion_list.bin with {}.", full_patch.to_str().unwrap());
-                        let mut contents: String = String::default();
-                        File::open(full_patch)?.read_to_string(&mut contents)?;
-                        if let Some(full) = from_str(&contents)? {
-                            motion_list = full;
-                            full_patches += 1;
-                        }
-                    }
+                patch_trace::begin(hash, local, format.name(), &patches);
+                let start = std::time::Instant::now();
 
-                    if full_patches > 1 {
-                        println!("[ARCropolis::loader] Multiple motion_list.yml files found for {}.", local.to_str().unwrap());
-                        println!("                     The last applied .yml file will be used.");
-                    }
-                }
+                let cache_key = patch_cache::compute_patch_cache_key(format.as_ref(), local, hash, &patches);
 
-                if !diff_patches.is_empty() {
-                    for patch_path in diff_patches.iter() {
-                        let mut contents: String = String::default();
-                        File::open(patch_path)?.read_to_string(&mut contents)?;
-                        if let Some(diff) = from_str(&contents)? {
-                            motion_list.apply(&diff);
-                        }
-                        else {
-                            return Err(ApiLoaderError::Other("This isn't a motion list patch file!".to_string()));
-                        }
-                    }
+                if let Some(data) = cache_key.and_then(|key| patch_cache::load_patched_file(format.as_ref(), hash, key)) {
+                    patch_trace::note(String::from("Loaded from the patch cache."));
+                    patch_trace::finish(start.elapsed(), Ok(data.len()));
+                    return Ok((data.len(), data));
                 }
 
-                println!("[ARCropolis::loader] 'motion_list.bin' patching finished!");
-                let mut writer = Cursor::new(Vec::new());
-                motion_lib::write_stream(&mut writer, &motion_list)?;
-                let data = writer.into_inner();
-                Ok((data.len(), data))
-            },
-            ApiLoadType::BgmPropertyPatch => {
-                let patches = if let Some(patches) = ApiLoader::get_bgm_property_patches_for_hash(local.smash_hash()?) {
-                    patches
-                } else {
-                    return Err(ApiLoaderError::Other("[ARCropolis::loader] No patches found for file bgm_property.bin!".to_string()));
-                };
+                let result = format.merge(local, &patches);
+                patch_trace::finish(start.elapsed(), result.as_ref().map(|data| data.len()).map_err(|err| err.to_string()));
 
-                let data = ApiLoader::handle_load_base_file(local)?;
-                let mut reader = Cursor::new(&data[..]);
-                let mut bgm_property = BgmPropertyFile::read(&mut reader).unwrap();
+                let data = result?;
 
-                for patch_path in patches.iter() {
-                    let mut patch_file = BgmPropertyFile::from_file(patch_path).unwrap();
-
-                    bgm_property.entries.append(&mut patch_file.entries);
+                if let Some(key) = cache_key {
+                    patch_cache::save_patched_file(format.as_ref(), hash, key, &data);
                 }
 
-                let mut writer = Cursor::new(Vec::new());
-                bgm_property.write(&mut writer).unwrap();
-                let data = writer.into_inner();
                 Ok((data.len(), data))
             },
             ApiLoadType::Generic if let ApiCallback::GenericCallback(cb) = usr_fn => {
@@ -464,6 +178,80 @@ impl ApiLoadType {
     }
 }
 
+/// Apply a single XMSBT entry to the MSBT, logging the parts of it that can't be applied
+fn apply_xmsbt_entry(msbt: &mut MsbtFile, entry: &XmsbtEntry, patch_path: &Path) {
+    if entry.remove {
+        if msbt.remove(&entry.label).is_none() {
+            warn!("XMSBT file `{}` removes label {}, which does not exist.", patch_path.display(), entry.label);
+        }
+        return;
+    }
+
+    let label = match entry.rename.as_ref() {
+        Some(new_label) => {
+            if msbt.position(new_label).is_some() {
+                warn!("XMSBT file `{}` renames label {} to {}, which already exists, skipping.", patch_path.display(), entry.label, new_label);
+                return;
+            }
+
+            match msbt.get_mut(&entry.label) {
+                Some(renamed) => renamed.label = new_label.clone(),
+                None => warn!("XMSBT file `{}` renames label {}, which does not exist. It is added instead.", patch_path.display(), entry.label),
+            }
+
+            new_label
+        },
+        None => &entry.label,
+    };
+
+    let has_attributes = msbt.has_attributes();
+    let attribute_size = msbt.attribute_size();
+    let has_styles = msbt.has_styles();
+    let big_endian = msbt.is_big_endian();
+
+    if msbt.position(label).is_none() && msbt.has_attribute_offsets() && entry.attributes.is_none() {
+        let message = format!(
+            "XMSBT file `{}` adds label {} with zeroed attributes, but the attributes of the file point to data stored after them. Set them with `attributes` if the game reads them.",
+            patch_path.display(),
+            label
+        );
+        warn!("{}", message);
+        patch_trace::note(message);
+    }
+
+    let target = msbt.get_or_insert(label);
+
+    if let Some(text) = entry.text.as_ref() {
+        target.text = text.encode(big_endian);
+    }
+
+    if let Some(attribute) = entry.attributes.as_ref() {
+        if !has_attributes {
+            warn!("XMSBT Label {} sets attributes, but the file doesn't have any. They are ignored.", label);
+        } else {
+            let mut attribute = attribute.clone();
+            if attribute.len() != attribute_size {
+                warn!(
+                    "XMSBT Label {} sets {} bytes of attributes, but the file expects {}. They are resized to fit.",
+                    label,
+                    attribute.len(),
+                    attribute_size
+                );
+                attribute.resize(attribute_size, 0);
+            }
+            target.attribute = Some(attribute);
+        }
+    }
+
+    if let Some(style) = entry.style {
+        if has_styles {
+            target.style = Some(style);
+        } else {
+            warn!("XMSBT Label {} sets a style, but the file doesn't have any. It is ignored.", label);
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum ApiCallback {
     None,
@@ -486,11 +274,8 @@ struct ApiFunctionEntry {
 pub struct ApiLoader {
     function_map: HashMap<Hash40, UnsafeCell<ApiFunctionEntry>>,
     stream_size_map: UnsafeCell<HashMap<PathBuf, usize>>,
-    param_patches: HashMap<Hash40, Vec<PathBuf>>,
-    msbt_patches: HashMap<Hash40, Vec<PathBuf>>,
-    nus3audio_patches: HashMap<Hash40, Vec<PathBuf>>,
-    motionlist_patches: HashMap<Hash40, Vec<PathBuf>>,
-    bgm_property_patches: HashMap<Hash40, Vec<PathBuf>>,
+    /// Patch files of every format, by the name of the format and the hash of the file they target
+    patches: HashMap<String, HashMap<Hash40, Vec<PathBuf>>>,
 }
 
 unsafe impl Send for ApiLoader {}
@@ -514,6 +299,11 @@ impl ApiLoader {
         }
     }
 
+    /// Whether the file is provided by the API loader, through a callback or a patch format
+    pub fn is_virtual(&self, local: &Path) -> bool {
+        local.smash_hash().map(|hash| self.function_map.contains_key(&hash)).unwrap_or(false)
+    }
+
     fn use_virtual_file(&self, local: &Path) -> Option<(&Path, ApiCallback)> {
         local.smash_hash().ok().and_then(|x| self.function_map.get(&x)).and_then(|entry| {
             let data = entry.get();
@@ -540,96 +330,102 @@ impl ApiLoader {
     }
 
     pub fn handle_load_vanilla_file(local: &Path) -> Result<Vec<u8>, ApiLoaderError> {
-        let arc = resource::arc();
         let hash = crate::get_smash_hash(local)?;
 
-        Ok(arc.get_file_contents(hash, config::region())?)
+        Ok(resource::backend().file_contents(hash, config::region())?)
     }
 
     pub fn handle_load_base_file(local: &Path) -> Result<Vec<u8>, ApiLoaderError> {
-        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let cached = filesystem.get();
+        let modded = crate::fs::read_filesystem(|filesystem| {
+            let cached = filesystem.get();
 
-        if cached.get_patch_entry_type(local).is_ok() {
-            cached.load_patch(local).map_err(|x| ApiLoaderError::Other(format!("{:?}", x)))
+            if cached.get_patch_entry_type(local).is_ok() {
+                let data = cached.load_patch(local).map_err(|x| ApiLoaderError::Other(format!("{:?}", x)));
+                Some(data.map(|data| (cached.query_actual_path(local), data)))
+            } else {
+                None
+            }
+        });
+
+        if let Some(modded) = modded {
+            let (path, data) = modded?;
+            patch_trace::record_base(PatchBase::Mod(path), data.len());
+            Ok(data)
         } else {
-            Self::handle_load_vanilla_file(local)
+            let data = Self::handle_load_vanilla_file(local)?;
+            patch_trace::record_base(PatchBase::Vanilla, data.len());
+            Ok(data)
         }
     }
 
-    pub fn get_prc_patches_for_hash(hash: Hash40) -> Option<&'static Vec<PathBuf>> {
-        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let cached = filesystem.get();
+    pub fn get_patches_for_hash(format: &str, hash: Hash40) -> Option<Vec<PathBuf>> {
+        crate::fs::read_filesystem(|filesystem| {
+            let cached = filesystem.get();
 
-        cached.virt().loader.param_patches.get(&hash)
+            cached.virt().loader.patches.get(format).and_then(|patches| patches.get(&hash)).cloned()
+        })
     }
 
-    pub fn get_msbt_patches_for_hash(hash: Hash40) -> Option<&'static Vec<PathBuf>> {
-        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let cached = filesystem.get();
-
-        cached.virt().loader.msbt_patches.get(&hash)
+    pub fn insert_patch(&mut self, format: &str, hash: Hash40, path: &Path) {
+        self.patches
+            .entry(format.to_string())
+            .or_default()
+            .entry(hash)
+            .or_default()
+            .push(path.to_path_buf());
     }
 
-    pub fn get_nus3audio_patches_for_hash(hash: Hash40) -> Option<&'static Vec<PathBuf>> {
-        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let cached = filesystem.get();
+    /// Index of the format that builds the file when it is loaded, and the patches of that format targeting it.
+    /// If several formats patch the same file, this is the one whose root is served first, like when loading.
+    pub fn patches_for(&self, hash: Hash40) -> Option<(usize, &Vec<PathBuf>)> {
+        let entry = unsafe { &*self.function_map.get(&hash)?.get() };
 
-        cached.virt().loader.nus3audio_patches.get(&hash)
-    }
+        entry.functions.iter().find_map(|(root, _)| {
+            let index = patch_format::position_for_root(root)?;
+            let format = patch_format::get(index)?;
 
-    pub fn get_motionlist_patches_for_hash(hash: Hash40) -> Option<&'static Vec<PathBuf>> {
-        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let cached = filesystem.get();
-
-        cached.virt().loader.motionlist_patches.get(&hash)
+            Some((index, self.patches.get(format.name())?.get(&hash)?))
+        })
     }
 
-    pub fn get_bgm_property_patches_for_hash(hash: Hash40) -> Option<&'static Vec<PathBuf>> {
-        let filesystem = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let cached = filesystem.get();
+    /// Size of the patched file once built, taken from the patch cache or by building it.
+    /// Returns `None` if the file can't be built ahead of time, because its format isn't cacheable.
+    pub fn patched_size(&self, hash: Hash40, local: &Path) -> Result<Option<usize>, ApiLoaderError> {
+        let (index, patches) = match self.patches_for(hash) {
+            Some(patches) => patches,
+            None => return Ok(None),
+        };
 
-        cached.virt().loader.bgm_property_patches.get(&hash)
-    }
+        let format = patch_format::get(index).ok_or_else(|| ApiLoaderError::Other("Unknown patch format!".to_string()))?;
 
-    pub fn insert_prc_patch(&mut self, hash: Hash40, path: &Path) {
-        if let Some(list) = self.param_patches.get_mut(&hash) {
-            list.push(path.to_path_buf())
-        } else {
-            self.param_patches.insert(hash, vec![path.to_path_buf()]);
+        if !format.is_cacheable() {
+            return Ok(None);
         }
-    }
 
-    pub fn insert_msbt_patch(&mut self, hash: Hash40, path: &Path) {
-        if let Some(list) = self.msbt_patches.get_mut(&hash) {
-            list.push(path.to_path_buf())
-        } else {
-            self.msbt_patches.insert(hash, vec![path.to_path_buf()]);
-        }
-    }
+        let cached_size = patch_cache::compute_patch_cache_key(format.as_ref(), local, hash, patches)
+            .and_then(|key| patch_cache::cached_size(format.as_ref(), hash, key));
 
-    pub fn insert_nus3audio_patch(&mut self, hash: Hash40, path: &Path) {
-        if let Some(list) = self.nus3audio_patches.get_mut(&hash) {
-            list.push(path.to_path_buf())
-        } else {
-            self.nus3audio_patches.insert(hash, vec![path.to_path_buf()]);
+        if let Some(size) = cached_size {
+            return Ok(Some(size));
         }
-    }
 
-    pub fn insert_motionlist_patch(&mut self, hash: Hash40, path: &Path) {
-        if let Some(list) = self.motionlist_patches.get_mut(&hash) {
-            list.push(path.to_path_buf())
-        } else {
-            self.motionlist_patches.insert(hash, vec![path.to_path_buf()]);
-        }
+        // Building the file also puts it in the patch cache, so it doesn't have to be built again when it is loaded
+        let (size, _) = ApiLoadType::Patch(index).load_path(local, ApiCallback::None)?;
+
+        Ok(Some(size))
     }
 
-    pub fn insert_bgm_property_patch(&mut self, hash: Hash40, path: &Path) {
-        if let Some(list) = self.bgm_property_patches.get_mut(&hash) {
-            list.push(path.to_path_buf())
-        } else {
-            self.bgm_property_patches.insert(hash, vec![path.to_path_buf()]);
+    /// Every registered patch file, grouped by the hash of the file it targets
+    pub fn patch_targets(&self) -> HashMap<Hash40, Vec<PathBuf>> {
+        let mut targets: HashMap<Hash40, Vec<PathBuf>> = HashMap::new();
+
+        for patches in self.patches.values() {
+            for (hash, paths) in patches.iter() {
+                targets.entry(*hash).or_default().extend(paths.iter().cloned());
+            }
         }
+
+        targets
     }
 
     fn get_stream_cb_path(&self, local: &Path) -> Option<String> {
@@ -642,7 +438,7 @@ impl ApiLoader {
                         } else {
                             (*self.stream_size_map.get()).insert(local.to_path_buf(), sz);
                         }
-                        Some(skyline::from_c_str(data.as_ptr()))
+                        Some(std::ffi::CStr::from_ptr(data.as_ptr() as _).to_string_lossy().into_owned())
                     },
                     _ => self.get_stream_cb_path(local),
                 },
@@ -727,42 +523,29 @@ impl FileLoader for ApiLoader {
     }
 }
 
-#[repr(transparent)]
-pub struct ArcLoader(pub(super) &'static LoadedArc);
-
-unsafe impl Send for ArcLoader {}
-unsafe impl Sync for ArcLoader {}
-
-impl Deref for ArcLoader {
-    type Target = LoadedArc;
-
-    fn deref(&self) -> &Self::Target {
-        self.0
-    }
-}
+/// Loads vanilla files through the active resource backend
+pub struct ArcLoader;
 
 impl FileLoader for ArcLoader {
     type ErrorType = LookupError;
 
     fn path_exists(&self, _: &Path, local_path: &Path) -> bool {
         match crate::get_smash_hash(local_path) {
-            Ok(hash) => self.get_file_path_index_from_hash(hash).is_ok(),
+            Ok(hash) => resource::backend().contains_file(hash),
             _ => false,
         }
     }
 
     fn get_file_size(&self, _: &Path, local_path: &Path) -> Option<usize> {
         match crate::get_smash_hash(local_path) {
-            Ok(hash) => self
-                .get_file_data_from_hash(hash, config::region())
-                .map_or_else(|_| None, |data| Some(data.decomp_size as usize)),
+            Ok(hash) => resource::backend().decompressed_size(hash, config::region()).ok(),
             Err(_) => None,
         }
     }
 
     fn get_path_type(&self, _: &Path, local_path: &Path) -> Result<FileEntryType, Self::ErrorType> {
         match crate::get_smash_hash(local_path) {
-            Ok(hash) => match self.get_path_list_entry_from_hash(hash)?.is_directory() {
+            Ok(hash) => match resource::backend().is_directory(hash)? {
                 true => Ok(FileEntryType::Directory),
                 false => Ok(FileEntryType::File),
             },
@@ -779,7 +562,7 @@ impl FileLoader for ArcLoader {
         };
 
         match hash {
-            Ok(path) => self.get_file_contents(path, config::region()),
+            Ok(path) => resource::backend().file_contents(path, config::region()),
             Err(_) => Err(LookupError::Missing),
         }
     }
@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use hash40::Hash40;
+use serde::Deserialize;
+use smash_bgm_property::{BgmPropertyEntry, BgmPropertyFile};
+
+/// Readable patch for `bgm_property.bin`, written as a `bgm_property.yml` or `bgm_property.json`
+#[derive(Debug, Default, Deserialize)]
+pub struct BgmPropertyPatch {
+    /// Entries to edit or add, matched by their name
+    #[serde(default)]
+    pub entries: Vec<BgmPropertyEntryPatch>,
+    /// Names of the entries to remove
+    #[serde(default)]
+    pub remove: Vec<Hash40>,
+}
+
+/// Fields to set on an entry. Fields that are left out keep their current value, but new entries need all of them.
+#[derive(Debug, Deserialize)]
+pub struct BgmPropertyEntryPatch {
+    pub name_id: Hash40,
+    pub loop_start_ms: Option<u32>,
+    pub loop_start_sample: Option<u32>,
+    pub loop_end_ms: Option<u32>,
+    pub loop_end_sample: Option<u32>,
+    pub total_time_ms: Option<u32>,
+    pub total_samples: Option<u32>,
+}
+
+impl BgmPropertyEntryPatch {
+    fn apply(&self, entry: &mut BgmPropertyEntry) {
+        entry.loop_start_ms = self.loop_start_ms.unwrap_or(entry.loop_start_ms);
+        entry.loop_start_sample = self.loop_start_sample.unwrap_or(entry.loop_start_sample);
+        entry.loop_end_ms = self.loop_end_ms.unwrap_or(entry.loop_end_ms);
+        entry.loop_end_sample = self.loop_end_sample.unwrap_or(entry.loop_end_sample);
+        entry.total_time_ms = self.total_time_ms.unwrap_or(entry.total_time_ms);
+        entry.total_samples = self.total_samples.unwrap_or(entry.total_samples);
+    }
+
+    fn to_entry(&self) -> Option<BgmPropertyEntry> {
+        Some(BgmPropertyEntry {
+            name_id: self.name_id,
+            loop_start_ms: self.loop_start_ms?,
+            loop_start_sample: self.loop_start_sample?,
+            loop_end_ms: self.loop_end_ms?,
+            loop_end_sample: self.loop_end_sample?,
+            total_time_ms: self.total_time_ms?,
+            total_samples: self.total_samples?,
+        })
+    }
+}
+
+fn same_entry(a: &BgmPropertyEntry, b: &BgmPropertyEntry) -> bool {
+    a.name_id == b.name_id
+        && a.loop_start_ms == b.loop_start_ms
+        && a.loop_start_sample == b.loop_start_sample
+        && a.loop_end_ms == b.loop_end_ms
+        && a.loop_end_sample == b.loop_end_sample
+        && a.total_time_ms == b.total_time_ms
+        && a.total_samples == b.total_samples
+}
+
+/// The entries of a `bgm_property.bin`, with patches applied in order.
+///
+/// Entries are matched by their name, so patches replace or remove the existing entry instead of adding a duplicate.
+pub struct BgmPropertyMerge {
+    file: BgmPropertyFile,
+    /// Patch that last changed each entry, to report entries that several patches change
+    changed_by: HashMap<Hash40, PathBuf>,
+}
+
+impl BgmPropertyMerge {
+    pub fn new(file: BgmPropertyFile) -> Self {
+        Self {
+            file,
+            changed_by: HashMap::new(),
+        }
+    }
+
+    fn position(&self, name_id: Hash40) -> Option<usize> {
+        self.file.entries.iter().position(|entry| entry.name_id == name_id)
+    }
+
+    fn mark_changed(&mut self, name_id: Hash40, patch_path: &Path) {
+        if let Some(previous) = self.changed_by.insert(name_id, patch_path.to_path_buf()) {
+            if previous != patch_path {
+                warn!(
+                    "bgm_property entry {:?} is changed by both '{}' and '{}'. The change from '{}' is used.",
+                    name_id,
+                    previous.display(),
+                    patch_path.display(),
+                    patch_path.display()
+                );
+            }
+        }
+    }
+
+    /// Apply a binary `bgm_property.bin` patch, replacing the entries it shares with the file and adding the others
+    pub fn apply_file(&mut self, patch: BgmPropertyFile, patch_path: &Path) {
+        for entry in patch.entries {
+            match self.position(entry.name_id) {
+                // Patches made from a full copy of the file carry every vanilla entry, those are left alone
+                Some(index) if same_entry(&self.file.entries[index], &entry) => {},
+                Some(index) => {
+                    debug!("Replacing bgm_property entry {:?} with the one in '{}'.", entry.name_id, patch_path.display());
+                    self.mark_changed(entry.name_id, patch_path);
+                    self.file.entries[index] = entry;
+                },
+                None => {
+                    debug!("Adding bgm_property entry {:?} from '{}'.", entry.name_id, patch_path.display());
+                    self.mark_changed(entry.name_id, patch_path);
+                    self.file.entries.push(entry);
+                },
+            }
+        }
+    }
+
+    pub fn apply_patch(&mut self, patch: BgmPropertyPatch, patch_path: &Path) {
+        for name_id in patch.remove {
+            match self.position(name_id) {
+                Some(index) => {
+                    self.mark_changed(name_id, patch_path);
+                    self.file.entries.remove(index);
+                },
+                None => warn!("'{}' removes bgm_property entry {:?}, which does not exist.", patch_path.display(), name_id),
+            }
+        }
+
+        for entry_patch in patch.entries.iter() {
+            match self.position(entry_patch.name_id) {
+                Some(index) => {
+                    self.mark_changed(entry_patch.name_id, patch_path);
+                    entry_patch.apply(&mut self.file.entries[index]);
+                },
+                None => match entry_patch.to_entry() {
+                    Some(entry) => {
+                        self.mark_changed(entry.name_id, patch_path);
+                        self.file.entries.push(entry);
+                    },
+                    None => warn!(
+                        "'{}' adds bgm_property entry {:?} without setting all of its fields, skipping.",
+                        patch_path.display(),
+                        entry_patch.name_id
+                    ),
+                },
+            }
+        }
+    }
+
+    pub fn into_file(self) -> BgmPropertyFile {
+        self.file
+    }
+}
@@ -0,0 +1,212 @@
+use std::io::Cursor;
+
+use thiserror::Error;
+
+use super::vcdiff::{self, VcdiffError};
+
+/// Magic of the header that records which file a delta patch was made from
+pub const DELTA_MAGIC: &[u8; 8] = b"ARCDELTA";
+const DELTA_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 0x18;
+
+#[derive(Debug, Error)]
+pub enum DeltaError {
+    #[error("The patch was made from a file of {expected_size:#x} bytes with CRC32 {expected_crc:#010x}, but the file it applies to has {size:#x} bytes with CRC32 {crc:#010x}. Another mod probably replaces it, or the game was updated.")]
+    BaseMismatch {
+        expected_size: u64,
+        expected_crc: u32,
+        size: u64,
+        crc: u32,
+    },
+
+    #[error("The patch doesn't record the file it was made from. Add the header with `arcrop-check delta-header`, or remake it with `arcrop-check bsdiff`.")]
+    MissingHeader,
+
+    #[error("The header of the patch has version {0}, which is not supported.")]
+    UnsupportedVersion(u32),
+
+    #[error("Unable to apply the bsdiff patch. {0}")]
+    Bsdiff(std::io::Error),
+
+    #[error("Unable to apply the xdelta patch. {0}")]
+    Xdelta(#[from] VcdiffError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaKind {
+    /// Patches made by the `bsdiff` crate, which are not compressed
+    Bsdiff,
+    /// VCDIFF patches made by `xdelta3 -S none`
+    Xdelta,
+}
+
+/// Size and checksum of the file a delta patch was made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaHeader {
+    pub base_size: u64,
+    pub base_crc: u32,
+}
+
+impl DeltaHeader {
+    pub fn new(base: &[u8]) -> Self {
+        Self {
+            base_size: base.len() as u64,
+            base_crc: crc32fast::hash(base),
+        }
+    }
+
+    /// Read the header in front of the patch and return it with the rest of the patch
+    pub fn read(patch: &[u8]) -> Result<(Self, &[u8]), DeltaError> {
+        if patch.len() < HEADER_SIZE || &patch[..8] != DELTA_MAGIC {
+            return Err(DeltaError::MissingHeader);
+        }
+
+        let version = u32::from_le_bytes(patch[0x8..0xC].try_into().unwrap());
+
+        if version != DELTA_VERSION {
+            return Err(DeltaError::UnsupportedVersion(version));
+        }
+
+        let header = Self {
+            base_size: u64::from_le_bytes(patch[0xC..0x14].try_into().unwrap()),
+            base_crc: u32::from_le_bytes(patch[0x14..0x18].try_into().unwrap()),
+        };
+
+        Ok((header, &patch[HEADER_SIZE..]))
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(DELTA_MAGIC);
+        out.extend_from_slice(&DELTA_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.base_size.to_le_bytes());
+        out.extend_from_slice(&self.base_crc.to_le_bytes());
+    }
+
+    pub fn check(&self, base: &[u8]) -> Result<(), DeltaError> {
+        let actual = Self::new(base);
+
+        if actual != *self {
+            return Err(DeltaError::BaseMismatch {
+                expected_size: self.base_size,
+                expected_crc: self.base_crc,
+                size: actual.base_size,
+                crc: actual.base_crc,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Apply a delta patch to the file it was made from. Patches without a header are rejected, since nothing tells which file they expect.
+pub fn apply(kind: DeltaKind, base: &[u8], patch: &[u8]) -> Result<Vec<u8>, DeltaError> {
+    let (header, payload) = DeltaHeader::read(patch)?;
+    header.check(base)?;
+
+    let patched = match kind {
+        DeltaKind::Bsdiff => {
+            let mut patched = Vec::new();
+            bsdiff::patch(base, &mut Cursor::new(payload), &mut patched).map_err(DeltaError::Bsdiff)?;
+            patched
+        },
+        DeltaKind::Xdelta => vcdiff::apply(base, payload)?,
+    };
+
+    Ok(patched)
+}
+
+/// Size of the file the patch builds, if the format records it. bsdiff patches don't.
+pub fn target_size(kind: DeltaKind, patch: &[u8]) -> Option<usize> {
+    let (_, payload) = DeltaHeader::read(patch).ok()?;
+
+    match kind {
+        DeltaKind::Bsdiff => None,
+        DeltaKind::Xdelta => vcdiff::target_size(payload).ok(),
+    }
+}
+
+/// Make a bsdiff patch that turns the base file into the modified one, with a header so it is only applied to that base
+pub fn make_bsdiff(base: &[u8], modified: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut patch = Vec::new();
+    DeltaHeader::new(base).write(&mut patch);
+    bsdiff::diff(base, modified, &mut patch)?;
+
+    Ok(patch)
+}
+
+/// Put a header in front of a patch made by another tool, like `xdelta3`, so it is only applied to the base file
+pub fn add_header(base: &[u8], patch: &[u8]) -> Vec<u8> {
+    let payload = DeltaHeader::read(patch).map_or(patch, |(_, payload)| payload);
+
+    let mut out = Vec::with_capacity(HEADER_SIZE + payload.len());
+    DeltaHeader::new(base).write(&mut out);
+    out.extend_from_slice(payload);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// VCDIFF delta that adds "abc" to whatever the source is, made by hand since it only has one instruction
+    const XDELTA_ADD_ABC: &[u8] = &[
+        0xD6, 0xC3, 0xC4, 0x00, 0x00, 0x00, 0x0A, 0x03, 0x00, 0x03, 0x02, 0x00, b'a', b'b', b'c', 0x01, 0x03,
+    ];
+
+    #[test]
+    fn header_round_trips() {
+        let mut patch = Vec::new();
+        DeltaHeader::new(b"base").write(&mut patch);
+        patch.extend_from_slice(b"payload");
+
+        let (header, payload) = DeltaHeader::read(&patch).unwrap();
+        assert_eq!(header, DeltaHeader::new(b"base"));
+        assert_eq!(payload, b"payload");
+    }
+
+    #[test]
+    fn patch_without_header_is_rejected() {
+        assert!(matches!(apply(DeltaKind::Xdelta, b"", XDELTA_ADD_ABC), Err(DeltaError::MissingHeader)));
+        assert!(matches!(apply(DeltaKind::Bsdiff, b"", b"BSDIFF40"), Err(DeltaError::MissingHeader)));
+    }
+
+    #[test]
+    fn unsupported_version_is_rejected() {
+        let mut patch = DELTA_MAGIC.to_vec();
+        patch.extend_from_slice(&2u32.to_le_bytes());
+        patch.extend_from_slice(&[0; 12]);
+
+        assert!(matches!(DeltaHeader::read(&patch), Err(DeltaError::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn xdelta_is_checked_against_its_base() {
+        let patch = add_header(b"base", XDELTA_ADD_ABC);
+
+        assert_eq!(apply(DeltaKind::Xdelta, b"base", &patch).unwrap(), b"abc");
+        assert_eq!(target_size(DeltaKind::Xdelta, &patch), Some(3));
+        assert!(matches!(apply(DeltaKind::Xdelta, b"other", &patch), Err(DeltaError::BaseMismatch { .. })));
+    }
+
+    #[test]
+    fn add_header_replaces_an_existing_one() {
+        let patch = add_header(b"other", &add_header(b"base", XDELTA_ADD_ABC));
+
+        assert_eq!(patch.len(), HEADER_SIZE + XDELTA_ADD_ABC.len());
+        assert_eq!(apply(DeltaKind::Xdelta, b"other", &patch).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn bsdiff_round_trips() {
+        let base = b"The quick brown fox jumps over the lazy dog".to_vec();
+        let modified = b"The quick brown cat jumps over the lazy dog!".to_vec();
+        let patch = make_bsdiff(&base, &modified).unwrap();
+
+        assert_eq!(apply(DeltaKind::Bsdiff, &base, &patch).unwrap(), modified);
+        assert!(matches!(
+            apply(DeltaKind::Bsdiff, &modified, &patch),
+            Err(DeltaError::BaseMismatch { .. })
+        ));
+    }
+}
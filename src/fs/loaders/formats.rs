@@ -0,0 +1,623 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use config::PrcMergePolicy;
+use hash40::diff::Diff;
+use nus3audio::Nus3audioFile;
+use serde_yaml::from_str;
+use smash_bgm_property::BgmPropertyFile;
+
+use super::{
+    apply_xmsbt_entry,
+    bgm_property_patch::{BgmPropertyMerge, BgmPropertyPatch},
+    delta::{self, DeltaKind},
+    motion_merge::{self, MotionChanges},
+    msbt_file::MsbtFile,
+    nus3audio_patch::{Nus3audioManifest, Nus3audioMerge},
+    nus3bank_patch::Nus3bankFile,
+    prc_merge::{self, PrcChanges},
+    xmsbt::{self, XmsbtDocument},
+    ApiLoader, ApiLoaderError,
+};
+use crate::{
+    fs::{patch_format::PatchFormat, patch_trace},
+    PathExtension,
+};
+
+/// The patch formats ARCropolis supports out of the box, in the order they are matched against file names
+pub fn builtin() -> Vec<Arc<dyn PatchFormat>> {
+    vec![
+        Arc::new(PrcFormat),
+        Arc::new(MsbtFormat),
+        Arc::new(Nus3audioFormat),
+        Arc::new(Nus3bankFormat),
+        Arc::new(MotionListFormat),
+        Arc::new(BgmPropertyFormat),
+        Arc::new(DeltaFormat(DeltaKind::Bsdiff)),
+        Arc::new(DeltaFormat(DeltaKind::Xdelta)),
+    ]
+}
+
+/// Size of the file with every patch added to it, an upper bound for formats whose patches hold at least as many bytes as they add to the file
+fn grown_by_patches(base_size: usize, patches: &[PathBuf]) -> Option<usize> {
+    patches
+        .iter()
+        .try_fold(base_size, |size, patch| Some(size + fs::metadata(patch).ok()?.len() as usize))
+}
+
+/// `.prcx`/`.prcxml` patches for param files, and their stage counterparts
+pub struct PrcFormat;
+
+impl PatchFormat for PrcFormat {
+    fn name(&self) -> &str {
+        "prc"
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        &["prcx", "prcxml", "stdatx", "stdatxml", "stprmx", "stprmxml"]
+    }
+
+    fn has_region_fallback(&self) -> bool {
+        true
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        if local.has_extension("prcx") || local.has_extension("prcxml") {
+            Some(local.with_extension("prc"))
+        } else if local.has_extension("stdatx") || local.has_extension("stdatxml") {
+            Some(local.with_extension("stdat"))
+        } else if local.has_extension("stprmx") || local.has_extension("stprmxml") {
+            Some(local.with_extension("stprm"))
+        } else {
+            None
+        }
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        let vanilla = ApiLoader::handle_load_vanilla_file(local)?;
+        let vanilla_data = prcx::read_stream(&mut Cursor::new(vanilla))
+            .map_err(|_| ApiLoaderError::Other("Unable to parse vanilla param data!".to_string()))?;
+
+        let data = ApiLoader::handle_load_base_file(local)?;
+        let mut param_data = prcx::read_stream(&mut Cursor::new(data))
+            .map_err(|_| ApiLoaderError::Other("Unable to parse param data!".to_string()))?;
+
+        // Diff every patch against vanilla on its own, so that params changed by several of them can be told apart
+        let row_keys: Vec<_> = config::prc_row_keys().iter().map(|key| prcx::hash40::hash40(key)).collect();
+        let mut changes = Vec::new();
+
+        for patch_path in patches.iter() {
+            let patch = if let Ok(patch) = prcx::open(patch_path) {
+                patch
+            } else {
+                let file = File::open(patch_path)?;
+                let mut reader = std::io::BufReader::new(file);
+
+                prcx::read_xml(&mut reader).map_err(|_| ApiLoaderError::Other("Unable to parse param patch data!".to_string()))?
+            };
+
+            let mut patched = vanilla_data.clone();
+            prcx::apply_patch(&patch, &mut patched).map_err(|_| ApiLoaderError::Other("Unable to patch param data!".to_string()))?;
+
+            let mut patch_changes = PrcChanges::new(patch_path.clone(), &vanilla_data, &patched, &row_keys);
+
+            // A patch can set a param back to vanilla over a mod's value, which only shows when it is applied to the mod's file
+            let mut patched_base = param_data.clone();
+            match prcx::apply_patch(&patch, &mut patched_base) {
+                Ok(_) => patch_changes.add_explicit(&param_data, &patched_base, &row_keys),
+                Err(_) => warn!(
+                    "Param patch '{}' can't be applied to the file '{}' is replaced with, the params it sets to their vanilla value are skipped.",
+                    patch_path.display(),
+                    local.display()
+                ),
+            }
+
+            for list in patch_changes.replaced_lists.iter() {
+                let message = format!(
+                    "Param patch '{}' removes rows of the list '{}' of '{}', the whole list is replaced.",
+                    patch_path.display(),
+                    list,
+                    local.display()
+                );
+                warn!("{}", message);
+                patch_trace::note(message);
+            }
+
+            changes.push(patch_changes);
+        }
+
+        let policy = config::prc_merge_policy();
+        let conflicts = prc_merge::find_conflicts(&changes);
+
+        for conflict in conflicts.iter() {
+            let values: Vec<String> = conflict
+                .values
+                .iter()
+                .map(|(patch_path, value)| format!("'{}' sets {:?}", patch_path.display(), value))
+                .collect();
+
+            let message = format!("Param '{}' of '{}' is changed by several patches: {}.", conflict.path, local.display(), values.join(", "));
+
+            match conflict.winner(policy) {
+                Some((winner, _)) => {
+                    warn!("{} The value from '{}' is used.", message, winner.display());
+                    patch_trace::note(format!("{} The value from '{}' is used.", message, winner.display()));
+                },
+                None => {
+                    error!("{}", message);
+                    patch_trace::note(message);
+                },
+            }
+        }
+
+        if policy == PrcMergePolicy::Error && !conflicts.is_empty() {
+            return Err(ApiLoaderError::Other(format!(
+                "{} params of '{}' are changed by several patches, none of them were applied.",
+                conflicts.len(),
+                local.display()
+            )));
+        }
+
+        prc_merge::apply_changes(&mut param_data, &changes, policy);
+
+        let mut writer = Cursor::new(Vec::new());
+        prcx::write_stream(&mut writer, &param_data)?;
+        let data = writer.into_inner();
+        Ok(data)
+    }
+
+    fn estimate_size(&self, base_size: usize, patches: &[PathBuf]) -> Option<usize> {
+        grown_by_patches(base_size, patches)
+    }
+}
+
+/// `.xmsbt` patches for text files
+pub struct MsbtFormat;
+
+impl PatchFormat for MsbtFormat {
+    fn name(&self) -> &str {
+        "msbt"
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        &["xmsbt"]
+    }
+
+    fn has_region_fallback(&self) -> bool {
+        true
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        Some(local.with_extension("msbt"))
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        let data = ApiLoader::handle_load_base_file(local)?;
+        let mut msbt = MsbtFile::read(&data).map_err(|err| ApiLoaderError::Other(format!("Unable to parse MSBT data! {}", err)))?;
+
+        let documents: Vec<(&Path, XmsbtDocument)> = patches.iter().map(|patch_path| (patch_path.as_path(), xmsbt::parse_file(patch_path))).collect();
+
+        for (patch_path, document) in documents.iter() {
+            for diagnostic in document.diagnostics.iter() {
+                match diagnostic.severity {
+                    xmsbt::Severity::Error => {
+                        error!("XMSBT file `{}`, {}", patch_path.display(), diagnostic);
+                        patch_trace::note(format!("XMSBT file `{}`, {}", patch_path.display(), diagnostic));
+                    },
+                    xmsbt::Severity::Warning => warn!("XMSBT file `{}`, {}", patch_path.display(), diagnostic),
+                }
+            }
+        }
+
+        for duplicate in xmsbt::find_duplicate_labels(documents.iter().map(|(path, document)| (*path, document))) {
+            let patches: Vec<String> = duplicate.patches.iter().map(|path| format!("`{}`", path.display())).collect();
+            let message = format!(
+                "XMSBT Label {} of '{}' is set by {}. The value from `{}` is used.",
+                duplicate.label,
+                local.display(),
+                patches.join(", "),
+                duplicate.winner().display()
+            );
+            warn!("{}", message);
+            patch_trace::note(message);
+        }
+
+        // Entries are applied in the order they are written, so a label can be renamed and then added again
+        for (patch_path, document) in documents.iter() {
+            for entry in document.entries.iter() {
+                apply_xmsbt_entry(&mut msbt, entry, patch_path);
+            }
+        }
+
+        let data = msbt.write().map_err(|err| ApiLoaderError::Other(format!("Unable to write MSBT data! {}", err)))?;
+        Ok(data)
+    }
+
+    /// Text in the patches can be UTF-8, which takes up to twice as many bytes once written as UTF-16
+    fn estimate_size(&self, base_size: usize, patches: &[PathBuf]) -> Option<usize> {
+        let patches_size = grown_by_patches(0, patches)?;
+
+        Some(base_size + patches_size * 2)
+    }
+}
+
+/// Apply the `.patch3audio` patches and `.patch3audio.yml` manifests in order, both the sounds and the removals or renames they declare.
+/// Any other patch is left for the formats that use them.
+fn merge_nus3audio(file: Nus3audioFile, patches: &[PathBuf]) -> Nus3audioMerge {
+    let mut merge = Nus3audioMerge::new(file);
+
+    for patch_path in patches.iter() {
+        if patch_path.has_extension("yml") {
+            let manifest = match fs::read_to_string(patch_path).map(|contents| serde_yaml::from_str::<Nus3audioManifest>(&contents)) {
+                Ok(Ok(manifest)) => manifest,
+                Ok(Err(err)) => {
+                    warn!("NUS3AUDIO manifest '{}' is malformed, skipping. Reason: {}", patch_path.display(), err);
+                    continue;
+                },
+                Err(err) => {
+                    warn!("NUS3AUDIO manifest '{}' could not be read, skipping. Reason: {}", patch_path.display(), err);
+                    continue;
+                },
+            };
+
+            merge.apply_manifest(manifest, patch_path);
+        } else if patch_path.has_extension("patch3audio") {
+            match fs::read(patch_path) {
+                Ok(patch_data) => merge.apply_patch(Nus3audioFile::from_bytes(&patch_data), patch_path),
+                Err(err) => warn!("NUS3AUDIO patch '{}' could not be read, skipping. Reason: {}", patch_path.display(), err),
+            }
+        }
+    }
+
+    merge
+}
+
+/// `.patch3audio` sound patches, and the `.patch3audio.yml` manifests for changes they can't express
+pub struct Nus3audioFormat;
+
+impl PatchFormat for Nus3audioFormat {
+    fn name(&self) -> &str {
+        "nus3audio"
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        &["patch3audio", "patch3audio.yml"]
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        if local.has_extension("yml") {
+            // Drop the .yml first, so that only .patch3audio is left to replace
+            Some(local.with_extension("").with_extension("nus3audio"))
+        } else {
+            Some(local.with_extension("nus3audio"))
+        }
+    }
+
+    fn companion(&self) -> Option<&str> {
+        Some("nus3bank-tones")
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        let data = ApiLoader::handle_load_base_file(local)?;
+        let merge = merge_nus3audio(Nus3audioFile::from_bytes(&data), patches);
+
+        let original_file = merge.into_file();
+
+        let mut contents: Vec<u8> = Vec::new();
+
+        // Write the contents of the original file to the contents vec
+        original_file.write(&mut contents);
+
+        // Return the length of the contents and the contents
+        Ok(contents)
+    }
+
+    fn estimate_size(&self, base_size: usize, patches: &[PathBuf]) -> Option<usize> {
+        grown_by_patches(base_size, patches)
+    }
+}
+
+/// `.patch3bank` tone patches for the NUS3BANK of a NUS3AUDIO, and the sound patches of that NUS3AUDIO.
+///
+/// A sound only plays if the bank has a tone for it, so the bank follows the sounds that the `.patch3audio` patches add or rename.
+/// The tones of a `.patch3bank` replace the metadata of the sounds they are named after, and are used for the sounds that are added.
+pub struct Nus3bankFormat;
+
+impl PatchFormat for Nus3bankFormat {
+    fn name(&self) -> &str {
+        "nus3bank-tones"
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        &["patch3bank"]
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        if local.has_extension("yml") {
+            Some(local.with_extension("").with_extension("nus3bank"))
+        } else {
+            Some(local.with_extension("nus3bank"))
+        }
+    }
+
+    fn dependencies(&self, local: &Path) -> Vec<PathBuf> {
+        vec![local.with_extension("nus3audio")]
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        // The sounds are loaded first, so that the trace reports the bank as the base file
+        let sound_data = ApiLoader::handle_load_base_file(&local.with_extension("nus3audio"))?;
+        let base_sounds = Nus3audioFile::from_bytes(&sound_data);
+        let sounds = merge_nus3audio(Nus3audioFile::from_bytes(&sound_data), patches).into_file();
+
+        let data = ApiLoader::handle_load_base_file(local)?;
+        let mut bank = Nus3bankFile::read(&data).map_err(|err| ApiLoaderError::Other(format!("{}", err)))?;
+
+        let mut tones = HashMap::new();
+
+        for patch_path in patches.iter().filter(|path| path.has_extension("patch3bank")) {
+            match fs::read(patch_path).map(|data| Nus3bankFile::read(&data)) {
+                Ok(Ok(patch)) => tones.extend(patch.into_named_tones()),
+                Ok(Err(err)) => warn!("NUS3BANK patch '{}' is malformed, skipping. Reason: {}", patch_path.display(), err),
+                Err(err) => warn!("NUS3BANK patch '{}' could not be read, skipping. Reason: {}", patch_path.display(), err),
+            }
+        }
+
+        for note in bank.apply_sounds(&base_sounds, &sounds, tones) {
+            warn!("NUS3BANK '{}': {}", local.display(), note);
+            patch_trace::note(note);
+        }
+
+        Ok(bank.write())
+    }
+}
+
+/// `.motdiff` patches and full `motion_list.yml` replacements for motion lists
+pub struct MotionListFormat;
+
+impl PatchFormat for MotionListFormat {
+    fn name(&self) -> &str {
+        "motionlist"
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        &["motdiff", "motion_list.yml"]
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        let name = local.file_name()?.to_str()?;
+
+        name.contains("motion_list").then(|| local.with_extension("bin"))
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        let mut yml_patches = Vec::new();
+        let mut diff_patches = Vec::new();
+
+        for patch_path in patches.iter() {
+            if patch_path.has_extension("motdiff") {
+                diff_patches.push(patch_path.clone());
+            } else if patch_path.ends_with("motion_list.yml") {
+                yml_patches.push(patch_path.clone());
+            } else {
+                return Err(ApiLoaderError::Other("This isn't a motion list patch file!".to_string()));
+            }
+        }
+
+        let data = ApiLoader::handle_load_base_file(local)?;
+        let mut reader = Cursor::new(data);
+        let mut motion_list = motion_lib::read_stream(&mut reader)?;
+
+        // A full motion list would silently drop the edits of every other mod, unless the user accepts it
+        let use_full_patches = diff_patches.is_empty() || config::motion_list_replace_with_diffs();
+
+        if !yml_patches.is_empty() && !use_full_patches {
+            for full_patch in yml_patches.iter() {
+                let message = format!(
+                    "'{}' replaces {} entirely while other mods patch it with .motdiff files, it is ignored.",
+                    full_patch.display(),
+                    local.display()
+                );
+                error!("{}", message);
+                patch_trace::note(message);
+            }
+        } else if !yml_patches.is_empty() {
+            let mut full_patches = 0;
+
+            for full_patch in yml_patches.iter() {
+                debug!("Replacing {} with '{}'.", local.display(), full_patch.display());
+                let mut contents: String = String::default();
+                File::open(full_patch)?.read_to_string(&mut contents)?;
+                if let Some(full) = from_str(&contents)? {
+                    motion_list = full;
+                    full_patches += 1;
+                }
+            }
+
+            if full_patches > 1 {
+                let message = format!(
+                    "{} motion list files replace {} entirely, the last one, '{}', is used.",
+                    full_patches,
+                    local.display(),
+                    yml_patches.last().unwrap().display()
+                );
+                warn!("{}", message);
+                patch_trace::note(message);
+            }
+        }
+
+        if !diff_patches.is_empty() {
+            // Every diff is applied to the same motion list on its own, so the fields each of them changes can be merged
+            let base = serde_yaml::to_value(&motion_list)?;
+            let mut changes = Vec::new();
+
+            for patch_path in diff_patches.iter() {
+                let mut contents: String = String::default();
+                File::open(patch_path)?.read_to_string(&mut contents)?;
+                if let Some(diff) = from_str(&contents)? {
+                    let mut patched = motion_list.clone();
+                    patched.apply(&diff);
+                    changes.push(MotionChanges::new(patch_path.clone(), &base, &serde_yaml::to_value(&patched)?));
+                }
+                else {
+                    return Err(ApiLoaderError::Other("This isn't a motion list patch file!".to_string()));
+                }
+            }
+
+            for conflict in motion_merge::find_conflicts(&changes) {
+                let patches: Vec<String> = conflict.patches.iter().map(|path| format!("'{}'", path.display())).collect();
+                let message = format!(
+                    "Motion list field '{}' of '{}' is changed by several patches: {}. The change from {} is used.",
+                    conflict.path,
+                    local.display(),
+                    patches.join(", "),
+                    patches.last().unwrap()
+                );
+                warn!("{}", message);
+                patch_trace::note(message);
+            }
+
+            let mut merged = base;
+            motion_merge::apply_changes(&mut merged, &changes);
+            motion_list = serde_yaml::from_value(merged)?;
+        }
+
+        let mut writer = Cursor::new(Vec::new());
+        motion_lib::write_stream(&mut writer, &motion_list)?;
+        let data = writer.into_inner();
+        Ok(data)
+    }
+
+    fn estimate_size(&self, base_size: usize, patches: &[PathBuf]) -> Option<usize> {
+        grown_by_patches(base_size, patches)
+    }
+}
+
+/// Binary and readable patches for `bgm_property.bin`
+pub struct BgmPropertyFormat;
+
+impl PatchFormat for BgmPropertyFormat {
+    fn name(&self) -> &str {
+        "bgm_property"
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        &["bgm_property.bin", "bgm_property.yml", "bgm_property.json"]
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        Some(local.with_extension("bin"))
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        let data = ApiLoader::handle_load_base_file(local)?;
+        let mut reader = Cursor::new(&data[..]);
+        let bgm_property =
+            BgmPropertyFile::read(&mut reader).map_err(|err| ApiLoaderError::Other(format!("Unable to parse bgm_property data! {}", err)))?;
+        let mut merge = BgmPropertyMerge::new(bgm_property);
+
+        // Readable patches and binary ones are applied in order, matching entries by their name
+        for patch_path in patches.iter() {
+            if patch_path.has_extension("bin") {
+                match BgmPropertyFile::from_file(patch_path) {
+                    Ok(patch_file) => merge.apply_file(patch_file, patch_path),
+                    Err(err) => warn!("bgm_property patch '{}' could not be read, skipping. Reason: {}", patch_path.display(), err),
+                }
+
+                continue;
+            }
+
+            let patch = fs::read_to_string(patch_path).map_err(|err| err.to_string()).and_then(|contents| {
+                if patch_path.has_extension("json") {
+                    serde_json::from_str::<BgmPropertyPatch>(&contents).map_err(|err| err.to_string())
+                } else {
+                    serde_yaml::from_str::<BgmPropertyPatch>(&contents).map_err(|err| err.to_string())
+                }
+            });
+
+            match patch {
+                Ok(patch) => merge.apply_patch(patch, patch_path),
+                Err(err) => warn!("bgm_property patch '{}' is malformed, skipping. Reason: {}", patch_path.display(), err),
+            }
+        }
+
+        let bgm_property = merge.into_file();
+
+        let mut writer = Cursor::new(Vec::new());
+        bgm_property.write(&mut writer)?;
+        let data = writer.into_inner();
+        Ok(data)
+    }
+
+    fn estimate_size(&self, base_size: usize, patches: &[PathBuf]) -> Option<usize> {
+        grown_by_patches(base_size, patches)
+    }
+}
+
+/// Binary delta patches for any file, named after the file they patch like `model.numatb.xdelta`
+pub struct DeltaFormat(pub DeltaKind);
+
+impl PatchFormat for DeltaFormat {
+    fn name(&self) -> &str {
+        match self.0 {
+            DeltaKind::Bsdiff => "bsdiff",
+            DeltaKind::Xdelta => "xdelta",
+        }
+    }
+
+    fn suffixes(&self) -> &[&str] {
+        match self.0 {
+            DeltaKind::Bsdiff => &["bsdiff"],
+            DeltaKind::Xdelta => &["xdelta"],
+        }
+    }
+
+    fn target_path(&self, local: &Path) -> Option<PathBuf> {
+        let target = local.with_extension("");
+
+        // The patch has to keep the extension of the file it patches
+        target.extension().is_some().then_some(target)
+    }
+
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError> {
+        let mut data = ApiLoader::handle_load_base_file(local)?;
+        let mut applied = 0;
+
+        // Every patch is checked against the file as the previous ones left it, so only patches made on top of each other can be stacked
+        for patch_path in patches.iter() {
+            let patch = fs::read(patch_path)?;
+
+            match delta::apply(self.0, &data, &patch) {
+                Ok(patched) => {
+                    data = patched;
+                    applied += 1;
+                },
+                Err(err) => {
+                    let message = format!("Delta patch '{}' can't be applied to '{}', skipping. Reason: {}", patch_path.display(), local.display(), err);
+                    error!("{}", message);
+                    patch_trace::note(message);
+                },
+            }
+        }
+
+        if applied == 0 {
+            return Err(ApiLoaderError::Other(format!("None of the delta patches for '{}' could be applied!", local.display())));
+        }
+
+        Ok(data)
+    }
+
+    /// Patches that can't be applied are skipped, so the file ends up with the size of the base or of one of the patched files
+    fn estimate_size(&self, base_size: usize, patches: &[PathBuf]) -> Option<usize> {
+        patches.iter().try_fold(base_size, |size, patch| {
+            let patch = fs::read(patch).ok()?;
+
+            Some(size.max(delta::target_size(self.0, &patch)?))
+        })
+    }
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldChange {
+    Set(Value),
+    /// The motion or field was removed by the patch
+    Remove,
+}
+
+/// Location of a field in a motion list, starting with the motion kind
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldPath(pub Vec<Value>);
+
+impl FieldPath {
+    fn starts_with(&self, other: &FieldPath) -> bool {
+        self.0.starts_with(&other.0)
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ".")?;
+            }
+
+            match key {
+                Value::String(key) => write!(f, "{}", key)?,
+                Value::Number(key) => write!(f, "{}", key)?,
+                key => write!(f, "{:?}", key)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Every field of the motion list a patch changes
+pub struct MotionChanges {
+    pub patch_path: PathBuf,
+    pub changes: Vec<(FieldPath, FieldChange)>,
+}
+
+impl MotionChanges {
+    /// Compare the motion list before and after applying the patch to it, both serialized the way `motion_list.yml` files are
+    pub fn new(patch_path: PathBuf, base: &Value, patched: &Value) -> Self {
+        let mut changes = Vec::new();
+        diff_value(base, patched, &mut Vec::new(), &mut changes);
+
+        Self { patch_path, changes }
+    }
+}
+
+fn diff_value(base: &Value, patched: &Value, path: &mut Vec<Value>, changes: &mut Vec<(FieldPath, FieldChange)>) {
+    match (base, patched) {
+        (Value::Mapping(base), Value::Mapping(patched)) => {
+            for (key, value) in patched.iter() {
+                path.push(key.clone());
+
+                match base.get(key) {
+                    Some(base_value) => diff_value(base_value, value, path, changes),
+                    None => changes.push((FieldPath(path.clone()), FieldChange::Set(value.clone()))),
+                }
+
+                path.pop();
+            }
+
+            for key in base.iter().map(|(key, _)| key).filter(|key| !patched.contains_key(key)) {
+                path.push(key.clone());
+                changes.push((FieldPath(path.clone()), FieldChange::Remove));
+                path.pop();
+            }
+        },
+        // Lists, like the animations of a motion, are handled as a single field
+        _ if base != patched => changes.push((FieldPath(path.clone()), FieldChange::Set(patched.clone()))),
+        _ => {},
+    }
+}
+
+/// A field that several patches change in different ways
+#[derive(Debug, Clone)]
+pub struct MotionConflict {
+    pub path: FieldPath,
+    /// Every patch that changes the field or a motion containing it, in load order. The last one wins.
+    pub patches: Vec<PathBuf>,
+}
+
+/// Find the fields that more than one patch changes differently, including fields of motions another patch removes
+pub fn find_conflicts(patches: &[MotionChanges]) -> Vec<MotionConflict> {
+    let mut changes: Vec<(FieldPath, Vec<(usize, &FieldChange)>)> = Vec::new();
+    let mut indices: HashMap<&FieldPath, usize> = HashMap::new();
+
+    for (patch_index, patch) in patches.iter().enumerate() {
+        for (path, change) in patch.changes.iter() {
+            match indices.get(path) {
+                Some(index) => changes[*index].1.push((patch_index, change)),
+                None => {
+                    indices.insert(path, changes.len());
+                    changes.push((path.clone(), vec![(patch_index, change)]));
+                },
+            }
+        }
+    }
+
+    let removals: Vec<(&FieldPath, usize)> = changes
+        .iter()
+        .flat_map(|(path, changes)| changes.iter().filter(|(_, change)| **change == FieldChange::Remove).map(move |(patch, _)| (path, *patch)))
+        .collect();
+
+    let mut conflicts = Vec::new();
+
+    for (path, changes) in changes.iter() {
+        let mut involved: Vec<usize> = if changes.iter().any(|(_, change)| *change != changes[0].1) {
+            changes.iter().map(|(patch, _)| *patch).collect()
+        } else {
+            Vec::new()
+        };
+
+        // Editing a field of a motion that another patch removes
+        for (removed, patch) in removals.iter() {
+            if path != *removed && path.starts_with(*removed) && changes.iter().any(|(other, _)| other != patch) {
+                involved.push(*patch);
+                involved.extend(changes.iter().map(|(patch, _)| *patch));
+            }
+        }
+
+        if !involved.is_empty() {
+            involved.sort_unstable();
+            involved.dedup();
+
+            conflicts.push(MotionConflict {
+                path: path.clone(),
+                patches: involved.into_iter().map(|patch| patches[patch].patch_path.clone()).collect(),
+            });
+        }
+    }
+
+    conflicts
+}
+
+/// Apply the changes of every patch in order on top of the serialized motion list
+pub fn apply_changes(base: &mut Value, patches: &[MotionChanges]) {
+    for patch in patches.iter() {
+        for (path, change) in patch.changes.iter() {
+            if !apply_change(base, &path.0, change) {
+                warn!("Motion list field '{}' from '{}' could not be applied, skipping.", path, patch.patch_path.display());
+            }
+        }
+    }
+}
+
+fn apply_change(value: &mut Value, path: &[Value], change: &FieldChange) -> bool {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+
+    let mapping: &mut Mapping = match value {
+        Value::Mapping(mapping) => mapping,
+        _ => return false,
+    };
+
+    if rest.is_empty() {
+        match change {
+            FieldChange::Set(new_value) => {
+                mapping.insert(key.clone(), new_value.clone());
+            },
+            FieldChange::Remove => {
+                mapping.remove(key);
+            },
+        }
+
+        return true;
+    }
+
+    match mapping.get_mut(key) {
+        Some(value) => apply_change(value, rest, change),
+        None => false,
+    }
+}
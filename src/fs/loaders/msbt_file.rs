@@ -0,0 +1,626 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MsbtError {
+    #[error("The file is not a MSBT.")]
+    InvalidMagic,
+
+    #[error("The file ends in the middle of the {0} section.")]
+    UnexpectedEof(&'static str),
+
+    #[error("Label '{0}' is longer than 255 bytes.")]
+    LabelTooLong(String),
+
+    #[error("The text of the file uses encoding {0}, only UTF-16 (1) is supported.")]
+    UnsupportedEncoding(u8),
+}
+
+/// Value of the encoding byte of the header for UTF-16, which every MSBT of the game uses
+const ENCODING_UTF16: u8 = 1;
+
+/// A label and everything the MSBT stores about it
+#[derive(Debug, Clone, Default)]
+pub struct MsbtEntry {
+    pub label: String,
+    /// Encoded text, including the null terminator
+    pub text: Vec<u8>,
+    /// Raw ATR1 entry, only written if the file has attributes
+    pub attribute: Option<Vec<u8>>,
+    /// TSY1 style index, only written if the file has styles
+    pub style: Option<u32>,
+}
+
+#[derive(Debug)]
+enum Section {
+    Labels,
+    Attributes,
+    Texts,
+    Styles,
+    /// Sections that are not edited, written back as they were read
+    Other([u8; 4], Vec<u8>),
+}
+
+/// A MSBT read into its labels, so that they can be edited and written back in their original order.
+///
+/// Only the sections that are tied to the labels are rebuilt, every other one is kept as-is.
+#[derive(Debug)]
+pub struct MsbtFile {
+    big_endian: bool,
+    header: [u8; 0x20],
+    /// Bucket count of the label hash table
+    label_slots: u32,
+    attribute_size: u32,
+    /// Amount of attribute entries the file was read with, which tells where `attribute_tail` started
+    attribute_count: usize,
+    /// Data found after the attribute entries, like the strings some attributes point to
+    attribute_tail: Vec<u8>,
+    /// Position in the attribute entries of the fields that point into `attribute_tail`, by offsets from the start of the section.
+    /// They are moved along with the tail when labels are added or removed.
+    attribute_offsets: Vec<usize>,
+    /// Position of every label in the LBL1 section, so labels sharing a slot are written back in the same order
+    label_order: HashMap<String, usize>,
+    sections: Vec<Section>,
+    pub entries: Vec<MsbtEntry>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+    section: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], MsbtError> {
+        self.data.get(offset..offset + len).ok_or(MsbtError::UnexpectedEof(self.section))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, MsbtError> {
+        Ok(self.read_u32(self.bytes(offset, 4)?))
+    }
+
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        let bytes: [u8; 4] = bytes.try_into().unwrap();
+
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl MsbtFile {
+    pub fn read(data: &[u8]) -> Result<Self, MsbtError> {
+        if data.len() < 0x20 || &data[..8] != b"MsgStdBn" {
+            return Err(MsbtError::InvalidMagic);
+        }
+
+        let big_endian = data[8..10] == [0xFE, 0xFF];
+
+        if data[0xC] != ENCODING_UTF16 {
+            return Err(MsbtError::UnsupportedEncoding(data[0xC]));
+        }
+
+        let header: [u8; 0x20] = data[..0x20].try_into().unwrap();
+
+        let section_count = if big_endian {
+            u16::from_be_bytes([data[0xE], data[0xF]])
+        } else {
+            u16::from_le_bytes([data[0xE], data[0xF]])
+        };
+
+        let mut file = MsbtFile {
+            big_endian,
+            header,
+            label_slots: 0,
+            attribute_size: 0,
+            attribute_count: 0,
+            attribute_tail: Vec::new(),
+            attribute_offsets: Vec::new(),
+            label_order: HashMap::new(),
+            sections: Vec::new(),
+            entries: Vec::new(),
+        };
+
+        let mut labels: Vec<(String, u32)> = Vec::new();
+        let mut attributes: Vec<Vec<u8>> = Vec::new();
+        let mut texts: Vec<Vec<u8>> = Vec::new();
+        let mut styles: Vec<u32> = Vec::new();
+
+        let mut offset = 0x20;
+
+        for _ in 0..section_count {
+            let header = Reader {
+                data,
+                big_endian,
+                section: "header",
+            };
+            let magic: [u8; 4] = header.bytes(offset, 4)?.try_into().unwrap();
+            let size = header.u32(offset + 4)? as usize;
+
+            let (section, name) = match &magic {
+                b"LBL1" => (Section::Labels, "LBL1"),
+                b"ATR1" => (Section::Attributes, "ATR1"),
+                b"TXT2" => (Section::Texts, "TXT2"),
+                b"TSY1" => (Section::Styles, "TSY1"),
+                _ => (Section::Other(magic, header.bytes(offset + 0x10, size)?.to_vec()), "unknown"),
+            };
+
+            let reader = Reader {
+                data: header.bytes(offset + 0x10, size)?,
+                big_endian,
+                section: name,
+            };
+
+            match section {
+                Section::Labels => {
+                    file.label_slots = reader.u32(0)?;
+
+                    for slot in 0..file.label_slots as usize {
+                        let count = reader.u32(4 + slot * 8)?;
+                        let mut label_offset = reader.u32(8 + slot * 8)? as usize;
+
+                        for _ in 0..count {
+                            let len = reader.bytes(label_offset, 1)?[0] as usize;
+                            let name = String::from_utf8_lossy(reader.bytes(label_offset + 1, len)?).into_owned();
+                            let index = reader.u32(label_offset + 1 + len)?;
+
+                            labels.push((name, index));
+                            label_offset += 1 + len + 4;
+                        }
+                    }
+                },
+                Section::Attributes => {
+                    let count = reader.u32(0)? as usize;
+                    file.attribute_size = reader.u32(4)?;
+
+                    let entries_end = 8 + count * file.attribute_size as usize;
+
+                    for i in 0..count {
+                        attributes.push(reader.bytes(8 + i * file.attribute_size as usize, file.attribute_size as usize)?.to_vec());
+                    }
+
+                    file.attribute_count = count;
+                    file.attribute_tail = reader.data.get(entries_end..).unwrap_or_default().to_vec();
+
+                    // A field points into the tail if every entry leaves it empty or gives it an offset inside the tail
+                    let tail = entries_end..size;
+                    file.attribute_offsets = (0..(file.attribute_size as usize).saturating_sub(3))
+                        .step_by(4)
+                        .filter(|field| {
+                            let values: Vec<usize> = attributes
+                                .iter()
+                                .map(|attribute| reader.read_u32(&attribute[*field..*field + 4]) as usize)
+                                .collect();
+                            !tail.is_empty()
+                                && values.iter().any(|value| tail.contains(value))
+                                && values.iter().all(|value| *value == 0 || tail.contains(value))
+                        })
+                        .collect();
+                },
+                Section::Texts => {
+                    let count = reader.u32(0)? as usize;
+
+                    for i in 0..count {
+                        let start = reader.u32(4 + i * 4)? as usize;
+                        let end = if i + 1 < count { reader.u32(8 + i * 4)? as usize } else { size };
+
+                        texts.push(reader.bytes(start, end.saturating_sub(start))?.to_vec());
+                    }
+                },
+                Section::Styles => {
+                    for i in 0..size / 4 {
+                        styles.push(reader.u32(i * 4)?);
+                    }
+                },
+                Section::Other(..) => {},
+            }
+
+            file.sections.push(section);
+
+            // Sections are aligned to 16 bytes
+            offset = (offset + 0x10 + size + 0xF) & !0xF;
+        }
+
+        let has_attributes = file.sections.iter().any(|section| matches!(section, Section::Attributes));
+        let has_styles = file.sections.iter().any(|section| matches!(section, Section::Styles));
+
+        file.label_order = labels.iter().enumerate().map(|(order, (name, _))| (name.clone(), order)).collect();
+
+        let mut names: HashMap<u32, String> = labels.into_iter().map(|(name, index)| (index, name)).collect();
+
+        file.entries = texts
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| MsbtEntry {
+                label: names.remove(&(index as u32)).unwrap_or_default(),
+                text,
+                attribute: has_attributes.then(|| attributes.get(index).cloned().unwrap_or_default()),
+                style: has_styles.then(|| styles.get(index).copied().unwrap_or_default()),
+            })
+            .collect();
+
+        Ok(file)
+    }
+
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    pub fn has_attributes(&self) -> bool {
+        self.sections.iter().any(|section| matches!(section, Section::Attributes))
+    }
+
+    /// Size every attribute entry has to be
+    pub fn attribute_size(&self) -> usize {
+        self.attribute_size as usize
+    }
+
+    pub fn has_styles(&self) -> bool {
+        self.sections.iter().any(|section| matches!(section, Section::Styles))
+    }
+
+    pub fn position(&self, label: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.label == label)
+    }
+
+    pub fn get_mut(&mut self, label: &str) -> Option<&mut MsbtEntry> {
+        self.entries.iter_mut().find(|entry| entry.label == label)
+    }
+
+    /// Whether the attributes point to data stored after them, in which case the zeroed attributes of an added label point nowhere
+    pub fn has_attribute_offsets(&self) -> bool {
+        !self.attribute_offsets.is_empty()
+    }
+
+    /// Get the entry for a label, adding it after every other one with zeroed attributes if it doesn't exist yet
+    pub fn get_or_insert(&mut self, label: &str) -> &mut MsbtEntry {
+        let index = match self.position(label) {
+            Some(index) => index,
+            None => {
+                let attribute = self.has_attributes().then(|| vec![0; self.attribute_size()]);
+                let style = self.has_styles().then_some(0);
+
+                self.entries.push(MsbtEntry {
+                    label: label.to_owned(),
+                    // Empty text, only made of the null terminator
+                    text: vec![0, 0],
+                    attribute,
+                    style,
+                });
+
+                self.entries.len() - 1
+            },
+        };
+
+        &mut self.entries[index]
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<MsbtEntry> {
+        let index = self.position(label)?;
+
+        Some(self.entries.remove(index))
+    }
+
+    pub fn write(&self) -> Result<Vec<u8>, MsbtError> {
+        let mut out = self.header.to_vec();
+
+        for section in self.sections.iter() {
+            let (magic, data) = match section {
+                Section::Labels => (*b"LBL1", self.write_labels()?),
+                Section::Attributes => (*b"ATR1", self.write_attributes()),
+                Section::Texts => (*b"TXT2", self.write_texts()),
+                Section::Styles => (
+                    *b"TSY1",
+                    self.entries.iter().flat_map(|entry| self.u32(entry.style.unwrap_or_default())).collect(),
+                ),
+                Section::Other(magic, data) => (*magic, data.clone()),
+            };
+
+            out.extend_from_slice(&magic);
+            out.extend_from_slice(&self.u32(data.len() as u32));
+            out.extend_from_slice(&[0; 8]);
+            out.extend_from_slice(&data);
+
+            while out.len() % 0x10 != 0 {
+                out.push(0xAB);
+            }
+        }
+
+        let file_size = self.u32(out.len() as u32);
+        out[0x12..0x16].copy_from_slice(&file_size);
+
+        Ok(out)
+    }
+
+    fn u32(&self, value: u32) -> [u8; 4] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    fn write_labels(&self) -> Result<Vec<u8>, MsbtError> {
+        let slots = self.label_slots.max(1);
+        let mut buckets: Vec<Vec<(&str, u32)>> = vec![Vec::new(); slots as usize];
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if entry.label.is_empty() {
+                continue;
+            }
+
+            if entry.label.len() > u8::MAX as usize {
+                return Err(MsbtError::LabelTooLong(entry.label.clone()));
+            }
+
+            let hash = entry.label.bytes().fold(0u32, |hash, c| hash.wrapping_mul(0x492).wrapping_add(c as u32));
+            buckets[(hash % slots) as usize].push((&entry.label, index as u32));
+        }
+
+        // Labels that weren't in the file go after the others of their slot
+        for bucket in buckets.iter_mut() {
+            bucket.sort_by_key(|(label, _)| self.label_order.get(*label).copied().unwrap_or(usize::MAX));
+        }
+
+        let mut table = self.u32(slots).to_vec();
+        let mut labels = Vec::new();
+        let labels_start = 4 + slots as usize * 8;
+
+        for bucket in buckets.iter() {
+            table.extend_from_slice(&self.u32(bucket.len() as u32));
+            table.extend_from_slice(&self.u32((labels_start + labels.len()) as u32));
+
+            for (label, index) in bucket.iter() {
+                labels.push(label.len() as u8);
+                labels.extend_from_slice(label.as_bytes());
+                labels.extend_from_slice(&self.u32(*index));
+            }
+        }
+
+        table.extend(labels);
+
+        Ok(table)
+    }
+
+    fn write_attributes(&self) -> Vec<u8> {
+        let mut data = self.u32(self.entries.len() as u32).to_vec();
+        data.extend_from_slice(&self.u32(self.attribute_size));
+
+        // The tail moves by one entry for every label added or removed
+        let old_tail = 8 + self.attribute_count * self.attribute_size()..8 + self.attribute_count * self.attribute_size() + self.attribute_tail.len();
+        let new_tail_start = 8 + self.entries.len() * self.attribute_size();
+
+        for entry in self.entries.iter() {
+            let mut attribute = entry.attribute.clone().unwrap_or_default();
+            attribute.resize(self.attribute_size(), 0);
+
+            for field in self.attribute_offsets.iter() {
+                let bytes: [u8; 4] = attribute[*field..*field + 4].try_into().unwrap();
+                let offset = if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) } as usize;
+
+                if old_tail.contains(&offset) {
+                    attribute[*field..*field + 4].copy_from_slice(&self.u32((offset - old_tail.start + new_tail_start) as u32));
+                }
+            }
+
+            data.extend(attribute);
+        }
+
+        data.extend_from_slice(&self.attribute_tail);
+
+        data
+    }
+
+    fn write_texts(&self) -> Vec<u8> {
+        let mut data = self.u32(self.entries.len() as u32).to_vec();
+        let mut texts = Vec::new();
+        let texts_start = 4 + self.entries.len() * 4;
+
+        for entry in self.entries.iter() {
+            data.extend_from_slice(&self.u32((texts_start + texts.len()) as u32));
+            texts.extend_from_slice(&entry.text);
+        }
+
+        data.extend(texts);
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds MSBT files section by section, the way the game's are laid out
+    struct Builder {
+        big_endian: bool,
+        sections: Vec<u8>,
+        section_count: u16,
+    }
+
+    impl Builder {
+        fn new(big_endian: bool) -> Self {
+            Self {
+                big_endian,
+                sections: Vec::new(),
+                section_count: 0,
+            }
+        }
+
+        fn u32(&self, value: u32) -> [u8; 4] {
+            if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        }
+
+        fn utf16(&self, text: &str) -> Vec<u8> {
+            text.encode_utf16()
+                .chain(std::iter::once(0))
+                .flat_map(|c| if self.big_endian { c.to_be_bytes() } else { c.to_le_bytes() })
+                .collect()
+        }
+
+        fn section(&mut self, magic: &[u8; 4], data: &[u8]) {
+            self.sections.extend_from_slice(magic);
+            self.sections.extend_from_slice(&self.u32(data.len() as u32));
+            self.sections.extend_from_slice(&[0; 8]);
+            self.sections.extend_from_slice(data);
+
+            while self.sections.len() % 0x10 != 0 {
+                self.sections.push(0xAB);
+            }
+
+            self.section_count += 1;
+        }
+
+        /// Labels are given in the order they are stored in their slot, with the index of their text
+        fn labels(&mut self, slots: u32, labels: &[(&str, u32)]) {
+            let mut data = self.u32(slots).to_vec();
+            let mut names = Vec::new();
+            let start = 4 + slots as usize * 8;
+
+            for slot in 0..slots {
+                let in_slot: Vec<&(&str, u32)> = labels
+                    .iter()
+                    .filter(|(label, _)| label.bytes().fold(0u32, |hash, c| hash.wrapping_mul(0x492).wrapping_add(c as u32)) % slots == slot)
+                    .collect();
+
+                data.extend_from_slice(&self.u32(in_slot.len() as u32));
+                data.extend_from_slice(&self.u32((start + names.len()) as u32));
+
+                for (label, index) in in_slot {
+                    names.push(label.len() as u8);
+                    names.extend_from_slice(label.as_bytes());
+                    names.extend_from_slice(&self.u32(*index));
+                }
+            }
+
+            data.extend(names);
+            self.section(b"LBL1", &data);
+        }
+
+        fn texts(&mut self, texts: &[&str]) {
+            let mut data = self.u32(texts.len() as u32).to_vec();
+            let mut encoded = Vec::new();
+
+            for text in texts {
+                data.extend_from_slice(&self.u32((4 + texts.len() * 4 + encoded.len()) as u32));
+                encoded.extend(self.utf16(text));
+            }
+
+            data.extend(encoded);
+            self.section(b"TXT2", &data);
+        }
+
+        fn build(&self, encoding: u8) -> Vec<u8> {
+            let mut data = b"MsgStdBn".to_vec();
+            data.extend_from_slice(if self.big_endian { &[0xFE, 0xFF] } else { &[0xFF, 0xFE] });
+            data.extend_from_slice(&[0, 0, encoding, 3]);
+            data.extend_from_slice(&if self.big_endian { self.section_count.to_be_bytes() } else { self.section_count.to_le_bytes() });
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(&self.u32((0x20 + self.sections.len()) as u32));
+            data.extend_from_slice(&[0; 10]);
+            data.extend_from_slice(&self.sections);
+
+            data
+        }
+    }
+
+    /// A file with every section, where the first field of the attributes points to a string after them
+    fn fixture(big_endian: bool, encoding: u8) -> Vec<u8> {
+        let mut builder = Builder::new(big_endian);
+
+        // Neither the slots nor the labels sharing one are in text order
+        builder.labels(3, &[("Title", 0), ("Zeta", 3), ("Body", 1), ("Alpha", 2)]);
+
+        let strings = [builder.utf16("a"), builder.utf16("b")].concat();
+        let mut attributes = builder.u32(4).to_vec();
+        attributes.extend_from_slice(&builder.u32(8));
+        for (offset, flags) in [(40, 7), (0, 7), (44, 7), (40, 7)] {
+            attributes.extend_from_slice(&builder.u32(offset));
+            attributes.extend_from_slice(&builder.u32(flags));
+        }
+        attributes.extend(strings);
+        builder.section(b"ATR1", &attributes);
+
+        builder.texts(&["Hello", "World", "", "Last"]);
+
+        let styles: Vec<u8> = [0, 1, 2, 3].iter().flat_map(|style| builder.u32(*style)).collect();
+        builder.section(b"TSY1", &styles);
+        builder.section(b"NLI1", &[1, 2, 3, 4, 5]);
+
+        builder.build(encoding)
+    }
+
+    fn attribute_offset(msbt: &MsbtFile, label: &str) -> u32 {
+        let attribute = msbt.entries[msbt.position(label).unwrap()].attribute.clone().unwrap();
+        let bytes: [u8; 4] = attribute[..4].try_into().unwrap();
+
+        u32::from_le_bytes(bytes)
+    }
+
+    #[test]
+    fn unedited_file_round_trips() {
+        for big_endian in [false, true] {
+            let data = fixture(big_endian, ENCODING_UTF16);
+            let msbt = MsbtFile::read(&data).unwrap();
+
+            assert_eq!(msbt.entries.len(), 4);
+            assert_eq!(msbt.write().unwrap(), data, "big endian: {}", big_endian);
+        }
+    }
+
+    #[test]
+    fn entries_are_read_by_text_index() {
+        let msbt = MsbtFile::read(&fixture(false, ENCODING_UTF16)).unwrap();
+        let labels: Vec<&str> = msbt.entries.iter().map(|entry| entry.label.as_str()).collect();
+
+        assert_eq!(labels, ["Title", "Body", "Alpha", "Zeta"]);
+        assert_eq!(msbt.entries[3].style, Some(3));
+        assert_eq!(msbt.entries[1].text, [b'W', 0, b'o', 0, b'r', 0, b'l', 0, b'd', 0, 0, 0]);
+        assert!(msbt.has_attribute_offsets());
+    }
+
+    #[test]
+    fn other_encodings_are_rejected() {
+        assert!(matches!(MsbtFile::read(&fixture(false, 0)), Err(MsbtError::UnsupportedEncoding(0))));
+        assert!(matches!(MsbtFile::read(&fixture(false, 2)), Err(MsbtError::UnsupportedEncoding(2))));
+    }
+
+    #[test]
+    fn adding_a_label_moves_the_attribute_offsets() {
+        let mut msbt = MsbtFile::read(&fixture(false, ENCODING_UTF16)).unwrap();
+        msbt.get_or_insert("New").text = vec![b'!', 0, 0, 0];
+
+        let written = MsbtFile::read(&msbt.write().unwrap()).unwrap();
+
+        assert_eq!(written.entries.len(), 5);
+        assert_eq!(written.entries[4].label, "New");
+        assert_eq!(written.entries[4].attribute.as_deref(), Some(&[0; 8][..]));
+        assert_eq!(written.entries[4].style, Some(0));
+        assert_eq!(attribute_offset(&written, "Title"), 48);
+        assert_eq!(attribute_offset(&written, "Body"), 0);
+        assert_eq!(attribute_offset(&written, "Alpha"), 52);
+        assert_eq!(written.attribute_tail, msbt.attribute_tail);
+    }
+
+    #[test]
+    fn removing_a_label_moves_the_attribute_offsets() {
+        let mut msbt = MsbtFile::read(&fixture(false, ENCODING_UTF16)).unwrap();
+        assert!(msbt.remove("Title").is_some());
+        assert!(msbt.remove("Missing").is_none());
+
+        let written = MsbtFile::read(&msbt.write().unwrap()).unwrap();
+        let labels: Vec<&str> = written.entries.iter().map(|entry| entry.label.as_str()).collect();
+
+        assert_eq!(labels, ["Body", "Alpha", "Zeta"]);
+        assert_eq!(attribute_offset(&written, "Alpha"), 36);
+        assert_eq!(attribute_offset(&written, "Zeta"), 32);
+        assert_eq!(written.entries[0].text, [b'W', 0, b'o', 0, b'r', 0, b'l', 0, b'd', 0, 0, 0]);
+    }
+}
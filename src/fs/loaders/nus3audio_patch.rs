@@ -0,0 +1,208 @@
+use std::{collections::HashMap, path::Path};
+
+use nus3audio::{AudioFile, Nus3audioFile};
+use serde::Deserialize;
+
+/// Changes to the sounds of a NUS3AUDIO that can't be expressed with a `.patch3audio`, written in a `.patch3audio.yml`
+#[derive(Debug, Default, Deserialize)]
+pub struct Nus3audioManifest {
+    /// Names of the sounds to remove
+    #[serde(default)]
+    pub remove: Vec<String>,
+    /// Sounds to rename, keeping their audio and id
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+}
+
+/// The sounds of a NUS3AUDIO, with patches applied in order.
+///
+/// The game finds sounds by their position, which matches their id, so ids are never moved or reused:
+/// sounds that replace existing ones keep their id, added sounds get the next free one and removed sounds keep their slot without any audio.
+pub struct Nus3audioMerge {
+    file: Nus3audioFile,
+}
+
+impl Nus3audioMerge {
+    pub fn new(mut file: Nus3audioFile) -> Self {
+        file.files.sort_by_key(|file| file.id);
+
+        Self { file }
+    }
+
+    fn next_id(&self) -> u32 {
+        self.file.files.iter().map(|file| file.id + 1).max().unwrap_or(0)
+    }
+
+    pub fn apply_patch(&mut self, patch: Nus3audioFile, patch_path: &Path) {
+        for audio_file in patch.files {
+            match self.file.files.iter_mut().find(|file| file.name == audio_file.name) {
+                Some(_) if audio_file.data.is_empty() => {
+                    info!("Sound {} in '{}' has no audio, only its tone is replaced if a .patch3bank has one.", audio_file.name, patch_path.display());
+                },
+                Some(file) => {
+                    debug!("Replacing sound {} with the one in '{}'.", file.name, patch_path.display());
+                    file.data = audio_file.data;
+                },
+                None if audio_file.data.is_empty() => {
+                    warn!("Sound {} in '{}' has no audio and doesn't exist yet, skipping.", audio_file.name, patch_path.display());
+                },
+                None => {
+                    let id = self.next_id();
+                    debug!("Adding sound {} from '{}' with id {}.", audio_file.name, patch_path.display(), id);
+                    self.file.files.push(AudioFile {
+                        id,
+                        name: audio_file.name,
+                        data: audio_file.data,
+                    });
+                },
+            }
+        }
+    }
+
+    pub fn apply_manifest(&mut self, manifest: Nus3audioManifest, patch_path: &Path) {
+        for name in manifest.remove.iter() {
+            match self.file.files.iter_mut().find(|file| file.name == *name) {
+                Some(file) => file.data.clear(),
+                None => warn!("'{}' removes sound {}, which does not exist.", patch_path.display(), name),
+            }
+        }
+
+        for (name, new_name) in manifest.rename.iter() {
+            if self.file.files.iter().any(|file| file.name == *new_name) {
+                warn!("'{}' renames sound {} to {}, which already exists, skipping.", patch_path.display(), name, new_name);
+                continue;
+            }
+
+            match self.file.files.iter_mut().find(|file| file.name == *name) {
+                Some(file) => file.name = new_name.clone(),
+                None => warn!("'{}' renames sound {}, which does not exist.", patch_path.display(), name),
+            }
+        }
+    }
+
+    pub fn into_file(self) -> Nus3audioFile {
+        self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sound(id: u32, name: &str, data: &[u8]) -> AudioFile {
+        AudioFile {
+            id,
+            name: name.to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    fn merge(files: Vec<AudioFile>) -> Nus3audioMerge {
+        Nus3audioMerge::new(Nus3audioFile { files })
+    }
+
+    fn contents(merge: Nus3audioMerge) -> Vec<(u32, String, Vec<u8>)> {
+        merge.into_file().files.into_iter().map(|file| (file.id, file.name, file.data)).collect()
+    }
+
+    #[test]
+    fn replaced_sounds_keep_their_id() {
+        let mut merge = merge(vec![sound(1, "se_b", b"b"), sound(0, "se_a", b"a")]);
+        merge.apply_patch(
+            Nus3audioFile {
+                files: vec![sound(7, "se_b", b"new")],
+            },
+            Path::new("patch"),
+        );
+
+        assert_eq!(
+            contents(merge),
+            vec![(0, "se_a".to_string(), b"a".to_vec()), (1, "se_b".to_string(), b"new".to_vec())]
+        );
+    }
+
+    #[test]
+    fn added_sounds_get_the_next_free_id() {
+        let mut merge = merge(vec![sound(0, "se_a", b"a"), sound(3, "se_d", b"d")]);
+        merge.apply_patch(
+            Nus3audioFile {
+                files: vec![sound(0, "se_e", b"e"), sound(0, "se_f", b"f")],
+            },
+            Path::new("patch"),
+        );
+
+        let ids: Vec<_> = contents(merge).into_iter().map(|(id, name, _)| (id, name)).collect();
+        assert_eq!(
+            ids,
+            vec![
+                (0, "se_a".to_string()),
+                (3, "se_d".to_string()),
+                (4, "se_e".to_string()),
+                (5, "se_f".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn sounds_without_audio_change_nothing() {
+        let mut merge = merge(vec![sound(0, "se_a", b"a")]);
+        merge.apply_patch(
+            Nus3audioFile {
+                files: vec![sound(0, "se_a", b""), sound(1, "se_b", b"")],
+            },
+            Path::new("patch"),
+        );
+
+        assert_eq!(contents(merge), vec![(0, "se_a".to_string(), b"a".to_vec())]);
+    }
+
+    #[test]
+    fn removed_sounds_keep_their_slot() {
+        let mut merge = merge(vec![sound(0, "se_a", b"a"), sound(1, "se_b", b"b")]);
+        merge.apply_manifest(
+            Nus3audioManifest {
+                remove: vec!["se_b".to_string(), "se_missing".to_string()],
+                ..Default::default()
+            },
+            Path::new("patch"),
+        );
+        merge.apply_patch(
+            Nus3audioFile {
+                files: vec![sound(0, "se_c", b"c")],
+            },
+            Path::new("patch"),
+        );
+
+        assert_eq!(
+            contents(merge),
+            vec![
+                (0, "se_a".to_string(), b"a".to_vec()),
+                (1, "se_b".to_string(), Vec::new()),
+                (2, "se_c".to_string(), b"c".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_keep_the_audio_and_skip_taken_names() {
+        let mut merge = merge(vec![sound(0, "se_a", b"a"), sound(1, "se_b", b"b")]);
+        merge.apply_manifest(
+            Nus3audioManifest {
+                rename: HashMap::from([
+                    ("se_a".to_string(), "se_z".to_string()),
+                    ("se_b".to_string(), "se_z".to_string()),
+                    ("se_missing".to_string(), "se_y".to_string()),
+                ]),
+                ..Default::default()
+            },
+            Path::new("patch"),
+        );
+
+        let names: Vec<_> = contents(merge).into_iter().map(|(_, name, data)| (name, data)).collect();
+        // Only one of se_a and se_b can take the name, whichever is renamed first
+        assert!(
+            names == vec![("se_z".to_string(), b"a".to_vec()), ("se_b".to_string(), b"b".to_vec())]
+                || names == vec![("se_a".to_string(), b"a".to_vec()), ("se_z".to_string(), b"b".to_vec())]
+        );
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+
+use nus3audio::Nus3audioFile;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Nus3bankError {
+    #[error("The file is not a NUS3BANK.")]
+    InvalidMagic,
+
+    #[error("The file ends in the middle of the {0} section.")]
+    UnexpectedEof(&'static str),
+
+    #[error("The NUS3BANK has no TONE section.")]
+    NoTones,
+
+    #[error("Sound name '{0}' is longer than 254 bytes.")]
+    NameTooLong(String),
+}
+
+/// Tone entries this small hold no sound, they keep the slot of a sound that was removed from the bank
+const EMPTY_TONE_SIZE: usize = 0xC;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    section: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Nus3bankError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(Nus3bankError::UnexpectedEof(self.section))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Nus3bankError> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into().unwrap()))
+    }
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// The metadata the bank keeps about a sound. The game plays the sound of the NUS3AUDIO whose id is the position of the tone.
+#[derive(Debug, Clone)]
+pub struct Tone {
+    data: Vec<u8>,
+}
+
+impl Tone {
+    pub fn is_empty(&self) -> bool {
+        self.data.len() <= EMPTY_TONE_SIZE
+    }
+
+    /// Offset of the byte holding the length of the name, which follows a header that is 4 bytes longer when the lowest flag is set
+    fn name_offset(&self) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(if self.data[2] & 1 != 0 { 0xA } else { 0x6 })
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        let offset = self.name_offset()?;
+        let len = *self.data.get(offset)? as usize;
+        let name = self.data.get(offset + 1..offset + 1 + len)?;
+
+        // The length counts the null terminator
+        std::str::from_utf8(name.strip_suffix(&[0]).unwrap_or(name)).ok()
+    }
+
+    /// Replace the name, keeping the parameters that follow it 4-byte aligned like the rest of the entry
+    pub fn set_name(&mut self, name: &str) -> Result<(), Nus3bankError> {
+        let offset = self.name_offset().ok_or(Nus3bankError::UnexpectedEof("TONE"))?;
+
+        if name.len() + 1 > u8::MAX as usize {
+            return Err(Nus3bankError::NameTooLong(name.to_string()));
+        }
+
+        let old_len = *self.data.get(offset).ok_or(Nus3bankError::UnexpectedEof("TONE"))? as usize;
+        let old_end = align4(offset + 1 + old_len).min(self.data.len());
+
+        let mut data = self.data[..offset].to_vec();
+        data.push((name.len() + 1) as u8);
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        data.resize(align4(data.len()), 0);
+        data.extend_from_slice(&self.data[old_end..]);
+
+        self.data = data;
+
+        Ok(())
+    }
+}
+
+/// A NUS3BANK read into its sections, with the TONE section split into its entries so that sounds can be added to it.
+///
+/// Every other section is written back as-is. The ones following TONE only move, they are not pointed to by offsets from the start of the file.
+#[derive(Debug)]
+pub struct Nus3bankFile {
+    /// Data between the section list of the table of contents and the first section
+    toc_padding: Vec<u8>,
+    sections: Vec<([u8; 4], Vec<u8>)>,
+    /// Data found after the last section
+    tail: Vec<u8>,
+    pub tones: Vec<Tone>,
+}
+
+impl Nus3bankFile {
+    pub fn read(data: &[u8]) -> Result<Self, Nus3bankError> {
+        if data.len() < 0x18 || &data[..4] != b"NUS3" || &data[8..16] != b"BANKTOC " {
+            return Err(Nus3bankError::InvalidMagic);
+        }
+
+        let header = Reader { data, section: "BANKTOC" };
+        let toc_size = header.u32(0x10)? as usize;
+        let section_count = header.u32(0x14)? as usize;
+
+        let toc_end = 0x18 + section_count * 8;
+        let data_start = 0x14 + toc_size;
+
+        if toc_end > data_start {
+            return Err(Nus3bankError::UnexpectedEof("BANKTOC"));
+        }
+
+        let mut file = Nus3bankFile {
+            toc_padding: header.bytes(toc_end, data_start - toc_end)?.to_vec(),
+            sections: Vec::with_capacity(section_count),
+            tail: Vec::new(),
+            tones: Vec::new(),
+        };
+
+        let mut offset = data_start;
+        let mut has_tones = false;
+
+        for i in 0..section_count {
+            let magic: [u8; 4] = header.bytes(0x18 + i * 8, 4)?.try_into().unwrap();
+            let size = header.u32(0x18 + i * 8 + 4)? as usize;
+
+            let body = Reader { data, section: "section" }.bytes(offset + 8, size)?;
+
+            if &magic == b"TONE" {
+                file.tones = Self::read_tones(body)?;
+                has_tones = true;
+                file.sections.push((magic, Vec::new()));
+            } else {
+                file.sections.push((magic, body.to_vec()));
+            }
+
+            offset += 8 + size;
+        }
+
+        if !has_tones {
+            return Err(Nus3bankError::NoTones);
+        }
+
+        file.tail = data[offset..].to_vec();
+
+        Ok(file)
+    }
+
+    fn read_tones(data: &[u8]) -> Result<Vec<Tone>, Nus3bankError> {
+        let reader = Reader { data, section: "TONE" };
+        let count = reader.u32(0)? as usize;
+
+        (0..count)
+            .map(|i| {
+                let offset = reader.u32(4 + i * 8)? as usize;
+                let size = reader.u32(4 + i * 8 + 4)? as usize;
+
+                Ok(Tone {
+                    data: reader.bytes(offset, size)?.to_vec(),
+                })
+            })
+            .collect()
+    }
+
+    fn write_tones(&self) -> Vec<u8> {
+        let mut table = Vec::with_capacity(4 + self.tones.len() * 8);
+        let mut entries = Vec::new();
+
+        table.extend_from_slice(&(self.tones.len() as u32).to_le_bytes());
+
+        let entries_start = 4 + self.tones.len() * 8;
+
+        for tone in self.tones.iter() {
+            table.extend_from_slice(&((entries_start + entries.len()) as u32).to_le_bytes());
+            table.extend_from_slice(&(tone.data.len() as u32).to_le_bytes());
+
+            entries.extend_from_slice(&tone.data);
+            entries.resize(align4(entries.len()), 0);
+        }
+
+        table.extend(entries);
+        table
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let tones = self.write_tones();
+
+        let sections: Vec<(&[u8; 4], &[u8])> = self
+            .sections
+            .iter()
+            .map(|(magic, body)| (magic, if magic == b"TONE" { tones.as_slice() } else { body.as_slice() }))
+            .collect();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"NUS3");
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(b"BANKTOC ");
+        out.extend_from_slice(&((4 + sections.len() * 8 + self.toc_padding.len()) as u32).to_le_bytes());
+        out.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+
+        for (magic, body) in sections.iter() {
+            out.extend_from_slice(*magic);
+            out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        }
+
+        out.extend_from_slice(&self.toc_padding);
+
+        for (magic, body) in sections.iter() {
+            out.extend_from_slice(*magic);
+            out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            out.extend_from_slice(body);
+        }
+
+        out.extend_from_slice(&self.tail);
+
+        let size = (out.len() - 8) as u32;
+        out[4..8].copy_from_slice(&size.to_le_bytes());
+
+        out
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.tones.iter().position(|tone| tone.name() == Some(name))
+    }
+
+    /// Bring the tones in line with the sounds of the patched NUS3AUDIO.
+    ///
+    /// `base` holds the sounds before any patch, which must match the tones one to one for sounds to be renamed or added.
+    /// Added sounds use the tone given for them in `tones`, or a copy of the tone before them. Tones given for sounds
+    /// that already exist replace their metadata, whether the audio changed or not.
+    pub fn apply_sounds(&mut self, base: &Nus3audioFile, patched: &Nus3audioFile, mut tones: HashMap<String, Tone>) -> Vec<String> {
+        let mut notes = Vec::new();
+
+        let mut base_files: Vec<_> = base.files.iter().collect();
+        base_files.sort_by_key(|file| file.id);
+
+        let mut patched_files: Vec<_> = patched.files.iter().collect();
+        patched_files.sort_by_key(|file| file.id);
+
+        let matches_base = base_files.len() == self.tones.len()
+            && base_files
+                .iter()
+                .zip(self.tones.iter())
+                .enumerate()
+                .all(|(i, (file, tone))| file.id as usize == i && (tone.is_empty() || tone.name() == Some(file.name.as_str())));
+
+        if matches_base {
+            for (i, file) in patched_files.iter().enumerate() {
+                let result = if let Some(tone) = self.tones.get_mut(i) {
+                    if tone.is_empty() || tone.name() == Some(file.name.as_str()) {
+                        continue;
+                    }
+
+                    tone.set_name(&file.name)
+                } else {
+                    let tone = match tones.remove(&file.name) {
+                        Some(tone) => Ok(tone),
+                        None => match self.tones.iter().rev().find(|tone| !tone.is_empty()).cloned() {
+                            Some(mut tone) => {
+                                notes.push(format!(
+                                    "Sound {} has no tone in a .patch3bank, it uses a copy of the last tone of the bank.",
+                                    file.name
+                                ));
+                                tone.set_name(&file.name).map(|_| tone)
+                            },
+                            None => {
+                                notes.push(format!("Sound {} has no tone and the bank has none to copy, it won't play.", file.name));
+                                continue;
+                            },
+                        },
+                    };
+
+                    tone.map(|tone| self.tones.push(tone))
+                };
+
+                if let Err(err) = result {
+                    notes.push(format!("Tone of sound {} could not be updated. Reason: {}", file.name, err));
+                }
+            }
+        } else if base_files.len() != patched_files.len() || base_files.iter().zip(patched_files.iter()).any(|(a, b)| a.name != b.name) {
+            notes.push(String::from(
+                "The tones of the bank don't match the sounds of the NUS3AUDIO, added and renamed sounds won't get a tone.",
+            ));
+        }
+
+        for (name, tone) in tones {
+            match self.position(&name) {
+                Some(i) => self.tones[i] = tone,
+                None => notes.push(format!("A .patch3bank has a tone for sound {}, which does not exist.", name)),
+            }
+        }
+
+        notes
+    }
+
+    /// Tones of a bank that holds the metadata of the sounds of a patch, by the name of their sound
+    pub fn into_named_tones(self) -> HashMap<String, Tone> {
+        self.tones.into_iter().filter_map(|tone| Some((tone.name()?.to_string(), tone))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use nus3audio::AudioFile;
+
+    use super::{
+        super::nus3audio_patch::{Nus3audioManifest, Nus3audioMerge},
+        *,
+    };
+
+    /// Parameters following the name of every tone, to check they stay in place when the name changes
+    const PARAMS: [u8; 8] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+
+    fn tone(name: &str, long_header: bool) -> Vec<u8> {
+        let mut data = if long_header {
+            vec![0x00, 0x00, 0x01, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00]
+        } else {
+            vec![0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF]
+        };
+
+        data.push(name.len() as u8 + 1);
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        data.resize(align4(data.len()), 0);
+        data.extend_from_slice(&PARAMS);
+
+        data
+    }
+
+    /// A bank laid out like the game's, with sections before and after TONE and padding after the table of contents
+    fn bank(tones: &[Vec<u8>]) -> Vec<u8> {
+        let mut tone_section = (tones.len() as u32).to_le_bytes().to_vec();
+        let mut entries = Vec::new();
+
+        for tone in tones {
+            tone_section.extend_from_slice(&((4 + tones.len() * 8 + entries.len()) as u32).to_le_bytes());
+            tone_section.extend_from_slice(&(tone.len() as u32).to_le_bytes());
+            entries.extend_from_slice(tone);
+            entries.resize(align4(entries.len()), 0);
+        }
+
+        tone_section.extend(entries);
+
+        let sections: [(&[u8; 4], Vec<u8>); 3] = [(b"PROP", vec![1, 2, 3, 4]), (b"TONE", tone_section), (b"JUNK", vec![5, 6, 7, 8])];
+        let padding = [0u8; 8];
+
+        let mut data = b"NUS3\0\0\0\0BANKTOC ".to_vec();
+        data.extend_from_slice(&((4 + sections.len() * 8 + padding.len()) as u32).to_le_bytes());
+        data.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+
+        for (magic, body) in sections.iter() {
+            data.extend_from_slice(*magic);
+            data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        }
+
+        data.extend_from_slice(&padding);
+
+        for (magic, body) in sections.iter() {
+            data.extend_from_slice(*magic);
+            data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            data.extend_from_slice(body);
+        }
+
+        let size = (data.len() - 8) as u32;
+        data[4..8].copy_from_slice(&size.to_le_bytes());
+
+        data
+    }
+
+    fn sounds(names: &[&str]) -> Nus3audioFile {
+        Nus3audioFile {
+            files: names
+                .iter()
+                .enumerate()
+                .map(|(id, name)| AudioFile {
+                    id: id as u32,
+                    name: name.to_string(),
+                    data: vec![id as u8 + 1; 0x10],
+                })
+                .collect(),
+        }
+    }
+
+    /// Go through the bytes of the NUS3AUDIO, like a `.patch3audio` read from the SD card
+    fn patch3audio(names: &[&str]) -> Nus3audioFile {
+        let mut data = Vec::new();
+        sounds(names).write(&mut data);
+
+        Nus3audioFile::from_bytes(&data)
+    }
+
+    fn names(bank: &Nus3bankFile) -> Vec<Option<&str>> {
+        bank.tones.iter().map(Tone::name).collect()
+    }
+
+    #[test]
+    fn unedited_bank_round_trips() {
+        let data = bank(&[tone("se_a", false), vec![0; EMPTY_TONE_SIZE], tone("se_c", true)]);
+        let file = Nus3bankFile::read(&data).unwrap();
+
+        assert_eq!(names(&file), [Some("se_a"), None, Some("se_c")]);
+        assert_eq!(file.write(), data);
+    }
+
+    #[test]
+    fn unchanged_sounds_leave_the_bank_as_is() {
+        let data = bank(&[tone("se_a", false), tone("se_b", true)]);
+        let base = sounds(&["se_a", "se_b"]);
+
+        let mut merge = Nus3audioMerge::new(sounds(&["se_a", "se_b"]));
+        merge.apply_patch(patch3audio(&["se_b"]), Path::new("replace.patch3audio"));
+
+        let mut file = Nus3bankFile::read(&data).unwrap();
+        assert!(file.apply_sounds(&base, &merge.into_file(), HashMap::new()).is_empty());
+        assert_eq!(file.write(), data);
+    }
+
+    #[test]
+    fn added_sounds_get_tones() {
+        let base = sounds(&["se_a", "se_b"]);
+
+        let mut merge = Nus3audioMerge::new(sounds(&["se_a", "se_b"]));
+        merge.apply_patch(patch3audio(&["se_c", "se_d"]), Path::new("add.patch3audio"));
+
+        let patch3bank = Nus3bankFile::read(&bank(&[tone("se_c", true)])).unwrap();
+        let given_tone = patch3bank.tones[0].clone();
+
+        let mut file = Nus3bankFile::read(&bank(&[tone("se_a", false), tone("se_b", false)])).unwrap();
+        let notes = file.apply_sounds(&base, &merge.into_file(), patch3bank.into_named_tones());
+
+        // Only the sound without a tone in the .patch3bank is reported
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].contains("se_d"));
+
+        let written = Nus3bankFile::read(&file.write()).unwrap();
+        assert_eq!(names(&written), [Some("se_a"), Some("se_b"), Some("se_c"), Some("se_d")]);
+        assert_eq!(written.tones[2].data, given_tone.data);
+        // A copy of the tone added just before it
+        assert_eq!(written.tones[3].data, tone("se_d", true));
+    }
+
+    #[test]
+    fn renamed_sounds_rename_their_tone() {
+        let base = sounds(&["se_a", "se_b"]);
+
+        let mut merge = Nus3audioMerge::new(sounds(&["se_a", "se_b"]));
+        let manifest = Nus3audioManifest {
+            remove: Vec::new(),
+            rename: [(String::from("se_b"), String::from("se_much_longer_name"))].into_iter().collect(),
+        };
+        merge.apply_manifest(manifest, Path::new("rename.patch3audio.yml"));
+
+        let mut file = Nus3bankFile::read(&bank(&[tone("se_a", false), tone("se_b", true)])).unwrap();
+        assert!(file.apply_sounds(&base, &merge.into_file(), HashMap::new()).is_empty());
+
+        let written = Nus3bankFile::read(&file.write()).unwrap();
+        assert_eq!(names(&written), [Some("se_a"), Some("se_much_longer_name")]);
+        assert_eq!(written.tones[1].data, tone("se_much_longer_name", true));
+        assert!(written.tones[1].data.ends_with(&PARAMS));
+    }
+
+    #[test]
+    fn tones_replace_the_metadata_of_existing_sounds() {
+        let base = sounds(&["se_a"]);
+        let tones = Nus3bankFile::read(&bank(&[tone("se_a", true)])).unwrap().into_named_tones();
+
+        let mut file = Nus3bankFile::read(&bank(&[tone("se_a", false)])).unwrap();
+        assert!(file.apply_sounds(&base, &sounds(&["se_a"]), tones).is_empty());
+        assert_eq!(file.tones[0].data, tone("se_a", true));
+    }
+
+    #[test]
+    fn bank_that_doesnt_match_the_sounds_is_reported() {
+        let base = sounds(&["se_a", "se_b"]);
+
+        let mut merge = Nus3audioMerge::new(sounds(&["se_a", "se_b"]));
+        merge.apply_patch(patch3audio(&["se_c"]), Path::new("add.patch3audio"));
+
+        let data = bank(&[tone("se_x", false), tone("se_b", false)]);
+        let mut file = Nus3bankFile::read(&data).unwrap();
+        let notes = file.apply_sounds(&base, &merge.into_file(), HashMap::new());
+
+        assert_eq!(notes.len(), 1);
+        assert_eq!(file.write(), data);
+    }
+}
@@ -0,0 +1,532 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+};
+
+use config::PrcMergePolicy;
+use prcx::{hash40::Hash40, ParamKind, ParamList, ParamStruct};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParamSegment {
+    /// A key of a struct
+    Key(Hash40),
+    /// An entry of a list
+    Index(usize),
+    /// A row of a keyed list, found by the value of its key field instead of its position
+    Row { key: Hash40, value: Hash40 },
+}
+
+/// Location of a param inside a param file, starting from the root struct
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ParamPath(pub Vec<ParamSegment>);
+
+impl fmt::Display for ParamPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                ParamSegment::Key(key) if i == 0 => write!(f, "{}", key)?,
+                ParamSegment::Key(key) => write!(f, ".{}", key)?,
+                ParamSegment::Index(index) => write!(f, "[{}]", index)?,
+                ParamSegment::Row { key, value } => write!(f, "[{}={}]", key, value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A param that several patches set to different values
+#[derive(Debug, Clone)]
+pub struct PrcConflict {
+    pub path: ParamPath,
+    /// Every patch that changes the param with the value it sets, in load order
+    pub values: Vec<(PathBuf, ParamKind)>,
+}
+
+impl PrcConflict {
+    /// The patch whose value ends up in the file for the policy
+    pub fn winner(&self, policy: PrcMergePolicy) -> Option<&(PathBuf, ParamKind)> {
+        match policy {
+            PrcMergePolicy::LastWins => self.values.last(),
+            PrcMergePolicy::FirstWins => self.values.first(),
+            PrcMergePolicy::Error => None,
+        }
+    }
+}
+
+/// Every param a patch changes compared to the vanilla file
+pub struct PrcChanges {
+    pub patch_path: PathBuf,
+    pub changes: Vec<(ParamPath, ParamKind)>,
+    /// Lists the patch makes shorter, which are replaced as a whole since their rows can't be told apart
+    pub replaced_lists: Vec<ParamPath>,
+}
+
+impl PrcChanges {
+    /// Compare the vanilla file with the result of applying the patch to it.
+    ///
+    /// Lists whose rows all have one of the `row_keys` fields are compared row by row using that key,
+    /// so that rows added by different patches don't take each other's place.
+    pub fn new(patch_path: PathBuf, vanilla: &ParamStruct, patched: &ParamStruct, row_keys: &[Hash40]) -> Self {
+        let mut diff = Diff::new(row_keys);
+        diff.diff_struct(vanilla, patched, &mut Vec::new());
+
+        Self {
+            patch_path,
+            changes: diff.changes,
+            replaced_lists: diff.replaced_lists,
+        }
+    }
+
+    /// Add the params the patch sets to their vanilla value, which comparing with vanilla can't see.
+    /// They show up when comparing the base file, replaced by a mod, with the result of applying the patch to it.
+    pub fn add_explicit(&mut self, base: &ParamStruct, patched_base: &ParamStruct, row_keys: &[Hash40]) {
+        let mut diff = Diff::new(row_keys);
+        diff.diff_struct(base, patched_base, &mut Vec::new());
+
+        let known: HashSet<ParamPath> = self.changes.iter().map(|(path, _)| path.clone()).collect();
+        self.changes.extend(diff.changes.into_iter().filter(|(path, _)| !known.contains(path)));
+    }
+}
+
+struct Diff<'a> {
+    row_keys: &'a [Hash40],
+    changes: Vec<(ParamPath, ParamKind)>,
+    replaced_lists: Vec<ParamPath>,
+}
+
+impl<'a> Diff<'a> {
+    fn new(row_keys: &'a [Hash40]) -> Self {
+        Self {
+            row_keys,
+            changes: Vec::new(),
+            replaced_lists: Vec::new(),
+        }
+    }
+
+    fn diff_struct(&mut self, vanilla: &ParamStruct, patched: &ParamStruct, path: &mut Vec<ParamSegment>) {
+        for (key, value) in patched.0.iter() {
+            path.push(ParamSegment::Key(*key));
+
+            match vanilla.0.iter().find(|(vanilla_key, _)| vanilla_key == key) {
+                Some((_, vanilla_value)) => self.diff_param(vanilla_value, value, path),
+                None => self.changes.push((ParamPath(path.clone()), value.clone())),
+            }
+
+            path.pop();
+        }
+    }
+
+    fn diff_param(&mut self, vanilla: &ParamKind, patched: &ParamKind, path: &mut Vec<ParamSegment>) {
+        match (vanilla, patched) {
+            (ParamKind::Struct(vanilla), ParamKind::Struct(patched)) => self.diff_struct(vanilla, patched, path),
+            (ParamKind::List(vanilla), ParamKind::List(patched)) if let Some(key) = self.row_key(vanilla, patched) => {
+                // Rows that the patch removes are not tracked, keyed lists only get rows added or updated
+                for row in patched.0.iter() {
+                    let value = row_key_value(row, key).unwrap();
+                    path.push(ParamSegment::Row { key, value });
+
+                    match vanilla.0.iter().find(|vanilla_row| row_key_value(vanilla_row, key) == Some(value)) {
+                        Some(vanilla_row) => self.diff_param(vanilla_row, row, path),
+                        None => self.changes.push((ParamPath(path.clone()), row.clone())),
+                    }
+
+                    path.pop();
+                }
+            },
+            // Rows past the end of the vanilla list are added by their index
+            (ParamKind::List(vanilla), ParamKind::List(patched)) if vanilla.0.len() <= patched.0.len() => {
+                for (index, patched) in patched.0.iter().enumerate() {
+                    path.push(ParamSegment::Index(index));
+
+                    match vanilla.0.get(index) {
+                        Some(vanilla) => self.diff_param(vanilla, patched, path),
+                        None => self.changes.push((ParamPath(path.clone()), patched.clone())),
+                    }
+
+                    path.pop();
+                }
+            },
+            (ParamKind::List(_), ParamKind::List(_)) => {
+                self.replaced_lists.push(ParamPath(path.clone()));
+                self.changes.push((ParamPath(path.clone()), patched.clone()));
+            },
+            _ if vanilla != patched => self.changes.push((ParamPath(path.clone()), patched.clone())),
+            _ => {},
+        }
+    }
+
+    /// The first key field that identifies every row of both lists, with no two rows of a list sharing a value
+    fn row_key(&self, vanilla: &ParamList, patched: &ParamList) -> Option<Hash40> {
+        self.row_keys
+            .iter()
+            .copied()
+            .find(|key| is_keyed_by(vanilla, *key) && is_keyed_by(patched, *key))
+    }
+}
+
+fn row_key_value(row: &ParamKind, key: Hash40) -> Option<Hash40> {
+    match row {
+        ParamKind::Struct(row) => row.0.iter().find_map(|(field, value)| match value {
+            ParamKind::Hash(value) if *field == key => Some(*value),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+fn is_keyed_by(list: &ParamList, key: Hash40) -> bool {
+    let mut values = HashSet::new();
+
+    !list.0.is_empty()
+        && list
+            .0
+            .iter()
+            .all(|row| row_key_value(row, key).map(|value| values.insert(value)).unwrap_or(false))
+}
+
+/// Find the params that more than one patch sets, to different values
+pub fn find_conflicts(patches: &[PrcChanges]) -> Vec<PrcConflict> {
+    let mut conflicts: Vec<PrcConflict> = Vec::new();
+    let mut indices: HashMap<&ParamPath, usize> = HashMap::new();
+
+    for patch in patches.iter() {
+        for (path, value) in patch.changes.iter() {
+            match indices.get(path) {
+                Some(index) => conflicts[*index].values.push((patch.patch_path.clone(), value.clone())),
+                None => {
+                    indices.insert(path, conflicts.len());
+                    conflicts.push(PrcConflict {
+                        path: path.clone(),
+                        values: vec![(patch.patch_path.clone(), value.clone())],
+                    });
+                },
+            }
+        }
+    }
+
+    conflicts.retain(|conflict| conflict.values.iter().any(|(_, value)| *value != conflict.values[0].1));
+
+    conflicts
+}
+
+/// Apply the changes of every patch on top of the base file, which is either vanilla or a file replaced by a mod.
+///
+/// Changes are applied one patch at a time, in the order that lets the winning patch of the policy go last.
+pub fn apply_changes(base: &mut ParamStruct, patches: &[PrcChanges], policy: PrcMergePolicy) {
+    let apply = |patch: &PrcChanges, base: &mut ParamStruct| {
+        for (path, value) in patch.changes.iter() {
+            if !set_param(base, &path.0, value.clone()) {
+                warn!(
+                    "Param '{}' from '{}' does not exist in the file it patches, skipping.",
+                    path,
+                    patch.patch_path.display()
+                );
+            }
+        }
+    };
+
+    match policy {
+        PrcMergePolicy::FirstWins => patches.iter().rev().for_each(|patch| apply(patch, base)),
+        _ => patches.iter().for_each(|patch| apply(patch, base)),
+    }
+}
+
+fn set_param(param: &mut ParamStruct, path: &[ParamSegment], value: ParamKind) -> bool {
+    let (key, rest) = match path.split_first() {
+        Some((ParamSegment::Key(key), rest)) => (key, rest),
+        _ => return false,
+    };
+
+    match param.0.iter().position(|(param_key, _)| param_key == key) {
+        Some(index) if rest.is_empty() => {
+            param.0[index].1 = value;
+            true
+        },
+        Some(index) => set_param_kind(&mut param.0[index].1, rest, value),
+        // Keys that only exist in the patch get added, like applying the patch would
+        None if rest.is_empty() => {
+            param.0.push((*key, value));
+            true
+        },
+        None => false,
+    }
+}
+
+fn set_param_kind(param: &mut ParamKind, path: &[ParamSegment], value: ParamKind) -> bool {
+    match (param, path.first()) {
+        (ParamKind::Struct(param), Some(ParamSegment::Key(_))) => set_param(param, path, value),
+        (ParamKind::List(list), Some(ParamSegment::Index(index))) => {
+            let len = list.0.len();
+
+            match list.0.get_mut(*index) {
+                Some(param) if path.len() == 1 => {
+                    *param = value;
+                    true
+                },
+                Some(param) => set_param_kind(param, &path[1..], value),
+                // Rows added right after the end of the list, anything further would leave a gap
+                None if path.len() == 1 && *index == len => {
+                    list.0.push(value);
+                    true
+                },
+                None => false,
+            }
+        },
+        (ParamKind::List(list), Some(ParamSegment::Row { key, value: key_value })) => {
+            match list.0.iter_mut().find(|row| row_key_value(row, *key) == Some(*key_value)) {
+                Some(param) if path.len() == 1 => {
+                    *param = value;
+                    true
+                },
+                Some(param) => set_param_kind(param, &path[1..], value),
+                // Rows that only exist in the patch are added after the others
+                None if path.len() == 1 => {
+                    list.0.push(value);
+                    true
+                },
+                None => false,
+            }
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prcx::hash40::hash40;
+
+    use super::*;
+
+    fn params(fields: Vec<(&str, ParamKind)>) -> ParamStruct {
+        ParamStruct(fields.into_iter().map(|(key, value)| (hash40(key), value)).collect())
+    }
+
+    fn list(rows: Vec<ParamKind>) -> ParamKind {
+        ParamKind::List(ParamList(rows))
+    }
+
+    /// Row of a list keyed by its `id` field
+    fn row(id: &str, value: i32) -> ParamKind {
+        ParamKind::Struct(params(vec![("id", ParamKind::Hash(hash40(id))), ("value", ParamKind::I32(value))]))
+    }
+
+    fn changes(name: &str, vanilla: &ParamStruct, patched: &ParamStruct) -> PrcChanges {
+        PrcChanges::new(PathBuf::from(name), vanilla, patched, &[hash40("id")])
+    }
+
+    fn merge(base: &ParamStruct, patches: &[PrcChanges], policy: PrcMergePolicy) -> ParamStruct {
+        let mut base = base.clone();
+        apply_changes(&mut base, patches, policy);
+
+        base
+    }
+
+    #[test]
+    fn patch_changes_go_on_top_of_a_modded_base() {
+        let vanilla = params(vec![("a", ParamKind::I32(1)), ("b", ParamKind::I32(2))]);
+        let base = params(vec![("a", ParamKind::I32(5)), ("b", ParamKind::I32(2))]);
+        let patched = params(vec![("a", ParamKind::I32(1)), ("b", ParamKind::I32(3))]);
+
+        let patch = changes("patch.prcxml", &vanilla, &patched);
+
+        assert_eq!(patch.changes, [(ParamPath(vec![ParamSegment::Key(hash40("b"))]), ParamKind::I32(3))]);
+        assert_eq!(
+            merge(&base, &[patch], PrcMergePolicy::LastWins),
+            params(vec![("a", ParamKind::I32(5)), ("b", ParamKind::I32(3))])
+        );
+    }
+
+    #[test]
+    fn patch_setting_a_vanilla_value_over_a_modded_base_is_applied() {
+        let vanilla = params(vec![("a", ParamKind::I32(1)), ("b", ParamKind::I32(2))]);
+        let base = params(vec![("a", ParamKind::I32(5)), ("b", ParamKind::I32(2))]);
+
+        // The patch sets `a` to its vanilla value, comparing with vanilla alone doesn't see it
+        let mut patch = changes("patch.prcxml", &vanilla, &vanilla);
+        assert!(patch.changes.is_empty());
+
+        patch.add_explicit(&base, &params(vec![("a", ParamKind::I32(1)), ("b", ParamKind::I32(2))]), &[hash40("id")]);
+
+        assert_eq!(merge(&base, &[patch], PrcMergePolicy::LastWins), vanilla);
+    }
+
+    #[test]
+    fn explicit_values_dont_duplicate_vanilla_changes() {
+        let vanilla = params(vec![("a", ParamKind::I32(1))]);
+        let base = params(vec![("a", ParamKind::I32(5))]);
+        let patched = params(vec![("a", ParamKind::I32(7))]);
+
+        let mut patch = changes("patch.prcxml", &vanilla, &patched);
+        patch.add_explicit(&base, &patched, &[hash40("id")]);
+
+        assert_eq!(patch.changes.len(), 1);
+    }
+
+    #[test]
+    fn grown_unkeyed_list_is_patched_row_by_row() {
+        let vanilla = params(vec![("list", list(vec![ParamKind::I32(1), ParamKind::I32(2)]))]);
+        let base = params(vec![("list", list(vec![ParamKind::I32(7), ParamKind::I32(2)]))]);
+        let patched = params(vec![("list", list(vec![ParamKind::I32(1), ParamKind::I32(9), ParamKind::I32(3)]))]);
+
+        let patch = changes("patch.prcxml", &vanilla, &patched);
+
+        assert!(patch.replaced_lists.is_empty());
+        assert_eq!(
+            merge(&base, &[patch], PrcMergePolicy::LastWins),
+            params(vec![("list", list(vec![ParamKind::I32(7), ParamKind::I32(9), ParamKind::I32(3)]))])
+        );
+    }
+
+    #[test]
+    fn shrunk_unkeyed_list_is_replaced_and_reported() {
+        let vanilla = params(vec![("list", list(vec![ParamKind::I32(1), ParamKind::I32(2)]))]);
+        let base = params(vec![("list", list(vec![ParamKind::I32(7), ParamKind::I32(2)]))]);
+        let patched = params(vec![("list", list(vec![ParamKind::I32(1)]))]);
+
+        let patch = changes("patch.prcxml", &vanilla, &patched);
+
+        assert_eq!(patch.replaced_lists, [ParamPath(vec![ParamSegment::Key(hash40("list"))])]);
+        assert_eq!(merge(&base, &[patch], PrcMergePolicy::LastWins), patched);
+    }
+
+    #[test]
+    fn keyed_rows_are_found_by_their_key() {
+        let vanilla = params(vec![("rows", list(vec![row("mario", 1), row("luigi", 2)]))]);
+        // The mod moved the rows around and added one
+        let base = params(vec![("rows", list(vec![row("peach", 4), row("luigi", 2), row("mario", 1)]))]);
+
+        let update = changes(
+            "update.prcxml",
+            &vanilla,
+            &params(vec![("rows", list(vec![row("mario", 1), row("luigi", 8)]))]),
+        );
+        let add_daisy = changes(
+            "daisy.prcxml",
+            &vanilla,
+            &params(vec![("rows", list(vec![row("mario", 1), row("luigi", 2), row("daisy", 5)]))]),
+        );
+        let add_wario = changes(
+            "wario.prcxml",
+            &vanilla,
+            &params(vec![("rows", list(vec![row("mario", 1), row("luigi", 2), row("wario", 6)]))]),
+        );
+
+        let patches = [update, add_daisy, add_wario];
+        assert!(find_conflicts(&patches).is_empty());
+
+        assert_eq!(
+            merge(&base, &patches, PrcMergePolicy::LastWins),
+            params(vec![(
+                "rows",
+                list(vec![row("peach", 4), row("luigi", 8), row("mario", 1), row("daisy", 5), row("wario", 6)])
+            )])
+        );
+    }
+
+    fn rows(rows: Vec<ParamKind>) -> ParamStruct {
+        params(vec![("rows", list(rows))])
+    }
+
+    fn row_path(id: &str) -> Vec<ParamSegment> {
+        vec![
+            ParamSegment::Key(hash40("rows")),
+            ParamSegment::Row {
+                key: hash40("id"),
+                value: hash40(id),
+            },
+        ]
+    }
+
+    #[test]
+    fn same_row_changed_by_two_patches_conflicts() {
+        let vanilla = rows(vec![row("mario", 1), row("luigi", 2)]);
+        let first = changes("first.prcxml", &vanilla, &rows(vec![row("mario", 3), row("luigi", 2)]));
+        // Rows in another order are still found by their key
+        let second = changes("second.prcxml", &vanilla, &rows(vec![row("luigi", 2), row("mario", 4)]));
+
+        let patches = [first, second];
+        let conflicts = find_conflicts(&patches);
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].path,
+            ParamPath([row_path("mario"), vec![ParamSegment::Key(hash40("value"))]].concat())
+        );
+        assert_eq!(conflicts[0].winner(PrcMergePolicy::LastWins).unwrap().0, PathBuf::from("second.prcxml"));
+        assert_eq!(conflicts[0].winner(PrcMergePolicy::FirstWins).unwrap().0, PathBuf::from("first.prcxml"));
+        assert!(conflicts[0].winner(PrcMergePolicy::Error).is_none());
+
+        assert_eq!(
+            merge(&vanilla, &patches, PrcMergePolicy::LastWins),
+            rows(vec![row("mario", 4), row("luigi", 2)])
+        );
+        assert_eq!(
+            merge(&vanilla, &patches, PrcMergePolicy::FirstWins),
+            rows(vec![row("mario", 3), row("luigi", 2)])
+        );
+    }
+
+    #[test]
+    fn same_row_added_by_two_patches_conflicts() {
+        let vanilla = rows(vec![row("mario", 1)]);
+        let first = changes("first.prcxml", &vanilla, &rows(vec![row("mario", 1), row("daisy", 5)]));
+        let second = changes("second.prcxml", &vanilla, &rows(vec![row("mario", 1), row("daisy", 6)]));
+
+        let patches = [first, second];
+        let conflicts = find_conflicts(&patches);
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, ParamPath(row_path("daisy")));
+        assert_eq!(
+            conflicts[0].path.to_string(),
+            format!("{}[{}={}]", hash40("rows"), hash40("id"), hash40("daisy"))
+        );
+
+        // The row is added once, with the value of the winner
+        assert_eq!(
+            merge(&vanilla, &patches, PrcMergePolicy::LastWins),
+            rows(vec![row("mario", 1), row("daisy", 6)])
+        );
+        assert_eq!(
+            merge(&vanilla, &patches, PrcMergePolicy::FirstWins),
+            rows(vec![row("mario", 1), row("daisy", 5)])
+        );
+    }
+
+    #[test]
+    fn same_value_from_two_patches_doesnt_conflict() {
+        let vanilla = rows(vec![row("mario", 1)]);
+        let patched = rows(vec![row("mario", 2), row("daisy", 5)]);
+
+        let patches = [changes("first.prcxml", &vanilla, &patched), changes("second.prcxml", &vanilla, &patched)];
+
+        assert!(find_conflicts(&patches).is_empty());
+        assert_eq!(merge(&vanilla, &patches, PrcMergePolicy::Error), patched);
+    }
+
+    #[test]
+    fn list_with_duplicate_keys_conflicts_by_index() {
+        let vanilla = rows(vec![row("mario", 1), row("mario", 2)]);
+        let first = changes("first.prcxml", &vanilla, &rows(vec![row("mario", 1), row("mario", 2), row("daisy", 5)]));
+        let second = changes("second.prcxml", &vanilla, &rows(vec![row("mario", 1), row("mario", 2), row("wario", 6)]));
+
+        let conflicts = find_conflicts(&[first, second]);
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].path,
+            ParamPath(vec![ParamSegment::Key(hash40("rows")), ParamSegment::Index(2)])
+        );
+    }
+
+    #[test]
+    fn row_missing_from_the_base_is_skipped() {
+        let vanilla = rows(vec![row("mario", 1), row("luigi", 2)]);
+        let base = rows(vec![row("mario", 1)]);
+        let patch = changes("patch.prcxml", &vanilla, &rows(vec![row("mario", 1), row("luigi", 3)]));
+
+        assert_eq!(merge(&base, &[patch], PrcMergePolicy::LastWins), base);
+    }
+}
@@ -0,0 +1,591 @@
+//! Decoder for VCDIFF (RFC 3284) deltas, the format written by `xdelta3`.
+//!
+//! Only deltas that don't use secondary compression or a custom code table are supported, which is what `xdelta3 -S none` produces.
+
+use std::borrow::Cow;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum VcdiffError {
+    #[error("The file is not a VCDIFF delta.")]
+    InvalidMagic,
+
+    #[error("The delta ends in the middle of a window.")]
+    UnexpectedEof,
+
+    #[error("The delta uses {0}, which is not supported. Make it with `xdelta3 -S none`.")]
+    Unsupported(&'static str),
+
+    #[error("The delta is malformed: {0}.")]
+    Malformed(&'static str),
+
+    #[error("A window of the patched file doesn't match its checksum.")]
+    ChecksumMismatch,
+}
+
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+const VCD_APPHEADER: u8 = 0x04;
+
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+/// Extension from xdelta3, the window carries the Adler-32 of its target
+const VCD_ADLER32: u8 = 0x04;
+
+/// xdelta3 never makes windows bigger than this, so a bigger one is a hostile delta trying to make the decoder run out of memory
+const MAX_WINDOW_SIZE: usize = 1 << 24;
+
+const NEAR_SIZE: usize = 4;
+const SAME_SIZE: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instruction {
+    Noop,
+    Add(usize),
+    Run(usize),
+    Copy(usize, u8),
+}
+
+/// The default code table from section 5.6 of the RFC, where a size of 0 means the size is read from the instructions
+fn default_code_table() -> Vec<(Instruction, Instruction)> {
+    use Instruction::*;
+
+    let mut table = Vec::with_capacity(256);
+
+    table.push((Run(0), Noop));
+
+    for size in 0..18 {
+        table.push((Add(size), Noop));
+    }
+
+    for mode in 0..9 {
+        table.push((Copy(0, mode), Noop));
+
+        for size in 4..19 {
+            table.push((Copy(size, mode), Noop));
+        }
+    }
+
+    for mode in 0..6 {
+        for add_size in 1..5 {
+            for copy_size in 4..7 {
+                table.push((Add(add_size), Copy(copy_size, mode)));
+            }
+        }
+    }
+
+    for mode in 6..9 {
+        for add_size in 1..5 {
+            table.push((Add(add_size), Copy(4, mode)));
+        }
+    }
+
+    for mode in 0..9 {
+        table.push((Copy(4, mode), Add(1)));
+    }
+
+    table
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, VcdiffError> {
+        let byte = *self.data.get(self.position).ok_or(VcdiffError::UnexpectedEof)?;
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], VcdiffError> {
+        let end = self.position.checked_add(len).ok_or(VcdiffError::UnexpectedEof)?;
+        let bytes = self.data.get(self.position..end).ok_or(VcdiffError::UnexpectedEof)?;
+        self.position += len;
+
+        Ok(bytes)
+    }
+
+    /// Size of an instruction, which is read from the instructions when the code table doesn't give one
+    fn size(&mut self, size: usize) -> Result<usize, VcdiffError> {
+        if size == 0 {
+            self.varint()
+        } else {
+            Ok(size)
+        }
+    }
+
+    /// Integers are written in base 128, most significant digit first, with the high bit set on every byte but the last
+    fn varint(&mut self) -> Result<usize, VcdiffError> {
+        let mut value: usize = 0;
+
+        loop {
+            let byte = self.byte()?;
+            value = value.checked_mul(128).ok_or(VcdiffError::Malformed("an integer overflows"))? | (byte & 0x7F) as usize;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+struct AddressCache {
+    near: [usize; NEAR_SIZE],
+    next_slot: usize,
+    same: [usize; SAME_SIZE * 256],
+}
+
+impl AddressCache {
+    fn new() -> Self {
+        Self {
+            near: [0; NEAR_SIZE],
+            next_slot: 0,
+            same: [0; SAME_SIZE * 256],
+        }
+    }
+
+    fn decode(&mut self, here: usize, mode: u8, addresses: &mut Reader) -> Result<usize, VcdiffError> {
+        let mode = mode as usize;
+
+        let address = match mode {
+            0 => addresses.varint()?,
+            1 => here
+                .checked_sub(addresses.varint()?)
+                .ok_or(VcdiffError::Malformed("a copy starts before the file"))?,
+            _ if mode < 2 + NEAR_SIZE => self.near[mode - 2]
+                .checked_add(addresses.varint()?)
+                .ok_or(VcdiffError::Malformed("a copy address overflows"))?,
+            _ => self.same[(mode - 2 - NEAR_SIZE) * 256 + addresses.byte()? as usize],
+        };
+
+        self.near[self.next_slot] = address;
+        self.next_slot = (self.next_slot + 1) % NEAR_SIZE;
+        self.same[address % (SAME_SIZE * 256)] = address;
+
+        Ok(address)
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+/// Read the header of the delta, leaving the reader on its first window
+fn read_header(reader: &mut Reader) -> Result<(), VcdiffError> {
+    if reader.bytes(4).map_err(|_| VcdiffError::InvalidMagic)? != [0xD6, 0xC3, 0xC4, 0x00] {
+        return Err(VcdiffError::InvalidMagic);
+    }
+
+    let header_indicator = reader.byte()?;
+
+    if header_indicator & VCD_DECOMPRESS != 0 {
+        return Err(VcdiffError::Unsupported("secondary compression"));
+    }
+
+    if header_indicator & VCD_CODETABLE != 0 {
+        return Err(VcdiffError::Unsupported("a custom code table"));
+    }
+
+    if header_indicator & VCD_APPHEADER != 0 {
+        let len = reader.varint()?;
+        reader.bytes(len)?;
+    }
+
+    Ok(())
+}
+
+/// Size of the file the delta builds, read from the headers of its windows without applying it
+pub fn target_size(delta: &[u8]) -> Result<usize, VcdiffError> {
+    let mut reader = Reader::new(delta);
+    read_header(&mut reader)?;
+
+    let mut size: usize = 0;
+
+    while !reader.is_empty() {
+        let window_indicator = reader.byte()?;
+
+        if window_indicator & (VCD_SOURCE | VCD_TARGET) != 0 {
+            reader.varint()?;
+            reader.varint()?;
+        }
+
+        // The length of the delta covers the rest of the window, starting with the size of its target
+        let delta_len = reader.varint()?;
+        let window = reader.bytes(delta_len)?;
+
+        size = size
+            .checked_add(Reader::new(window).varint()?)
+            .ok_or(VcdiffError::Malformed("an integer overflows"))?;
+    }
+
+    Ok(size)
+}
+
+/// Apply a VCDIFF delta to the source file it was made from
+pub fn apply(source: &[u8], delta: &[u8]) -> Result<Vec<u8>, VcdiffError> {
+    let mut reader = Reader::new(delta);
+    read_header(&mut reader)?;
+
+    let code_table = default_code_table();
+    let mut target = Vec::new();
+
+    while !reader.is_empty() {
+        let window_indicator = reader.byte()?;
+
+        // Copies from the target need their segment to be copied, since the target keeps growing while the window is decoded
+        let segment: Cow<[u8]> = if window_indicator & (VCD_SOURCE | VCD_TARGET) != 0 {
+            let len = reader.varint()?;
+            let position = reader.varint()?;
+            let range = position..position.checked_add(len).ok_or(VcdiffError::Malformed("an integer overflows"))?;
+
+            if window_indicator & VCD_SOURCE != 0 {
+                Cow::Borrowed(
+                    source
+                        .get(range)
+                        .ok_or(VcdiffError::Malformed("a window reads past the end of its source"))?,
+                )
+            } else {
+                Cow::Owned(
+                    target
+                        .get(range)
+                        .ok_or(VcdiffError::Malformed("a window reads past the end of its source"))?
+                        .to_vec(),
+                )
+            }
+        } else {
+            Cow::Borrowed(&[])
+        };
+
+        let _delta_len = reader.varint()?;
+        let window_len = reader.varint()?;
+
+        if window_len > MAX_WINDOW_SIZE {
+            return Err(VcdiffError::Malformed("a window is bigger than xdelta3 makes them"));
+        }
+
+        if reader.byte()? != 0 {
+            return Err(VcdiffError::Unsupported("compressed sections"));
+        }
+
+        let data_len = reader.varint()?;
+        let instructions_len = reader.varint()?;
+        let addresses_len = reader.varint()?;
+
+        let checksum = if window_indicator & VCD_ADLER32 != 0 {
+            Some(u32::from_be_bytes(reader.bytes(4)?.try_into().unwrap()))
+        } else {
+            None
+        };
+
+        let mut data = Reader::new(reader.bytes(data_len)?);
+        let mut instructions = Reader::new(reader.bytes(instructions_len)?);
+        let mut addresses = Reader::new(reader.bytes(addresses_len)?);
+
+        let mut window: Vec<u8> = Vec::with_capacity(window_len);
+        let mut cache = AddressCache::new();
+
+        while !instructions.is_empty() {
+            let (first, second) = code_table[instructions.byte()? as usize];
+
+            for instruction in [first, second] {
+                match instruction {
+                    Instruction::Noop => {},
+                    Instruction::Add(len) => {
+                        let len = instructions.size(len)?;
+                        window.extend_from_slice(data.bytes(len)?);
+                    },
+                    Instruction::Run(len) => {
+                        let len = instructions.size(len)?;
+                        let byte = data.byte()?;
+                        let end = window.len().checked_add(len).filter(|&end| end <= window_len);
+                        window.resize(end.ok_or(VcdiffError::Malformed("a run goes past the end of its window"))?, byte);
+                    },
+                    Instruction::Copy(len, mode) => {
+                        let len = instructions.size(len)?;
+                        let here = segment.len() + window.len();
+                        let address = cache.decode(here, mode, &mut addresses)?;
+
+                        // Copies from the window can overlap what they write, so go byte by byte
+                        if window.len().checked_add(len).map_or(true, |end| end > window_len) {
+                            return Err(VcdiffError::Malformed("a copy goes past the end of its window"));
+                        }
+
+                        let end = address.checked_add(len).ok_or(VcdiffError::Malformed("a copy address overflows"))?;
+
+                        for offset in address..end {
+                            let byte = if offset < segment.len() {
+                                segment[offset]
+                            } else {
+                                *window
+                                    .get(offset - segment.len())
+                                    .ok_or(VcdiffError::Malformed("a copy reads past what is decoded"))?
+                            };
+
+                            window.push(byte);
+                        }
+                    },
+                }
+            }
+        }
+
+        if window.len() != window_len {
+            return Err(VcdiffError::Malformed("a window doesn't have the size it declares"));
+        }
+
+        if checksum.map(|checksum| checksum != adler32(&window)).unwrap_or(false) {
+            return Err(VcdiffError::ChecksumMismatch);
+        }
+
+        target.extend(window);
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: [u8; 5] = [0xD6, 0xC3, 0xC4, 0x00, 0x00];
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = vec![(value & 0x7F) as u8];
+        value >>= 7;
+
+        while value != 0 {
+            bytes.insert(0, (value & 0x7F) as u8 | 0x80);
+            value >>= 7;
+        }
+
+        bytes
+    }
+
+    /// Encode a window the way xdelta3 does, with the length of the delta covering everything after it
+    fn window(
+        indicator: u8,
+        segment: Option<(usize, usize)>,
+        target_len: usize,
+        data: &[u8],
+        instructions: &[u8],
+        addresses: &[u8],
+        checksum: Option<u32>,
+    ) -> Vec<u8> {
+        let mut delta = varint(target_len);
+        delta.push(0);
+        delta.extend(varint(data.len()));
+        delta.extend(varint(instructions.len()));
+        delta.extend(varint(addresses.len()));
+
+        if let Some(checksum) = checksum {
+            delta.extend_from_slice(&checksum.to_be_bytes());
+        }
+
+        delta.extend_from_slice(data);
+        delta.extend_from_slice(instructions);
+        delta.extend_from_slice(addresses);
+
+        let mut window = vec![indicator];
+
+        if let Some((len, position)) = segment {
+            window.extend(varint(len));
+            window.extend(varint(position));
+        }
+
+        window.extend(varint(delta.len()));
+        window.extend(delta);
+
+        window
+    }
+
+    fn delta(windows: &[Vec<u8>]) -> Vec<u8> {
+        let mut delta = MAGIC.to_vec();
+        windows.iter().for_each(|window| delta.extend_from_slice(window));
+
+        delta
+    }
+
+    /// Opcode of a copy whose size is read from the instructions
+    fn copy(mode: u8) -> u8 {
+        19 + 16 * mode
+    }
+
+    /// Opcode of a copy of 4 bytes
+    fn copy4(mode: u8) -> u8 {
+        20 + 16 * mode
+    }
+
+    const ADD: u8 = 1;
+    const RUN: u8 = 0;
+
+    #[test]
+    fn adler32_matches_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn add_and_run() {
+        let patch = delta(&[window(0, None, 7, b"abcx", &[ADD, 3, RUN, 4], &[], None)]);
+
+        assert_eq!(apply(&[], &patch).unwrap(), b"abcxxxx");
+        assert_eq!(target_size(&patch).unwrap(), 7);
+    }
+
+    #[test]
+    fn copy_self_mode_from_source() {
+        let patch = delta(&[window(VCD_SOURCE, Some((11, 0)), 10, &[], &[copy(0), 5, copy(0), 5], &[6, 0], None)]);
+
+        assert_eq!(apply(b"hello world", &patch).unwrap(), b"worldhello");
+    }
+
+    #[test]
+    fn copy_here_mode_overlaps_what_it_writes() {
+        let patch = delta(&[window(0, None, 6, b"a", &[ADD, 1, copy(1), 5], &[1], None)]);
+
+        assert_eq!(apply(&[], &patch).unwrap(), b"aaaaaa");
+    }
+
+    #[test]
+    fn copy_near_and_same_modes() {
+        // SELF at 2, NEAR from the first slot holding 2, then SAME looking up 2 in the first block
+        let patch = delta(&[window(
+            VCD_SOURCE,
+            Some((10, 0)),
+            12,
+            &[],
+            &[copy4(0), copy4(2), copy4(6)],
+            &[2, 3, 2],
+            None,
+        )]);
+
+        assert_eq!(apply(b"0123456789", &patch).unwrap(), b"234556782345");
+    }
+
+    #[test]
+    fn source_segment_is_offset() {
+        let patch = delta(&[window(VCD_SOURCE, Some((4, 6)), 4, &[], &[copy4(0)], &[0], None)]);
+
+        assert_eq!(apply(b"0123456789", &patch).unwrap(), b"6789");
+    }
+
+    #[test]
+    fn target_segment_copies_from_previous_windows() {
+        let patch = delta(&[
+            window(0, None, 3, b"abc", &[ADD, 3], &[], None),
+            window(VCD_TARGET, Some((3, 0)), 4, b"d", &[copy(0), 3, ADD, 1], &[0], None),
+        ]);
+
+        assert_eq!(apply(&[], &patch).unwrap(), b"abcabcd");
+        assert_eq!(target_size(&patch).unwrap(), 7);
+    }
+
+    #[test]
+    fn checksum_is_checked() {
+        let good = delta(&[window(VCD_ADLER32, None, 3, b"abc", &[ADD, 3], &[], Some(adler32(b"abc")))]);
+        let bad = delta(&[window(VCD_ADLER32, None, 3, b"abc", &[ADD, 3], &[], Some(adler32(b"abd")))]);
+
+        assert_eq!(apply(&[], &good).unwrap(), b"abc");
+        assert!(matches!(apply(&[], &bad), Err(VcdiffError::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn unsupported_headers_are_rejected() {
+        assert!(matches!(apply(&[], b"BSDIFF40"), Err(VcdiffError::InvalidMagic)));
+        assert!(matches!(apply(&[], &[0xD6, 0xC3]), Err(VcdiffError::InvalidMagic)));
+        assert!(matches!(
+            apply(&[], &[0xD6, 0xC3, 0xC4, 0x00, VCD_DECOMPRESS]),
+            Err(VcdiffError::Unsupported(_))
+        ));
+        assert!(matches!(
+            apply(&[], &[0xD6, 0xC3, 0xC4, 0x00, VCD_CODETABLE]),
+            Err(VcdiffError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_window_is_an_error() {
+        let patch = delta(&[window(
+            VCD_SOURCE | VCD_ADLER32,
+            Some((11, 0)),
+            13,
+            b"!!",
+            &[copy(0), 11, ADD, 2],
+            &[0],
+            Some(adler32(b"hello world!!")),
+        )]);
+        assert_eq!(apply(b"hello world", &patch).unwrap(), b"hello world!!");
+
+        for len in MAGIC.len() + 1..patch.len() {
+            assert!(apply(b"hello world", &patch[..len]).is_err(), "Truncated to {} bytes", len);
+            assert!(target_size(&patch[..len]).is_err(), "Truncated to {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn hostile_windows_are_errors() {
+        let source = b"0123456789";
+
+        let hostile = [
+            // Copies past what is decoded
+            window(VCD_SOURCE, Some((10, 0)), 4, &[], &[copy4(0)], &[12], None),
+            // Source segment past the end of the source
+            window(VCD_SOURCE, Some((4, 8)), 4, &[], &[copy4(0)], &[0], None),
+            // HERE address before the start of the window
+            window(0, None, 4, &[], &[copy4(1)], &[1], None),
+            // Run past the end of the window
+            window(0, None, 2, b"x", &[RUN, 3], &[], None),
+            // Copy past the end of the window
+            window(VCD_SOURCE, Some((10, 0)), 2, &[], &[copy4(0)], &[0], None),
+            // Window smaller than declared
+            window(0, None, 4, b"ab", &[ADD, 2], &[], None),
+            // Add reading past the data section
+            window(0, None, 4, b"ab", &[ADD, 4], &[], None),
+            // Size that overflows
+            window(
+                0,
+                None,
+                4,
+                b"ab",
+                &[ADD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F],
+                &[],
+                None,
+            ),
+            // Window too big to allocate
+            window(0, None, usize::MAX >> 1, b"x", &[RUN, 0xFF, 0xFF, 0xFF, 0x7F], &[], None),
+            // Compressed sections
+            {
+                let mut compressed = window(0, None, 1, b"x", &[ADD, 1], &[], None);
+                compressed[3] = 0x01;
+                compressed
+            },
+        ];
+
+        for (index, window) in hostile.iter().enumerate() {
+            assert!(apply(source, &delta(&[window.clone()])).is_err(), "Hostile window {}", index);
+        }
+    }
+}
@@ -0,0 +1,681 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use serde::Serialize;
+use xml::{
+    attribute::OwnedAttribute,
+    common::{Position, TextPosition},
+    reader::{EventReader, ParserConfig, XmlEvent},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    /// The entry, or the part of it that is concerned, is skipped
+    Error,
+    /// The entry is applied, but probably not the way the author intended
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct XmsbtDiagnostic {
+    pub severity: Severity,
+    /// Starts at 1
+    pub line: u64,
+    /// Starts at 1
+    pub column: u64,
+    pub message: String,
+}
+
+impl fmt::Display for XmsbtDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// A piece of the text of an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextSegment {
+    Text(String),
+    /// Control tag, written as `{{group,type}}` or `{{group,type,params}}` with the parameters as hexadecimal bytes.
+    /// `\{{` writes the braces as text, and `\\{{` a backslash followed by a tag.
+    Tag {
+        group: u16,
+        kind: u16,
+        params: Vec<u8>,
+    },
+    /// Already encoded data, from base64 entries
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmsbtText(pub Vec<TextSegment>);
+
+impl XmsbtText {
+    /// Encode the text as UTF-16 with its control tags and the null terminator
+    pub fn encode(&self, big_endian: bool) -> Vec<u8> {
+        let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+        let mut data = Vec::new();
+
+        for segment in self.0.iter() {
+            match segment {
+                TextSegment::Text(text) => data.extend(text.encode_utf16().flat_map(u16_bytes)),
+                TextSegment::Tag { group, kind, params } => {
+                    data.extend_from_slice(&u16_bytes(0x0E));
+                    data.extend_from_slice(&u16_bytes(*group));
+                    data.extend_from_slice(&u16_bytes(*kind));
+                    data.extend_from_slice(&u16_bytes(params.len() as u16));
+                    data.extend_from_slice(params);
+                },
+                TextSegment::Raw(raw) => data.extend_from_slice(raw),
+            }
+        }
+
+        // Null terminator
+        data.extend_from_slice(&[0, 0]);
+
+        data
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct XmsbtEntry {
+    pub label: String,
+    /// Remove the label from the file, every other field is ignored
+    pub remove: bool,
+    /// Give the label a new name, before the rest of the entry is applied to it
+    pub rename: Option<String>,
+    /// Raw ATR1 data for the label, written as hexadecimal bytes
+    pub attributes: Option<Vec<u8>>,
+    /// TSY1 style index for the label
+    pub style: Option<u32>,
+    pub text: Option<XmsbtText>,
+    pub line: u64,
+    pub column: u64,
+}
+
+impl XmsbtEntry {
+    /// Whether the entry gives the label a value, as opposed to only removing or renaming it
+    pub fn sets_label(&self) -> bool {
+        !self.remove && (self.text.is_some() || self.attributes.is_some() || self.style.is_some())
+    }
+}
+
+/// The entries of a XMSBT file that could be read, and everything wrong with it
+#[derive(Debug, Default)]
+pub struct XmsbtDocument {
+    pub entries: Vec<XmsbtEntry>,
+    pub diagnostics: Vec<XmsbtDiagnostic>,
+}
+
+impl XmsbtDocument {
+    fn report(&mut self, severity: Severity, position: TextPosition, message: impl Into<String>) {
+        self.diagnostics.push(XmsbtDiagnostic {
+            severity,
+            line: position.row + 1,
+            column: position.column + 1,
+            message: message.into(),
+        });
+    }
+}
+
+struct PendingEntry {
+    position: TextPosition,
+    attributes: Vec<OwnedAttribute>,
+    text: Option<(TextPosition, String)>,
+}
+
+/// Read a XMSBT file, keeping going after malformed entries so all of them are reported at once.
+///
+/// A file that isn't valid XML doesn't provide any entry, since it's impossible to tell which ones were meant.
+pub fn parse<R: Read>(reader: R) -> XmsbtDocument {
+    // Same configuration serde_xml_rs uses, which older XMSBT files were written for
+    let config = ParserConfig::new()
+        .trim_whitespace(true)
+        .whitespace_to_characters(true)
+        .cdata_to_characters(true)
+        .ignore_comments(true)
+        .coalesce_characters(true);
+
+    let mut reader = EventReader::new_with_config(reader, config);
+    let mut document = XmsbtDocument::default();
+
+    let mut depth = 0;
+    let mut pending: Option<PendingEntry> = None;
+    let mut in_text = false;
+
+    loop {
+        let event = reader.next();
+        let position = reader.position();
+
+        match event {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                depth += 1;
+
+                match (depth, name.local_name.as_str()) {
+                    (1, _) => {},
+                    (2, "entry") => {
+                        pending = Some(PendingEntry {
+                            position,
+                            attributes,
+                            text: None,
+                        })
+                    },
+                    (3, "text") if pending.is_some() => {
+                        in_text = true;
+
+                        if let Some(entry) = pending.as_mut() {
+                            if entry.text.is_some() {
+                                document.report(Severity::Warning, position, "The entry has several texts, only the last one is used.");
+                            }
+                            entry.text = Some((position, String::new()));
+                        }
+                    },
+                    (_, other) => document.report(Severity::Warning, position, format!("Unknown element <{}> is ignored.", other)),
+                }
+            },
+            Ok(XmlEvent::Characters(characters)) => {
+                if let Some((text_position, text)) = pending.as_mut().and_then(|entry| entry.text.as_mut()).filter(|_| in_text) {
+                    // Point at the text itself rather than the element, so control tags can be located
+                    if text.is_empty() {
+                        *text_position = position;
+                    }
+                    text.push_str(&characters);
+                }
+            },
+            Ok(XmlEvent::EndElement { name }) => {
+                match (depth, name.local_name.as_str()) {
+                    (2, "entry") => {
+                        if let Some(entry) = pending.take() {
+                            if let Some(entry) = finish_entry(entry, &mut document) {
+                                if entry.sets_label() && document.entries.iter().any(|other| other.label == entry.label && other.sets_label()) {
+                                    document.report(
+                                        Severity::Warning,
+                                        position,
+                                        format!("Label {} is set several times in the file, the last entry wins.", entry.label),
+                                    );
+                                }
+                                document.entries.push(entry);
+                            }
+                        }
+                    },
+                    (3, "text") => in_text = false,
+                    _ => {},
+                }
+
+                depth -= 1;
+            },
+            Ok(XmlEvent::EndDocument) => break,
+            Ok(_) => {},
+            Err(err) => {
+                let position = err.position();
+                document.entries.clear();
+                document.report(Severity::Error, position, format!("{}. The file is skipped.", err.msg()));
+                break;
+            },
+        }
+    }
+
+    document
+}
+
+pub fn parse_file(path: &Path) -> XmsbtDocument {
+    match std::fs::File::open(path) {
+        Ok(file) => parse(std::io::BufReader::new(file)),
+        Err(err) => {
+            let mut document = XmsbtDocument::default();
+            document.report(Severity::Error, TextPosition::new(), format!("The file could not be opened: {}", err));
+            document
+        },
+    }
+}
+
+fn finish_entry(entry: PendingEntry, document: &mut XmsbtDocument) -> Option<XmsbtEntry> {
+    let position = entry.position;
+
+    let mut label = None;
+    let mut base64 = false;
+    let mut remove = false;
+    let mut rename = None;
+    let mut attributes = None;
+    let mut style = None;
+
+    let parse_bool = |value: &str, document: &mut XmsbtDocument| match value {
+        "true" => true,
+        "false" => false,
+        _ => {
+            document.report(
+                Severity::Error,
+                position,
+                format!("'{}' is not 'true' or 'false', it is treated as false.", value),
+            );
+            false
+        },
+    };
+
+    for attribute in entry.attributes.iter() {
+        let value = attribute.value.as_str();
+
+        match attribute.name.local_name.as_str() {
+            "label" => label = Some(value.to_string()),
+            "base64" => base64 = parse_bool(value, document),
+            "remove" => remove = parse_bool(value, document),
+            "rename" => rename = Some(value.to_string()),
+            "attributes" => match parse_hex(value) {
+                Some(bytes) => attributes = Some(bytes),
+                None => document.report(
+                    Severity::Error,
+                    position,
+                    format!("Attributes '{}' are not hexadecimal bytes, they are ignored.", value),
+                ),
+            },
+            "style" => match value.parse() {
+                Ok(value) => style = Some(value),
+                Err(_) => document.report(Severity::Error, position, format!("Style '{}' is not a number, it is ignored.", value)),
+            },
+            other => document.report(Severity::Warning, position, format!("Unknown attribute '{}' is ignored.", other)),
+        }
+    }
+
+    let label = match label {
+        Some(label) if !label.is_empty() && label.len() <= u8::MAX as usize => label,
+        Some(label) if !label.is_empty() => {
+            document.report(
+                Severity::Error,
+                position,
+                format!("Label {} is longer than 255 bytes, the entry is skipped.", label),
+            );
+            return None;
+        },
+        _ => {
+            document.report(Severity::Error, position, "The entry has no label, it is skipped.");
+            return None;
+        },
+    };
+
+    let text = match entry.text {
+        Some((_, text)) if base64 => match BASE64_STANDARD.decode(text) {
+            Ok(decoded) => Some(XmsbtText(vec![TextSegment::Raw(decoded)])),
+            Err(err) => {
+                document.report(
+                    Severity::Error,
+                    position,
+                    format!("Label {} could not be base64 decoded, the entry is skipped. Reason: {}", label, err),
+                );
+                return None;
+            },
+        },
+        Some((text_position, text)) => Some(parse_text(&text, text_position, document)),
+        None => None,
+    };
+
+    if !remove && rename.is_none() && text.is_none() && attributes.is_none() && style.is_none() {
+        document.report(
+            Severity::Warning,
+            position,
+            format!("The entry for label {} doesn't change anything.", label),
+        );
+    }
+
+    Some(XmsbtEntry {
+        label,
+        remove,
+        rename,
+        attributes,
+        style,
+        text,
+        line: position.row + 1,
+        column: position.column + 1,
+    })
+}
+
+fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    let digits: String = value.split_whitespace().collect();
+
+    if digits.len() % 2 != 0 || !digits.is_ascii() {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Split the text into its control tags and the text around them. Malformed tags are kept as text.
+fn parse_text(text: &str, start: TextPosition, document: &mut XmsbtDocument) -> XmsbtText {
+    // Position of a byte offset of the text, to point at the tag itself
+    let position_of = |offset: usize| {
+        let before = &text[..offset];
+        match before.rfind('\n') {
+            Some(newline) => TextPosition {
+                row: start.row + before.matches('\n').count() as u64,
+                column: before[newline + 1..].chars().count() as u64,
+            },
+            None => TextPosition {
+                row: start.row,
+                column: start.column + before.chars().count() as u64,
+            },
+        }
+    };
+
+    let mut segments = Vec::new();
+    // Text that isn't pushed yet, so that escapes and malformed tags end up in the same segment as the text around them
+    let mut literal = String::new();
+    let mut rest = text;
+    let mut offset = 0;
+
+    while let Some(tag_start) = rest.find("{{") {
+        let before = &rest[..tag_start];
+        let tag_offset = offset + tag_start;
+
+        if before.ends_with("\\\\") {
+            literal.push_str(&before[..before.len() - 1]);
+        } else if let Some(before) = before.strip_suffix('\\') {
+            literal.push_str(before);
+            literal.push_str("{{");
+            rest = &rest[tag_start + 2..];
+            offset += tag_start + 2;
+            continue;
+        } else {
+            literal.push_str(before);
+        }
+
+        let after = &rest[tag_start + 2..];
+
+        let tag_end = match after.find("}}") {
+            Some(tag_end) => tag_end,
+            None => {
+                document.report(
+                    Severity::Warning,
+                    position_of(tag_offset),
+                    "Control tag is missing its closing '}}', it is kept as text. Write '\\{{' to have braces in the text.",
+                );
+                literal.push_str(&rest[tag_start..]);
+                rest = "";
+                break;
+            },
+        };
+
+        let tag = &after[..tag_end];
+        let fields: Vec<&str> = tag.split(',').map(str::trim).collect();
+
+        let parsed = match fields.as_slice() {
+            [group, kind] => group.parse().ok().zip(kind.parse().ok()).map(|(group, kind)| (group, kind, Vec::new())),
+            [group, kind, params] => group
+                .parse()
+                .ok()
+                .zip(kind.parse().ok())
+                .and_then(|(group, kind)| parse_hex(params).map(|params| (group, kind, params))),
+            _ => None,
+        };
+
+        let consumed = tag_start + 2 + tag_end + 2;
+
+        match parsed {
+            Some((group, kind, params)) if params.len() <= u16::MAX as usize => {
+                if !literal.is_empty() {
+                    segments.push(TextSegment::Text(std::mem::take(&mut literal)));
+                }
+                segments.push(TextSegment::Tag { group, kind, params });
+            },
+            _ => {
+                document.report(
+                    Severity::Warning,
+                    position_of(tag_offset),
+                    format!(
+                        "Control tag '{{{{{}}}}}' should look like {{{{group,type}}}} or {{{{group,type,hex params}}}}, it is kept as text. Write '\\{{{{' to have braces in the text.",
+                        tag
+                    ),
+                );
+                literal.push_str(&rest[tag_start..consumed]);
+            },
+        }
+
+        rest = &rest[consumed..];
+        offset += consumed;
+    }
+
+    literal.push_str(rest);
+
+    if !literal.is_empty() {
+        segments.push(TextSegment::Text(literal));
+    }
+
+    XmsbtText(segments)
+}
+
+/// A label that several XMSBT files give a value to, the last one being the one that is used
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateLabel {
+    pub label: String,
+    pub patches: Vec<PathBuf>,
+}
+
+impl DuplicateLabel {
+    pub fn winner(&self) -> &Path {
+        self.patches.last().unwrap()
+    }
+}
+
+/// Find the labels set by more than one of the files, in the order they are applied.
+/// Renames and removals are applied in order as well, so they are not considered duplicates.
+pub fn find_duplicate_labels<'a>(documents: impl IntoIterator<Item = (&'a Path, &'a XmsbtDocument)>) -> Vec<DuplicateLabel> {
+    let mut duplicates: Vec<DuplicateLabel> = Vec::new();
+    let mut indices: HashMap<&str, usize> = HashMap::new();
+
+    for (path, document) in documents {
+        for entry in document.entries.iter().filter(|entry| entry.sets_label()) {
+            match indices.get(entry.label.as_str()) {
+                Some(index) => {
+                    let duplicate = &mut duplicates[*index];
+                    if duplicate.patches.last().map(|last| last != path).unwrap_or(true) {
+                        duplicate.patches.push(path.to_path_buf());
+                    }
+                },
+                None => {
+                    indices.insert(&entry.label, duplicates.len());
+                    duplicates.push(DuplicateLabel {
+                        label: entry.label.clone(),
+                        patches: vec![path.to_path_buf()],
+                    });
+                },
+            }
+        }
+    }
+
+    duplicates.retain(|duplicate| duplicate.patches.len() > 1);
+
+    duplicates
+}
+
+#[derive(Debug, Serialize)]
+pub struct XmsbtFileReport {
+    pub path: PathBuf,
+    pub diagnostics: Vec<XmsbtDiagnostic>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct XmsbtCheckReport {
+    pub files: Vec<XmsbtFileReport>,
+    pub duplicate_labels: Vec<DuplicateLabel>,
+}
+
+impl XmsbtCheckReport {
+    pub fn has_errors(&self) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error))
+    }
+}
+
+/// Check XMSBT files the way they would be read when patching, in the order they would be applied
+pub fn check_files(paths: &[PathBuf]) -> XmsbtCheckReport {
+    let documents: Vec<(&Path, XmsbtDocument)> = paths.iter().map(|path| (path.as_path(), parse_file(path))).collect();
+
+    XmsbtCheckReport {
+        duplicate_labels: find_duplicate_labels(documents.iter().map(|(path, document)| (*path, document))),
+        files: documents
+            .into_iter()
+            .map(|(path, document)| XmsbtFileReport {
+                path: path.to_path_buf(),
+                diagnostics: document.diagnostics,
+            })
+            .collect(),
+    }
+}
+
+impl fmt::Display for XmsbtCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in self.files.iter() {
+            if file.diagnostics.is_empty() {
+                writeln!(f, "{}: ok", file.path.display())?;
+                continue;
+            }
+
+            writeln!(f, "{}:", file.path.display())?;
+
+            for diagnostic in file.diagnostics.iter() {
+                let severity = match diagnostic.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                writeln!(f, "  {}: {}", severity, diagnostic)?;
+            }
+        }
+
+        for duplicate in self.duplicate_labels.iter() {
+            let patches: Vec<String> = duplicate.patches.iter().map(|path| path.display().to_string()).collect();
+            writeln!(
+                f,
+                "Label {} is set by {}, the value from {} is used.",
+                duplicate.label,
+                patches.join(", "),
+                duplicate.winner().display()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> (XmsbtText, XmsbtDocument) {
+        let mut document = XmsbtDocument::default();
+        let text = parse_text(text, TextPosition { row: 4, column: 10 }, &mut document);
+
+        (text, document)
+    }
+
+    fn positions(document: &XmsbtDocument) -> Vec<(Severity, u64, u64)> {
+        document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.line, diagnostic.column))
+            .collect()
+    }
+
+    #[test]
+    fn tags_are_split_from_the_text() {
+        let (parsed, document) = text("Press {{0,3}} to {{1, 2, 0A 0b}}jump");
+
+        assert!(document.diagnostics.is_empty());
+        assert_eq!(
+            parsed.0,
+            [
+                TextSegment::Text("Press ".into()),
+                TextSegment::Tag {
+                    group: 0,
+                    kind: 3,
+                    params: vec![]
+                },
+                TextSegment::Text(" to ".into()),
+                TextSegment::Tag {
+                    group: 1,
+                    kind: 2,
+                    params: vec![0x0A, 0x0B]
+                },
+                TextSegment::Text("jump".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_braces_are_text() {
+        let (parsed, document) = text(r"\{{0,3}} and \\{{0,3}}");
+
+        assert!(document.diagnostics.is_empty());
+        assert_eq!(
+            parsed.0,
+            [
+                TextSegment::Text(r"{{0,3}} and \".into()),
+                TextSegment::Tag {
+                    group: 0,
+                    kind: 3,
+                    params: vec![]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_tags_are_kept_as_text() {
+        let (parsed, document) = text("a {{smile}} b {{0,3}} c {{1,2");
+
+        assert_eq!(
+            parsed.0,
+            [
+                TextSegment::Text("a {{smile}} b ".into()),
+                TextSegment::Tag {
+                    group: 0,
+                    kind: 3,
+                    params: vec![]
+                },
+                TextSegment::Text(" c {{1,2".into()),
+            ]
+        );
+        assert_eq!(positions(&document), [(Severity::Warning, 5, 13), (Severity::Warning, 5, 35)]);
+    }
+
+    #[test]
+    fn diagnostics_point_at_the_tag_on_later_lines() {
+        let (_, document) = text("first line\n  {{0,x}}\nthird {{é}} {{1,2,ZZ}}");
+
+        assert_eq!(
+            positions(&document),
+            [(Severity::Warning, 6, 3), (Severity::Warning, 7, 7), (Severity::Warning, 7, 13)]
+        );
+    }
+
+    #[test]
+    fn diagnostics_of_a_file_have_lines_and_columns() {
+        let xml =
+            "<xmsbt>\n  <entry label=\"A\">\n    <text>Hi {{bad}}</text>\n  </entry>\n  <entry>\n    <text>No label</text>\n  </entry>\n</xmsbt>";
+        let document = parse(xml.as_bytes());
+
+        assert_eq!(document.entries.len(), 1);
+        assert_eq!(document.entries[0].text.as_ref().unwrap().0, [TextSegment::Text("Hi {{bad}}".into())]);
+        assert_eq!(document.entries[0].line, 2);
+        assert_eq!(document.entries[0].column, 3);
+        assert_eq!(positions(&document), [(Severity::Warning, 3, 14), (Severity::Error, 5, 3)]);
+        assert_eq!(document.diagnostics[0].to_string().split(':').next(), Some("line 3, column 14"));
+    }
+
+    #[test]
+    fn invalid_xml_skips_the_file() {
+        let document = parse("<xmsbt>\n  <entry label=\"A\"><text>Hi</text></entry>\n  <entry label=\"B\">\n</xmsbt>".as_bytes());
+
+        assert!(document.entries.is_empty());
+        assert_eq!(document.diagnostics.len(), 1);
+        assert_eq!(document.diagnostics[0].severity, Severity::Error);
+        assert_eq!(document.diagnostics[0].line, 4);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    hash::{Hash, Hasher},
+    io::Read,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use smash_arc::Hash40;
+
+use super::patch_format::PatchFormat;
+
+/// Bump this whenever a built-in format changes how it builds a file, so older outputs are discarded instead of reused
+const PATCH_CACHE_VERSION: u32 = 1;
+
+fn hash_metadata<H: Hasher>(path: &Path, hasher: &mut H) -> Option<()> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    path.hash(hasher);
+    metadata.len().hash(hasher);
+    modified.hash(hasher);
+
+    Some(())
+}
+
+/// Make a cache key for a patched file from the file it is built on and every patch applied to it, with their sizes and modification times.
+///
+/// Returns None if the output can't be cached, because the format doesn't allow it or the SD card doesn't provide modification times.
+pub fn compute_patch_cache_key(format: &dyn PatchFormat, local: &Path, hash: Hash40, patches: &[PathBuf]) -> Option<u64> {
+    if !format.is_cacheable() {
+        return None;
+    }
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+
+    // Include the ARCropolis version and the cache layout so updates invalidate the cache
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    PATCH_CACHE_VERSION.hash(&mut hasher);
+    format.name().hash(&mut hasher);
+    hash.0.hash(&mut hasher);
+
+    // Vanilla files depend on the game version, which the cache folder already accounts for, and on the region
+    config::region().to_string().hash(&mut hasher);
+
+    for local in std::iter::once(local.to_path_buf()).chain(format.dependencies(local)) {
+        let base = crate::fs::read_filesystem(|filesystem| {
+            let cached = filesystem.get();
+
+            // Same check as the one that picks the base file when patching
+            cached.get_patch_entry_type(&local).is_ok().then(|| cached.query_actual_path(&local))
+        });
+
+        match base {
+            Some(path) => hash_metadata(&path?, &mut hasher)?,
+            None => "vanilla".hash(&mut hasher),
+        }
+    }
+
+    for patch in patches {
+        hash_metadata(patch, &mut hasher)?;
+    }
+
+    // Settings that change how the built-in formats merge their patches
+    config::prc_merge_policy().as_str().hash(&mut hasher);
+    config::prc_row_keys().hash(&mut hasher);
+    config::motion_list_replace_with_diffs().hash(&mut hasher);
+
+    Some(hasher.finish())
+}
+
+fn cache_dir() -> PathBuf {
+    crate::utils::paths::cache().join("patched").into()
+}
+
+fn cache_path(format: &dyn PatchFormat, hash: Hash40) -> PathBuf {
+    cache_dir().join(format!("{}-{:016x}.bin", format.name(), hash.0))
+}
+
+/// Read the patched file built during an earlier boot, if it was built from the same files
+pub fn load_patched_file(format: &dyn PatchFormat, hash: Hash40, key: u64) -> Option<Vec<u8>> {
+    let bytes = std::fs::read(cache_path(format, hash)).ok()?;
+    if bytes.len() < 8 {
+        return None;
+    }
+    let mut key_bytes = [0u8; 8];
+    key_bytes.copy_from_slice(&bytes[..8]);
+    if u64::from_le_bytes(key_bytes) != key {
+        return None;
+    }
+
+    Some(bytes[8..].to_vec())
+}
+
+/// Size of the patched file built during an earlier boot, if it was built from the same files, without reading all of it
+pub fn cached_size(format: &dyn PatchFormat, hash: Hash40, key: u64) -> Option<usize> {
+    let mut file = std::fs::File::open(cache_path(format, hash)).ok()?;
+    let mut key_bytes = [0u8; 8];
+    file.read_exact(&mut key_bytes).ok()?;
+    if u64::from_le_bytes(key_bytes) != key {
+        return None;
+    }
+
+    Some(file.metadata().ok()?.len() as usize - 8)
+}
+
+pub fn save_patched_file(format: &dyn PatchFormat, hash: Hash40, key: u64, data: &[u8]) {
+    let mut bytes = Vec::with_capacity(8 + data.len());
+    bytes.extend_from_slice(&key.to_le_bytes());
+    bytes.extend_from_slice(data);
+
+    let path = cache_path(format, hash);
+
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    if let Err(e) = std::fs::write(&path, &bytes) {
+        warn!("Failed to write the patched file to the cache: {:?}", e);
+    }
+}
+
+/// Remove every cached patched file, so that they are built again the next time they are loaded
+pub fn clear_patch_cache() {
+    let path = cache_dir();
+
+    if path.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&path) {
+            warn!("Failed to remove the patch cache: {:?}", e);
+        }
+    }
+}
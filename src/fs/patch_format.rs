@@ -0,0 +1,189 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use thiserror::Error;
+
+use super::{loaders::formats, ApiLoaderError};
+
+/// A kind of patch file, which ARCropolis merges into the file it targets when the game loads it.
+///
+/// Every registered format drives the collection of its patches during discovery, their registration to the API loader
+/// and the building of the patched file.
+pub trait PatchFormat: Send + Sync {
+    /// Unique name of the format. The files it patches are loaded through the `api:/patch-<name>` root.
+    fn name(&self) -> &str;
+
+    /// Endings of the file names that are patches of this format, like an extension or a full file name
+    fn suffixes(&self) -> &[&str];
+
+    /// Whether patches for another region can be picked through the region fallback chain
+    fn has_region_fallback(&self) -> bool {
+        false
+    }
+
+    /// Whether the patched file only depends on the file it is built on and the patches, so it can be cached between boots
+    fn is_cacheable(&self) -> bool {
+        true
+    }
+
+    /// Name of another format that the patches of this one also apply to, for files that have to change along with the ones they target
+    fn companion(&self) -> Option<&str> {
+        None
+    }
+
+    /// Other files the patched file is built from, whose base file is part of the patch cache key
+    fn dependencies(&self, _local: &Path) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    fn is_patch(&self, name: &str) -> bool {
+        self.suffixes().iter().any(|suffix| name.ends_with(suffix))
+    }
+
+    /// Local path of the file a patch applies to, once its regional suffix is removed.
+    /// Returns `None` if the patch doesn't target a file this format can patch.
+    fn target_path(&self, local: &Path) -> Option<PathBuf>;
+
+    /// Build the patched file by applying every patch in order
+    fn merge(&self, local: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, ApiLoaderError>;
+
+    /// Upper bound of the size of the patched file, used to size it when it can't be built ahead of time.
+    /// Returns `None` if the format can't tell, in which case a multiple of the vanilla size is reserved.
+    fn estimate_size(&self, _base_size: usize, _patches: &[PathBuf]) -> Option<usize> {
+        None
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PatchFormatError {
+    #[error("A patch format named '{0}' is already registered.")]
+    DuplicateName(String),
+
+    #[error("Patch format '{0}' does not handle any file.")]
+    NoSuffix(String),
+}
+
+/// Formats are only ever added, so their index can be used to refer to them
+static PATCH_FORMATS: LazyLock<RwLock<Vec<Arc<dyn PatchFormat>>>> = LazyLock::new(|| RwLock::new(formats::builtin()));
+
+/// Add a patch format to the registry. It is only used for the mods discovered after it is registered.
+pub fn register(format: Arc<dyn PatchFormat>) -> Result<usize, PatchFormatError> {
+    let mut formats = PATCH_FORMATS.write().unwrap();
+
+    if formats.iter().any(|registered| registered.name() == format.name()) {
+        return Err(PatchFormatError::DuplicateName(format.name().to_string()));
+    }
+
+    if format.suffixes().is_empty() {
+        return Err(PatchFormatError::NoSuffix(format.name().to_string()));
+    }
+
+    formats.push(format);
+
+    Ok(formats.len() - 1)
+}
+
+pub fn get(index: usize) -> Option<Arc<dyn PatchFormat>> {
+    PATCH_FORMATS.read().unwrap().get(index).cloned()
+}
+
+/// Index of the format with this name
+pub fn position(name: &str) -> Option<usize> {
+    PATCH_FORMATS.read().unwrap().iter().position(|format| format.name() == name)
+}
+
+/// Index of the format whose patched files are loaded through the root
+pub fn position_for_root(root: &Path) -> Option<usize> {
+    position(root.to_str()?.strip_prefix("api:/patch-")?)
+}
+
+/// Root of the API tree that the files patched by the format are loaded through
+pub fn root(format: &dyn PatchFormat) -> PathBuf {
+    PathBuf::from(format!("api:/patch-{}", format.name()))
+}
+
+/// The first format that the file is a patch of, in registration order
+pub fn find(local: &Path) -> Option<Arc<dyn PatchFormat>> {
+    let name = local.file_name()?.to_str()?;
+
+    PATCH_FORMATS.read().unwrap().iter().find(|format| format.is_patch(name)).cloned()
+}
+
+/// Whether the format would apply the patch to the file
+fn patches(format: &dyn PatchFormat, patch: &Path, local: &Path) -> bool {
+    patch.file_name().and_then(|name| name.to_str()).map(|name| format.is_patch(name)).unwrap_or(false)
+        && format.target_path(patch).as_deref() == Some(local)
+}
+
+/// Suffix of a patch that could be shipped instead of the file, from the first format that can patch it in registration order
+pub fn patch_suffix_for(local: &Path) -> Option<String> {
+    let name = local.file_name()?.to_str()?;
+
+    PATCH_FORMATS.read().unwrap().iter().find_map(|format| {
+        format
+            .suffixes()
+            .iter()
+            .find(|suffix| {
+                // Patches either swap the extension of the file, keep its name with the suffix added, or are named after the suffix
+                [
+                    local.with_extension(suffix),
+                    local.with_file_name(format!("{}.{}", name, suffix)),
+                    local.with_file_name(suffix),
+                ]
+                .iter()
+                .any(|patch| patches(format.as_ref(), patch, local))
+            })
+            .map(|suffix| suffix.to_string())
+    })
+}
+
+/// Whether discovery should collect the file as a patch
+pub fn is_patch_file(name: &str) -> bool {
+    PATCH_FORMATS.read().unwrap().iter().any(|format| format.is_patch(name))
+}
+
+/// Whether the file is a patch that can be picked through the region fallback chain
+pub fn has_region_fallback(name: &str) -> bool {
+    PATCH_FORMATS
+        .read()
+        .unwrap()
+        .iter()
+        .any(|format| format.has_region_fallback() && format.is_patch(name))
+}
+
+/// Name and suffixes of every registered format, which decide what discovery collects
+pub fn signature() -> Vec<(String, Vec<String>)> {
+    PATCH_FORMATS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|format| (format.name().to_string(), format.suffixes().iter().map(|suffix| suffix.to_string()).collect()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_suffix_swaps_the_extension() {
+        assert_eq!(patch_suffix_for(Path::new("fighter/mario/param/vl.prc")).as_deref(), Some("prcx"));
+        assert_eq!(patch_suffix_for(Path::new("stage/common/normal/param/x.stdat")).as_deref(), Some("stdatx"));
+        assert_eq!(patch_suffix_for(Path::new("ui/message/msg_name.msbt")).as_deref(), Some("xmsbt"));
+        assert_eq!(patch_suffix_for(Path::new("sound/bank/fighter/se_mario.nus3audio")).as_deref(), Some("patch3audio"));
+        assert_eq!(patch_suffix_for(Path::new("motion/fighter/mario/motion_list.bin")).as_deref(), Some("motdiff"));
+    }
+
+    #[test]
+    fn patch_suffix_is_the_file_name() {
+        assert_eq!(patch_suffix_for(Path::new("sound/config/bgm_property.bin")).as_deref(), Some("bgm_property.bin"));
+    }
+
+    #[test]
+    fn delta_patches_target_any_file() {
+        assert_eq!(patch_suffix_for(Path::new("fighter/mario/model/body/c00/model.numdlb")).as_deref(), Some("bsdiff"));
+        assert_eq!(patch_suffix_for(Path::new("fighter/mario/model/body/c00/model")), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use smash_arc::Hash40;
+
+/// The file that patches were applied on top of
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "path", rename_all = "snake_case")]
+pub enum PatchBase {
+    Vanilla,
+    /// A file replaced by a mod, with its path on the SD card
+    Mod(Option<PathBuf>),
+}
+
+/// What happened the last time a file was built from its patches
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchTrace {
+    pub hash: u64,
+    pub path: PathBuf,
+    /// Name of the patch format that built the file
+    pub format: String,
+    /// Not known if the format failed before loading it, or if the file was loaded from the patch cache
+    pub base: Option<PatchBase>,
+    /// Patch files given to the format, in the order they are applied
+    pub patches: Vec<PathBuf>,
+    /// What the format reported while applying the patches, like conflicts or skipped patches
+    pub notes: Vec<String>,
+    pub base_size: usize,
+    pub patched_size: usize,
+    pub duration_us: u64,
+    /// Why the file could not be patched, in which case the game loads it without the patches
+    pub error: Option<String>,
+}
+
+static PATCH_TRACES: LazyLock<Mutex<HashMap<Hash40, PatchTrace>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+thread_local! {
+    /// Trace of the file being patched on this thread, filled while the format runs
+    static CURRENT_TRACE: RefCell<Option<PatchTrace>> = RefCell::new(None);
+}
+
+/// Start tracing a file that is about to be built by a patch format
+pub fn begin(hash: Hash40, local: &Path, format: &str, patches: &[PathBuf]) {
+    let trace = PatchTrace {
+        hash: hash.0,
+        path: local.to_path_buf(),
+        format: format.to_string(),
+        base: None,
+        patches: patches.to_vec(),
+        notes: Vec::new(),
+        base_size: 0,
+        patched_size: 0,
+        duration_us: 0,
+        error: None,
+    };
+
+    CURRENT_TRACE.with(|current| *current.borrow_mut() = Some(trace));
+}
+
+/// Record the file the patches are applied to. Does nothing if no file is being patched.
+pub fn record_base(base: PatchBase, size: usize) {
+    CURRENT_TRACE.with(|current| {
+        if let Some(trace) = current.borrow_mut().as_mut() {
+            trace.base = Some(base);
+            trace.base_size = size;
+        }
+    });
+}
+
+/// Add something the format noticed while applying the patches to the trace. Does nothing if no file is being patched.
+pub fn note(message: String) {
+    CURRENT_TRACE.with(|current| {
+        if let Some(trace) = current.borrow_mut().as_mut() {
+            trace.notes.push(message);
+        }
+    });
+}
+
+/// Store the trace of the file being patched, replacing the one from the last time it was loaded
+pub fn finish(duration: Duration, result: Result<usize, String>) {
+    let trace = CURRENT_TRACE.with(|current| current.borrow_mut().take());
+
+    if let Some(mut trace) = trace {
+        trace.duration_us = duration.as_micros() as u64;
+
+        match result {
+            Ok(size) => trace.patched_size = size,
+            Err(err) => trace.error = Some(err),
+        }
+
+        PATCH_TRACES.lock().unwrap().insert(Hash40(trace.hash), trace);
+    }
+}
+
+pub fn get(hash: Hash40) -> Option<PatchTrace> {
+    PATCH_TRACES.lock().unwrap().get(&hash).cloned()
+}
+
+/// Every trace, ordered by path
+pub fn all() -> Vec<PatchTrace> {
+    let mut traces: Vec<PatchTrace> = PATCH_TRACES.lock().unwrap().values().cloned().collect();
+    traces.sort_by(|a, b| a.path.cmp(&b.path));
+
+    traces
+}
+
+/// Default location of the trace dump
+pub fn dump_path() -> PathBuf {
+    crate::utils::paths::logs().join("patch_trace.json").into()
+}
+
+/// Write every trace to a JSON file
+pub fn dump(path: &Path) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(&all()).map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+
+    std::fs::write(path, json)
+}
@@ -0,0 +1,161 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use orbits::ConflictHandler;
+use serde::Serialize;
+#[cfg(target_os = "switch")]
+use skyline::nn;
+use smash_arc::Hash40;
+#[cfg(target_os = "switch")]
+use smash_arc::ArcLookup;
+
+use super::{active_roots, discover_mods, patch_cache::clear_patch_cache, refresh_mod_paths, utils, CachedFilesystem};
+#[cfg(target_os = "switch")]
+use super::GlobalFilesystem;
+use crate::{replacement, resource};
+#[cfg(target_os = "switch")]
+use crate::{hashes, resource::LoadState};
+
+/// Why a file that changed on the SD card could not be picked up without rebooting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ReloadSkip {
+    /// The file does not exist in the data.arc yet, so it has to be added to the file tables
+    Added,
+    /// The file is the target of patch files, which are only merged at boot
+    Patched,
+    /// The file is shared with other ones, so it has to be unshared from them
+    Shared,
+    /// The file is bigger than the size its FileData was patched to at boot
+    TooLarge { size: usize, max_size: usize },
+    /// The file was removed from the mods, and the game still expects the modded size
+    Removed,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    /// Files that will be read from the SD card again the next time they load
+    pub reloaded: Vec<Hash40>,
+    /// Reloaded files that were loaded, and got written over the buffer the game keeps them in
+    pub invalidated: Vec<Hash40>,
+    /// Reloaded files the game is currently using, they are picked up once the game lets go of them
+    pub in_use: Vec<Hash40>,
+    pub skipped: Vec<(Hash40, ReloadSkip)>,
+}
+
+impl CachedFilesystem {
+    /// Discover the active mods again and pick up the files that can be swapped without editing the file tables.
+    /// Anything else is reported as skipped, and only changes on the next boot.
+    pub fn reload(&mut self) -> ReloadReport {
+        let backend = resource::backend();
+        let region = config::region();
+
+        refresh_mod_paths();
+
+        let (roots, _) = active_roots();
+        let (launchpad, _) = discover_mods(&roots, ConflictHandler::NoRoot);
+
+        let (sizes, paths, _) = utils::make_hash_maps_and_nus3bank_deps(launchpad.tree(), &self.config.unshare_blacklist);
+
+        // The orbit still walks the trees from boot, so keep track of where the files come from now
+        let mut full_paths: HashMap<PathBuf, PathBuf> = HashMap::new();
+        launchpad.tree().walk_paths(|node, entry_type| {
+            if entry_type.is_file() {
+                full_paths.insert(node.get_local().to_path_buf(), node.full_path());
+            }
+        });
+
+        let patch_targets = self.patch_targets();
+
+        let mut report = ReloadReport::default();
+
+        for (hash, local) in paths.iter() {
+            let skip = if patch_targets.contains_key(hash) {
+                Some(ReloadSkip::Patched)
+            } else if !backend.contains_file(*hash) {
+                Some(ReloadSkip::Added)
+            } else if !self.hash_lookup.contains_key(hash) && replacement::lookup::is_shared_file(*hash) {
+                Some(ReloadSkip::Shared)
+            } else {
+                let size = sizes.get(hash).copied().unwrap_or_default();
+                match backend.decompressed_size(*hash, region) {
+                    Ok(max_size) if size > max_size => Some(ReloadSkip::TooLarge { size, max_size }),
+                    Ok(_) => None,
+                    Err(_) => Some(ReloadSkip::Added),
+                }
+            };
+
+            if let Some(skip) = skip {
+                // Patched files show up in every reload, only report them when they are not already handled
+                if skip != ReloadSkip::Patched {
+                    report.skipped.push((*hash, skip));
+                }
+                continue;
+            }
+
+            let full_path = match full_paths.get(local) {
+                Some(full_path) => full_path.clone(),
+                None => continue,
+            };
+
+            // Files that only start being modded now weren't patched, so their size fits the vanilla one like at boot
+            self.hash_size_cache.entry(*hash).or_insert_with(|| sizes[hash]);
+            self.hash_lookup.insert(*hash, local.clone());
+            self.reloaded_paths.insert(*hash, full_path);
+
+            report.reloaded.push(*hash);
+        }
+
+        for (hash, path) in self.hash_lookup.iter() {
+            if !paths.contains_key(hash) && self.hash(*hash).map(|full| full.starts_with(crate::utils::paths::mods())).unwrap_or(false) {
+                warn!("'{}' was removed from the mods, it will keep being loaded until the next boot.", path.display());
+                report.skipped.push((*hash, ReloadSkip::Removed));
+            }
+        }
+
+        // Files were edited on the SD card, which doesn't always show in the times the patch cache is keyed on
+        clear_patch_cache();
+
+        info!("Reloaded {} files, {} files need a reboot.", report.reloaded.len(), report.skipped.len());
+
+        report
+    }
+}
+
+/// Write the reloaded files over the buffers the game already loaded them into, so they change without going through the loading hooks.
+/// Going through the hooks would mean dropping the buffers, and only the resource service knows how to free them.
+#[cfg(target_os = "switch")]
+pub(crate) fn refresh_loaded_files(fs: &mut GlobalFilesystem, report: &mut ReloadReport) {
+    let arc = resource::arc();
+    let filesystem_info = resource::filesystem_info();
+
+    // Keeps the game from picking up a file while it is being written to
+    unsafe { nn::os::LockMutex(filesystem_info.mutex) };
+
+    for hash in report.reloaded.iter() {
+        let file_path_index = match arc.get_file_path_index_from_hash(*hash) {
+            Ok(index) => usize::from(index),
+            Err(_) => continue,
+        };
+
+        let loaded_filepath = &filesystem_info.get_loaded_filepaths()[file_path_index];
+
+        if loaded_filepath.is_loaded == 0 {
+            continue;
+        }
+
+        let loaded_data = &filesystem_info.get_loaded_datas()[loaded_filepath.loaded_data_index as usize];
+
+        if loaded_data.state != LoadState::Loaded || loaded_data.ref_count.load(std::sync::atomic::Ordering::SeqCst) != 0 {
+            debug!("'{}' ({:#x}) is in use, it will be reloaded once it is released.", hashes::find(*hash), hash.0);
+            report.in_use.push(*hash);
+            continue;
+        }
+
+        if replacement::threads::replace_loaded_data(fs, *hash) {
+            report.invalidated.push(*hash);
+        }
+    }
+
+    unsafe { nn::os::UnlockMutex(filesystem_info.mutex) };
+
+    info!("{} of the reloaded files were replaced in memory.", report.invalidated.len());
+}
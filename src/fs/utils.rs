@@ -8,7 +8,10 @@ use arc_config::ToExternal;
 use orbits::{FileLoader, Tree};
 use smash_arc::Hash40;
 
-use super::{ApiCallback, ApiLoader};
+use super::{
+    patch_format::{self, PatchFormat},
+    ApiCallback, ApiLoader,
+};
 use crate::{hashes, PathExtension};
 
 /// Single tree walk that builds hash maps (size + path) and collects nus3bank dependencies.
@@ -80,6 +83,58 @@ where
     (size_map, path_map, nus3audio_deps)
 }
 
+/// Region a file targets with its `+region` suffix, if it has one
+fn regional_suffix(local: &Path) -> Option<&str> {
+    let name = local.file_name()?.to_str()?;
+    let index = name.find('+')?;
+
+    name.get(index + 1..index + 6)
+}
+
+/// Pick which regional variant of every patch each root uses, following the region fallback chain.
+///
+/// Returns the indices of the collected paths that should not be applied, either because the root has a
+/// variant that comes earlier in the chain, or because their region isn't part of the chain at all.
+pub fn rejected_regional_patches(collected: &[(PathBuf, PathBuf)]) -> HashSet<usize> {
+    let chain = config::region_fallback_chain();
+
+    let rank = |local: &Path| {
+        let region = regional_suffix(local).unwrap_or(config::ANY_REGION);
+        chain.iter().position(|link| link == region)
+    };
+
+    // Variants of a patch only differ by their suffix, so group them by root and by the path without it
+    let mut variants: HashMap<(&Path, PathBuf), Vec<usize>> = HashMap::new();
+
+    for (i, (root, local)) in collected.iter().enumerate() {
+        let name = match local.file_name().and_then(|name| name.to_str()) {
+            Some(name) if patch_format::has_region_fallback(name) => name,
+            _ => continue,
+        };
+
+        let base_name = match (name.find('+'), regional_suffix(local)) {
+            (Some(index), Some(region)) => format!("{}{}", &name[..index], &name[index + 1 + region.len()..]),
+            _ => name.to_string(),
+        };
+
+        variants.entry((root.as_path(), local.with_file_name(base_name))).or_default().push(i);
+    }
+
+    let mut rejected = HashSet::new();
+
+    for indices in variants.into_values() {
+        let best = indices.iter().filter_map(|&i| rank(&collected[i].1).map(|rank| (rank, i))).min();
+
+        for i in indices {
+            if best.map(|(_, best)| best != i).unwrap_or(true) {
+                rejected.insert(i);
+            }
+        }
+    }
+
+    rejected
+}
+
 pub fn add_file_to_api_tree<P: AsRef<Path>, Q: AsRef<Path>>(
     tree: &mut Tree<ApiLoader>,
     root: P,
@@ -101,98 +156,24 @@ pub fn add_file_to_api_tree<P: AsRef<Path>, Q: AsRef<Path>>(
     }
 }
 
-/// Adds a PRC patch file and information to the API loader
-pub fn add_prc_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, phys_root: P, local: Q) -> Option<Hash40> {
+/// Adds a patch file of the format and information to the API loader
+pub fn add_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, format: &dyn PatchFormat, phys_root: P, local: Q) -> Option<Hash40> {
     let local = local.as_ref();
-    let base_local = if local.has_extension("prcx") || local.has_extension("prcxml") {
-        // patch files have different extensions
-        local.with_extension("prc")
-    } else if local.has_extension("stdatx") || local.has_extension("stdatxml") {
-        local.with_extension("stdat")
-    } else if local.has_extension("stprmx") || local.has_extension("stprmxml") {
-        local.with_extension("stprm")
-    } else {
-        unreachable!()
-    };
-    let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
-        } else {
-            base_local
-        }
-    } else {
-        base_local
-    };
     let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
-    match base_local.smash_hash() {
-        Ok(hash) => {
-            tree.insert_file("api:/patch-prc", &base_local);
-            tree.loader.push_entry(hash, Path::new("api:/patch-prc"), ApiCallback::None);
-            // We need to add our file to the vector of patch files
-            tree.loader.insert_prc_patch(hash, &full_path);
-            if let Some(local) = local.to_str() {
-                hashes::add(local);
-            }
-            if let Some(base_local) = base_local.to_str() {
-                hashes::add(base_local);
-            }
-            Some(hash)
-        },
-        Err(e) => {
-            error!("Could not add file {} to API tree. Reason: {:?}", full_path.display(), e);
-            None
-        },
-    }
-}
 
-/// Adds a MSBT patch file and information to the API loader
-pub fn add_msbt_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, phys_root: P, local: Q) -> Option<Hash40> {
-    let local = local.as_ref();
-    let base_local = local.with_extension("msbt"); // patch files have different extensions
-    let mut is_current_region = true;
-
-    let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            is_current_region = (&name[idx + 1..idx + 6] == format!("{}", config::region())); //Check if XMSBT's region is current region
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
-        } else {
-            base_local
-        }
-    } else {
-        base_local
-    };
-    let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
-    match base_local.smash_hash() {
-        Ok(hash) => {
-            tree.insert_file("api:/patch-msbt", &base_local);
-            tree.loader.push_entry(hash, Path::new("api:/patch-msbt"), ApiCallback::None);
-            // We need to add our file to the vector of patch files
-            tree.loader.insert_msbt_patch(hash, &full_path);
-            if let Some(local) = local.to_str() {
-                hashes::add(local);
-            }
-            if let Some(base_local) = base_local.to_str() {
-                if is_current_region {
-                    hashes::add(base_local);
-                }
-            }
-            Some(hash)
+    let base_local = match format.target_path(local) {
+        Some(base_local) => base_local,
+        None => {
+            error!(
+                "Could not add file {} to API tree. Reason: This is not a valid {} patch.",
+                full_path.display(),
+                format.name()
+            );
+            return None;
         },
-        Err(e) => {
-            error!("Could not add file {} to API tree. Reason: {:?}", full_path.display(), e);
-            None
-        },
-    }
-}
-
-pub fn add_nus3audio_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, phys_root: P, local: Q) -> Option<Hash40> {
-    let local = local.as_ref();
-    let base_local = local.with_extension("nus3audio");
+    };
 
+    // Patches for other regions only make it here when the fallback chain picked them for the current region
     let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
         if let Some(idx) = name.find('+') {
             let mut new_name = name.to_string();
@@ -204,13 +185,14 @@ pub fn add_nus3audio_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLo
     } else {
         base_local
     };
-    let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
+
     match base_local.smash_hash() {
         Ok(hash) => {
-            tree.insert_file("api:/patch-nus3audio", &base_local);
-            tree.loader.push_entry(hash, Path::new("api:/patch-nus3audio"), ApiCallback::None);
+            let root = patch_format::root(format);
+            tree.insert_file(&root, &base_local);
+            tree.loader.push_entry(hash, &root, ApiCallback::None);
             // We need to add our file to the vector of patch files
-            tree.loader.insert_nus3audio_patch(hash, &full_path);
+            tree.loader.insert_patch(format.name(), hash, &full_path);
             if let Some(local) = local.to_str() {
                 hashes::add(local);
             }
@@ -225,95 +207,3 @@ pub fn add_nus3audio_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLo
         },
     }
 }
-
-pub fn add_motionlist_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, phys_root: P, local: Q) -> Option<Hash40> {
-    let local = local.as_ref();
-    let base_local = local.with_extension("bin");
-
-    let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
-        } else {
-            base_local
-        }
-    } else {
-        base_local
-    };
-    let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
-    if let Some(name) = full_path.file_name() {
-        if name.to_str().unwrap().contains(&"motion_list") {
-            match base_local.smash_hash() {
-                Ok(hash) => {
-                    tree.insert_file("api:/patch-motionlist", &base_local);
-                    tree.loader.push_entry(hash, Path::new("api:/patch-motionlist"), ApiCallback::None);
-                    // We need to add our file to the vector of patch files
-                    tree.loader.insert_motionlist_patch(hash, &full_path);
-                    if let Some(local) = local.to_str() {
-                        hashes::add(local);
-                    }
-                    if let Some(base_local) = base_local.to_str() {
-                        hashes::add(base_local);
-                    }
-                    return Some(hash);
-                },
-                Err(e) => {
-                    error!("Could not add file {} to API tree. Reason: {:?}", full_path.display(), e);
-                    return None;
-                },
-            }
-        }
-    }
-    error!(
-        "Could not add file {} to API tree. Reason: This is not a motion_list.bin file.",
-        full_path.display()
-    );
-    None
-}
-
-pub fn add_bgm_property_patch<P: AsRef<Path>, Q: AsRef<Path>>(tree: &mut Tree<ApiLoader>, phys_root: P, local: Q) -> Option<Hash40> {
-    let local = local.as_ref();
-    let base_local = local.with_extension("bin");
-
-    let base_local = if let Some(name) = base_local.file_name().and_then(|os_str| os_str.to_str()) {
-        if let Some(idx) = name.find('+') {
-            let mut new_name = name.to_string();
-            new_name.replace_range(idx..idx + 6, "");
-            base_local.with_file_name(new_name)
-        } else {
-            base_local
-        }
-    } else {
-        base_local
-    };
-    let full_path = phys_root.as_ref().join(local); // need the full path so that our API loader can load it
-    if let Some(name) = full_path.file_name() {
-        if name.to_str().unwrap().contains(&"bgm_property") {
-            match base_local.smash_hash() {
-                Ok(hash) => {
-                    tree.insert_file("api:/patch-bgm_property", &base_local);
-                    tree.loader.push_entry(hash, Path::new("api:/patch-bgm_property"), ApiCallback::None);
-                    // We need to add our file to the vector of patch files
-                    tree.loader.insert_bgm_property_patch(hash, &full_path);
-                    if let Some(local) = local.to_str() {
-                        hashes::add(local);
-                    }
-                    if let Some(base_local) = base_local.to_str() {
-                        hashes::add(base_local);
-                    }
-                    return Some(hash);
-                },
-                Err(e) => {
-                    error!("Could not add file {} to API tree. Reason: {:?}", full_path.display(), e);
-                    return None;
-                },
-            }
-        }
-    }
-    error!(
-        "Could not add file {} to API tree. Reason: This is not a bgm_property.bin file.",
-        full_path.display()
-    );
-    None
-}
@@ -1,26 +1,45 @@
-use std::{io::Write, path::PathBuf};
+use std::path::PathBuf;
 
 use nn_fuse::*;
 use orbits::FileEntryType;
 
-pub struct ModFileAccessor(PathBuf);
+use crate::fs::{direct, read_filesystem};
+
+pub struct ModFileAccessor {
+    path: PathBuf,
+    /// Path on the SD card of files loaded as is, which are read in ranges
+    full_path: Option<PathBuf>,
+    /// Other files are loaded once per open file, instead of on every read
+    data: Option<Vec<u8>>,
+}
 
 pub struct ModDirAccessor(PathBuf);
 
 pub struct ModFsAccessor;
 
 impl FileAccessor for ModFileAccessor {
-    fn read(&mut self, mut buffer: &mut [u8], offset: usize) -> Result<usize, AccessorResult> {
+    fn read(&mut self, buffer: &mut [u8], offset: usize) -> Result<usize, AccessorResult> {
         debug!(target: "no-mod-path", "ModFileAccessor::read - Buffer length: {:#x}", buffer.len());
 
-        let fs = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let file = fs.get().load(&self.0).map_err(|_| AccessorResult::Unexpected)?;
-        buffer.write(&file.as_slice()[offset..]).map_err(|_| AccessorResult::Unexpected)
+        if let Some(full_path) = self.full_path.as_ref() {
+            return direct::read_range(full_path, offset, buffer).map_err(|_| AccessorResult::Unexpected);
+        }
+
+        if self.data.is_none() {
+            let data = read_filesystem(|fs| fs.get().load(&self.path)).map_err(|_| AccessorResult::Unexpected)?;
+            self.data = Some(data);
+        }
+
+        let file = self.data.as_deref().unwrap_or_default();
+        let range = &file[offset.min(file.len())..];
+        let size = range.len().min(buffer.len());
+        buffer[..size].copy_from_slice(&range[..size]);
+
+        Ok(size)
     }
 
     fn get_size(&mut self) -> Result<usize, AccessorResult> {
-        let fs = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let size = fs.get().query_max_filesize(&self.0).map_or_else(|| Err(AccessorResult::Unexpected), Ok);
+        let size = read_filesystem(|fs| fs.get().query_max_filesize(&self.path)).map_or_else(|| Err(AccessorResult::Unexpected), Ok);
         if let Ok(size) = size {
             debug!(target: "no-mod-path", "ModFileAccessor::get_size - Size: {:#x}", size);
         } else {
@@ -32,35 +51,35 @@ impl FileAccessor for ModFileAccessor {
 
 impl DirectoryAccessor for ModDirAccessor {
     fn read(&mut self, buffer: &mut [DirectoryEntry]) -> Result<usize, AccessorResult> {
-        let fs = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        let children = fs.get().get_children(&self.0);
-        for (idx, path) in children.iter().enumerate() {
-            if idx >= buffer.len() {
-                break;
-            }
-
-            buffer[idx].path = path.to_path_buf();
-            let ty = match fs.get().get_virtual_entry_type(path) {
-                Err(_) => match fs.get().get_patch_entry_type(path) {
+        read_filesystem(|fs| {
+            let children = fs.get().get_children(&self.0);
+            for (idx, path) in children.iter().enumerate() {
+                if idx >= buffer.len() {
+                    break;
+                }
+
+                buffer[idx].path = path.to_path_buf();
+                let ty = match fs.get().get_virtual_entry_type(path) {
+                    Err(_) => match fs.get().get_patch_entry_type(path) {
+                        Ok(ty) => ty,
+                        Err(_) => return Err(AccessorResult::PathNotFound),
+                    },
                     Ok(ty) => ty,
-                    Err(_) => return Err(AccessorResult::PathNotFound),
-                },
-                Ok(ty) => ty,
-            };
-            match ty {
-                FileEntryType::File => match fs.get().query_max_filesize(path) {
-                    Some(size) => buffer[idx].ty = DirectoryEntryType::File(size as i64),
-                    None => return Err(AccessorResult::Unexpected),
-                },
-                FileEntryType::Directory => buffer[idx].ty = DirectoryEntryType::Directory,
+                };
+                match ty {
+                    FileEntryType::File => match fs.get().query_max_filesize(path) {
+                        Some(size) => buffer[idx].ty = DirectoryEntryType::File(size as i64),
+                        None => return Err(AccessorResult::Unexpected),
+                    },
+                    FileEntryType::Directory => buffer[idx].ty = DirectoryEntryType::Directory,
+                }
             }
-        }
-        Ok(children.len())
+            Ok(children.len())
+        })
     }
 
     fn get_entry_count(&mut self) -> Result<usize, AccessorResult> {
-        let fs = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        Ok(fs.get().get_children(&self.0).len())
+        Ok(read_filesystem(|fs| fs.get().get_children(&self.0).len()))
     }
 }
 
@@ -68,8 +87,7 @@ impl FileSystemAccessor for ModFsAccessor {
     fn get_entry_type(&self, path: &std::path::Path) -> Result<FsEntryType, AccessorResult> {
         debug!(target: "no-mod-path", "ModFsAccessor::get_entry_type - Path: {}", path.display());
 
-        let fs = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-        match fs.get().get_virtual_entry_type(path) {
+        read_filesystem(|fs| match fs.get().get_virtual_entry_type(path) {
             Err(_) => match fs.get().get_patch_entry_type(path) {
                 Ok(ty) => match ty {
                     FileEntryType::File => Ok(FsEntryType::File),
@@ -81,7 +99,7 @@ impl FileSystemAccessor for ModFsAccessor {
                 FileEntryType::File => Ok(FsEntryType::File),
                 FileEntryType::Directory => Ok(FsEntryType::Directory),
             },
-        }
+        })
     }
 
     fn open_file(&self, path: &std::path::Path, mode: skyline::nn::fs::OpenMode) -> Result<*mut FAccessor, AccessorResult> {
@@ -91,14 +109,20 @@ impl FileSystemAccessor for ModFsAccessor {
 
         debug!(target: "no-mod-path", "ModFsAccessor::open_file - Path: {} | Read: {} | Write: {} | Append: {}", path.display(), read, write, append);
 
-        let fs = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-
         if write || append {
             return Err(AccessorResult::Unsupported);
         }
 
-        if fs.get().contains(path) {
-            Ok(FAccessor::new(ModFileAccessor(PathBuf::from(path)), mode))
+        let full_path = read_filesystem(|fs| fs.get().contains(path).then(|| direct::physical_path(fs.get(), path)));
+
+        if let Some(full_path) = full_path {
+            let accessor = ModFileAccessor {
+                path: PathBuf::from(path),
+                full_path,
+                data: None,
+            };
+
+            Ok(FAccessor::new(accessor, mode))
         } else {
             Err(AccessorResult::PathNotFound)
         }
@@ -107,9 +131,7 @@ impl FileSystemAccessor for ModFsAccessor {
     fn open_directory(&self, path: &std::path::Path, _mode: skyline::nn::fs::OpenDirectoryMode) -> Result<*mut DAccessor, AccessorResult> {
         debug!(target: "no-mod-path", "ModFsAccessor::open_directory - Path: {}", path.display());
 
-        let fs = unsafe { &*crate::GLOBAL_FILESYSTEM.get_mut().unwrap() };
-
-        if fs.get().contains(path) {
+        if read_filesystem(|fs| fs.get().contains(path)) {
             Ok(DAccessor::new(ModDirAccessor(PathBuf::from(path))))
         } else {
             Err(AccessorResult::PathNotFound)
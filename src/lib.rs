@@ -9,42 +9,64 @@
 #![feature(int_roundings)]
 
 use std::{
-    collections::HashMap, fmt, io::{BufWriter, Write}, path::{Path, PathBuf}, str::FromStr, sync::{LazyLock, RwLock}
+    fmt, path::{Path, PathBuf}, sync::RwLock
+};
+#[cfg(target_os = "switch")]
+use std::{
+    collections::HashMap, io::{BufWriter, Write}, str::FromStr, sync::LazyLock
 };
 
+#[cfg(target_os = "switch")]
 use arcropolis_api::Event;
+#[cfg(target_os = "switch")]
 use log::LevelFilter;
 use thiserror::Error;
 
 #[macro_use]
 extern crate log;
 
+#[cfg(target_os = "switch")]
 use skyline::{hooks::InlineCtx, libc::c_char, nn};
 
 mod api;
+#[cfg(target_os = "switch")]
 mod chainloader;
+#[cfg(feature = "check")]
+pub mod check;
+#[cfg(feature = "dry-run")]
+pub mod dry_run;
+#[cfg(target_os = "switch")]
 mod fixes;
-mod fs;
+pub mod fs;
+#[cfg(target_os = "switch")]
 mod fuse;
 mod hashes;
+#[cfg(target_os = "switch")]
 mod logging;
+#[cfg(target_os = "switch")]
 mod offsets;
-mod replacement;
-mod resource;
-#[cfg(feature = "online")]
+pub mod replacement;
+pub mod resource;
+#[cfg(all(feature = "online", target_os = "switch"))]
 mod update;
 mod utils;
+#[cfg(target_os = "switch")]
 mod lua;
 
 use fs::GlobalFilesystem;
-use smash_arc::{Hash40, Region};
+use smash_arc::Hash40;
+#[cfg(target_os = "switch")]
+use smash_arc::Region;
 
+#[cfg(target_os = "switch")]
 use crate::utils::save::{get_language_id_in_savedata, get_system_region_from_language_id, mount_save, unmount_save};
 
+#[cfg(target_os = "switch")]
 use config::{GLOBAL_CONFIG, REGION};
 
 pub static mut GLOBAL_FILESYSTEM: RwLock<GlobalFilesystem> = RwLock::new(GlobalFilesystem::Uninitialized);
 
+#[cfg(target_os = "switch")]
 static mut NEWS_DATA: LazyLock<RwLock<HashMap<String, String>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
 
 #[macro_export]
@@ -61,6 +83,7 @@ macro_rules! reg_w {
     };
 }
 
+#[cfg(target_os = "switch")]
 /// Basic code for displaying an ARCropolis dialog error informing the user to check their logs, or enable them if they don't currently.
 fn dialog_error<S: AsRef<str>>(msg: S) {
     if config::file_logging_enabled() {
@@ -154,6 +177,7 @@ pub const REGIONS: &[&str] = &[
     "jp_ja", "us_en", "us_fr", "us_es", "eu_en", "eu_fr", "eu_es", "eu_de", "eu_nl", "eu_it", "eu_ru", "kr_ko", "zh_cn", "zh_tw",
 ];
 
+#[cfg(target_os = "switch")]
 /// Initializes the `nn::time` library, for creating a log file based off of the current time. For some reason Smash does not initialize this
 fn init_time() {
     unsafe {
@@ -163,12 +187,13 @@ fn init_time() {
     }
 }
 
+#[cfg(target_os = "switch")]
 fn init_account() {
     // It is safe to initialize multiple times
     unsafe { nn::account::Initialize() }
 }
 
-#[cfg(feature = "online")]
+#[cfg(all(feature = "online", target_os = "switch"))]
 fn check_for_changelog() {
     if !crate::utils::env::is_emulator() {
         if let Ok(changelog) = std::fs::read_to_string("sd:/ultimate/arcropolis/changelog.toml") {
@@ -185,7 +210,7 @@ fn check_for_changelog() {
     }
 }
 
-#[cfg(feature = "online")]
+#[cfg(all(feature = "online", target_os = "switch"))]
 fn get_news_data() {
     skyline::install_hook!(msbt_text);
     match minreq::get("https://coolsonickirby.com/arc/news").send() {
@@ -197,17 +222,37 @@ fn get_news_data() {
     }
 }
 
-#[cfg(feature = "ui")]
+#[cfg(all(feature = "ui", target_os = "switch"))]
 fn check_input_on_boot() {
     if !crate::utils::env::is_emulator() {
         // Open the ARCropolis menu if Minus is held before mod discovery
         if ninput::any::is_down(ninput::Buttons::PLUS) {
-            menus::show_main_menu();
+            // Discovery hasn't happened yet, so there is nothing to reload
+            let _ = menus::show_main_menu();
+        }
+    }
+}
+
+#[cfg(all(feature = "ui", target_os = "switch"))]
+fn handle_main_menu_action(action: Option<menus::MainMenuAction>) {
+    if let Some(menus::MainMenuAction::ReloadMods) = action {
+        let report = fs::write_filesystem().reload();
+
+        match report {
+            Some(report) => {
+                let skipped: Vec<String> = report
+                    .skipped
+                    .iter()
+                    .map(|(hash, reason)| format!("{} ({:?})", hashes::find(*hash), reason))
+                    .collect();
+                menus::show_reload_result(report.reloaded.len(), report.invalidated.len(), &skipped);
+            },
+            None => dialog_error("The mods can't be reloaded before the game finished booting."),
         }
     }
 }
 
-#[cfg(feature = "online")]
+#[cfg(all(feature = "online", target_os = "switch"))]
 fn check_for_update() {
     // Changed to pre because prerelease doesn't compile
     if !semver::Version::from_str(env!("CARGO_PKG_VERSION")).unwrap().pre.is_empty() {
@@ -231,6 +276,7 @@ fn check_for_update() {
     }
 }
 
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::initial_loading(), inline)]
 fn initial_loading(_ctx: &InlineCtx) {
     #[cfg(feature = "online")]
@@ -258,14 +304,27 @@ fn initial_loading(_ctx: &InlineCtx) {
     api::event::send_event(Event::ArcFilesystemMounted);
     replacement::lookup::initialize(Some(arc));
     
-    let mut filesystem = unsafe { GLOBAL_FILESYSTEM.write().unwrap() };
+    let mut filesystem = fs::write_filesystem();
     
     *filesystem = filesystem.take().finish(arc).unwrap();
 
     filesystem.process_mods();
     filesystem.share_hashes();
+    filesystem.size_patched_files();
     filesystem.patch_files();
 
+    if let Some(report) = filesystem.memory_budget() {
+        info!(
+            "Mods add {:#x} bytes to the resource service, its buffer is {:#x} bytes.",
+            report.added_bytes,
+            report.buffer_size.unwrap_or(0)
+        );
+
+        if report.is_over_budget() && !config::skip_memory_warning() {
+            dialog_error(report.warning());
+        }
+    }
+
     if config::debug_enabled() {
         let mut output = BufWriter::new(std::fs::File::create("sd:/ultimate/arcropolis/filesystem_dump.txt").unwrap());
         filesystem.get().walk_patch(|node, entry_type| {
@@ -290,6 +349,7 @@ fn initial_loading(_ctx: &InlineCtx) {
     // _updater.join().unwrap();
 }
 
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::title_screen_version())]
 fn change_version_string(arg: u64, string: *const c_char) {
     let original_str = unsafe { skyline::from_c_str(string) };
@@ -321,6 +381,7 @@ fn change_version_string(arg: u64, string: *const c_char) {
 // #[skyline::from_offset(0x336d890)]
 // pub fn stop_all_bgm();
 
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::eshop_button())]
 fn show_eshop() {
     // stop_all_bgm();
@@ -328,10 +389,11 @@ fn show_eshop() {
     // play_bgm(instance as _, 0xd9ffff202a04c55b, false);
 
     #[cfg(feature = "ui")]
-    menus::show_main_menu();
+    handle_main_menu_action(menus::show_main_menu());
     // play_menu_bgm();
 }
 
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::msbt_text(), inline)]
 unsafe fn msbt_text(ctx: &mut InlineCtx) {
     let msbt_label = skyline::from_c_str((ctx as *const InlineCtx as *const u8).add(0x100).add(224));
@@ -346,6 +408,7 @@ unsafe fn msbt_text(ctx: &mut InlineCtx) {
     }
 }
 
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::packet_send(), inline)]
 unsafe fn online_slot_spoof(ctx: &InlineCtx) {
     let data = ctx.registers[3].x() as *mut u8;
@@ -360,6 +423,7 @@ unsafe fn online_slot_spoof(ctx: &InlineCtx) {
     }
 }
 
+#[cfg(target_os = "switch")]
 pub fn is_online() -> bool {
     unsafe {
         *(offsets::offset_to_addr(offsets::is_online()) as *const bool)
@@ -367,6 +431,7 @@ pub fn is_online() -> bool {
 }
 
 // Thanks to blujay for these two function hooks
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::change_color_r(), inline)]
 unsafe fn change_fighter_color_r(ctx: &mut skyline::hooks::InlineCtx) {
     if is_online() {
@@ -379,6 +444,7 @@ unsafe fn change_fighter_color_r(ctx: &mut skyline::hooks::InlineCtx) {
     }
 }
 
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::change_color_l(), inline)]
 unsafe fn change_fighter_color_l(ctx: &mut skyline::hooks::InlineCtx) {
     if is_online() {
@@ -392,23 +458,27 @@ unsafe fn change_fighter_color_l(ctx: &mut skyline::hooks::InlineCtx) {
     }
 }
 
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::skip_opening(), inline)]
 unsafe fn skip_opening_cutscene(ctx: &mut InlineCtx) {
     ctx.registers[8].set_x(0);
 }
 
 // Change the next callback for the TitleSceneInfo::callbacks::Enter from "DisplayOpeningCutscene" to "HowToPlay"
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::title_scene_play_opening(), inline)]
 unsafe fn title_scene_play_opening(ctx: &mut InlineCtx) {
     ctx.registers[9].set_x(1);
 }
 
 // Pretend the state for another state handler (OpeningCutsceneLayout?) is set to 5
+#[cfg(target_os = "switch")]
 #[skyline::hook(offset = offsets::title_scene_how_to_play(), inline)]
 unsafe fn title_scene_show_how_to_play_fake_state_index(ctx: &mut InlineCtx) {
     ctx.registers[8].set_x(5);
 }
 
+#[cfg(target_os = "switch")]
 #[skyline::main(name = "arcropolis")]
 pub fn main() {
     std::panic::set_hook(Box::new(|info| {
@@ -473,7 +543,7 @@ pub fn main() {
     }
 
     // Acquire the filesystem and promise it to the initial_loading hook
-    let mut filesystem = unsafe { GLOBAL_FILESYSTEM.write().unwrap() };
+    let mut filesystem = fs::write_filesystem();
 
     let discovery = std::thread::Builder::new()
         .stack_size(0x10000)
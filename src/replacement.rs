@@ -4,13 +4,17 @@ pub mod lookup;
 pub mod addition;
 // pub mod config;
 pub mod preprocess;
+#[cfg(target_os = "switch")]
 mod stream;
-mod threads;
+#[cfg(target_os = "switch")]
+pub mod threads;
+#[cfg(target_os = "switch")]
 mod uncompressed;
 pub mod unshare;
 
 pub use extensions::*;
 
+#[cfg(target_os = "switch")]
 pub fn install() {
     stream::install();
     threads::install();
@@ -498,7 +498,7 @@ impl LoadedArcEx for LoadedArc {
 
         if NEEDS_FREE.swap(true, Ordering::SeqCst) {
             unsafe {
-                skyline::libc::free(tmp);
+                resource::free(tmp);
             }
         }
         assert!(self
@@ -652,7 +652,7 @@ impl SearchEx for LoadedSearchSection {
 
         if NEEDS_FREE.swap(true, Ordering::SeqCst) {
             unsafe {
-                skyline::libc::free(tmp as _);
+                resource::free(tmp as _);
             }
         }
     }
@@ -686,7 +686,7 @@ impl SearchEx for LoadedSearchSection {
 
         if NEEDS_FREE.swap(true, Ordering::SeqCst) {
             unsafe {
-                skyline::libc::free(tmp as _);
+                resource::free(tmp as _);
             }
         }
     }
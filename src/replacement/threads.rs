@@ -4,9 +4,9 @@ use smash_arc::{ArcLookup, Hash40};
 
 use super::{FileInfoFlagsExt, NO_CHILD};
 use crate::{
+    fs::GlobalFilesystem,
     hashes, offsets, reg_w, reg_x,
     resource::{self, InflateFile, LoadInfo, LoadType},
-    GLOBAL_FILESYSTEM,
 };
 
 #[hook(offset = offsets::inflate(), inline)]
@@ -30,7 +30,7 @@ fn inflate_incoming(ctx: &InlineCtx) {
         hashes::find(path_hash).bright_yellow()
     );
 
-    let mut fs = unsafe { GLOBAL_FILESYSTEM.write().unwrap() };
+    let mut fs = crate::fs::write_filesystem();
 
     let should_add = if let Some(path) = fs.hash(path_hash) {
         info!("Added file '{}' to the queue.", path.display().yellow());
@@ -58,7 +58,7 @@ fn inflate_dir_file(arg: u64, out_decomp_data: &mut InflateFile, comp_data: &Inf
 
     if result == 0x0 {
         // returns 0x0 on the very last read, since they can be read in chunks
-        let hash = unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().get_incoming() };
+        let hash = crate::fs::write_filesystem().get_incoming();
         if let Some(hash) = hash {
             handle_file_replace(hash);
         }
@@ -68,6 +68,12 @@ fn inflate_dir_file(arg: u64, out_decomp_data: &mut InflateFile, comp_data: &Inf
 }
 
 pub fn handle_file_replace(hash: Hash40) {
+    replace_loaded_data(&mut crate::fs::write_filesystem(), hash);
+}
+
+/// Load the modded file over the buffer the game already loaded it into.
+/// Returns false if the file has no buffer to write to or failed to load.
+pub fn replace_loaded_data(fs: &mut GlobalFilesystem, hash: Hash40) -> bool {
     let arc = resource::arc();
     let filesystem_info = resource::filesystem_info();
 
@@ -75,7 +81,7 @@ pub fn handle_file_replace(hash: Hash40) {
         Ok(info) => info,
         Err(_) => {
             error!("Failed to find file info for '{}' ({:#x}) when replacing.", hashes::find(hash), hash.0);
-            return;
+            return false;
         },
     };
 
@@ -102,11 +108,9 @@ pub fn handle_file_replace(hash: Hash40) {
             filepath_index,
             file_info_indice_index
         );
-        return;
+        return false;
     }
 
-    let mut fs = unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap() };
-
     let buffer = unsafe {
         std::slice::from_raw_parts_mut(
             filesystem_info.get_loaded_datas()[file_info_indice_index].data as *mut u8,
@@ -137,6 +141,7 @@ pub fn handle_file_replace(hash: Hash40) {
             size,
             resource::res_service().buffer_size
         );
+        true
     } else {
         warn!(
             "Failed to load file '{}' ({:#x}) into buffer with size {:#X}",
@@ -144,6 +149,7 @@ pub fn handle_file_replace(hash: Hash40) {
             hash.0,
             decompressed_size
         );
+        false
     }
 }
 
@@ -17,7 +17,7 @@ use crate::{offsets, reg_x};
 fn memcpy_uncompressed_fix(ctx: &InlineCtx) {
     // For now, we will leave this as an unconditionally true if statement
     let buffer_size = reg_x!(ctx, 2) as usize;
-    let hash = unsafe { crate::GLOBAL_FILESYSTEM.write().unwrap().sub_remaining_bytes(buffer_size) };
+    let hash = crate::fs::write_filesystem().sub_remaining_bytes(buffer_size);
     if let Some(hash) = hash {
         super::threads::handle_file_replace(hash);
     } else {
@@ -1,26 +1,53 @@
+mod backend;
 mod containers;
+#[cfg(not(target_os = "switch"))]
+mod host;
 mod types;
 
+pub use backend::*;
 pub use containers::*;
+#[cfg(not(target_os = "switch"))]
+pub use host::*;
 use smash_arc::{LoadedArc, LoadedSearchSection};
 pub use types::*;
 
+/// Allocator the tables of the game come from, so the ones replacing them have to be freed with it as well
+#[cfg(not(target_os = "switch"))]
+pub(crate) use libc::{free, malloc};
+#[cfg(target_os = "switch")]
+pub(crate) use skyline::libc::{free, malloc};
+
+#[cfg(target_os = "switch")]
 use crate::offsets;
 
+#[cfg(target_os = "switch")]
 fn offset_to_addr<T>(offset: usize) -> *mut T {
     unsafe { (skyline::hooks::getRegionAddress(skyline::hooks::Region::Text) as usize + offset) as *mut T }
 }
 
+#[cfg(target_os = "switch")]
 pub fn filesystem_info() -> &'static FilesystemInfo {
     let addr = offset_to_addr::<&'static FilesystemInfo>(offsets::filesystem_info());
     unsafe { *addr }
 }
 
+#[cfg(target_os = "switch")]
 pub fn filesystem_info_mut() -> &'static mut FilesystemInfo {
     let addr = offset_to_addr::<&'static mut FilesystemInfo>(offsets::filesystem_info());
     unsafe { *addr }
 }
 
+/// Off-console, the tables are the ones installed by `ArcTables::install`
+#[cfg(not(target_os = "switch"))]
+pub fn filesystem_info() -> &'static FilesystemInfo {
+    unsafe { host::installed().as_ref().expect("No data.arc tables were installed") }
+}
+
+#[cfg(not(target_os = "switch"))]
+pub fn filesystem_info_mut() -> &'static mut FilesystemInfo {
+    unsafe { host::installed().as_mut().expect("No data.arc tables were installed") }
+}
+
 pub fn arc() -> &'static LoadedArc {
     filesystem_info().path_info.arc
 }
@@ -37,16 +64,24 @@ pub fn search_mut() -> &'static mut LoadedSearchSection {
     filesystem_info_mut().path_info.search
 }
 
+#[cfg(target_os = "switch")]
 pub fn res_service() -> &'static ResServiceNX {
     let addr = offset_to_addr::<&'static ResServiceNX>(offsets::res_service());
     unsafe { *addr }
 }
 
+#[cfg(target_os = "switch")]
 pub fn res_service_mut() -> &'static mut ResServiceNX {
     let addr = offset_to_addr::<&'static mut ResServiceNX>(offsets::res_service());
     unsafe { *addr }
 }
 
+#[cfg(target_os = "switch")]
 pub fn initialized() -> bool {
     !offset_to_addr::<&'static FilesystemInfo>(offsets::filesystem_info()).is_null()
 }
+
+#[cfg(not(target_os = "switch"))]
+pub fn initialized() -> bool {
+    !host::installed().is_null()
+}
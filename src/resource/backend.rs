@@ -0,0 +1,198 @@
+use std::sync::OnceLock;
+#[cfg(not(target_os = "switch"))]
+use std::{collections::HashMap, path::Path, sync::RwLock};
+
+#[cfg(not(target_os = "switch"))]
+use smash_arc::ArcFile;
+use smash_arc::{ArcLookup, Hash40, LookupError, Region, SearchLookup};
+
+use crate::replacement::LoadedArcEx;
+
+/// The parts of the data.arc that the mod filesystem needs to read or edit while it is being built.
+///
+/// On console this is backed by the game's `LoadedArc`, but anything implementing it can drive `CachedFilesystem`,
+/// which is what allows running discovery and the file patching logic off-console.
+pub trait ResourceBackend: Send + Sync {
+    /// Whether the backend is the game's own tables, which means the files the game already loaded can be refreshed.
+    fn is_game(&self) -> bool {
+        false
+    }
+
+    fn decompressed_size(&self, hash: Hash40, region: Region) -> Result<usize, LookupError>;
+
+    /// Overwrite the decompressed size of a file's FileData, returning the previous size
+    fn patch_decompressed_size(&self, hash: Hash40, size: u32, region: Region) -> Result<usize, LookupError>;
+
+    fn file_contents(&self, hash: Hash40, region: Region) -> Result<Vec<u8>, LookupError>;
+
+    /// Get the hash of the FilePath that actually owns the FileInfo for this hash (the one it is shared to, if any)
+    fn canonical_hash(&self, hash: Hash40) -> Result<Hash40, LookupError>;
+
+    fn contains_file(&self, hash: Hash40) -> bool;
+
+    fn is_directory(&self, hash: Hash40) -> Result<bool, LookupError>;
+}
+
+/// Backend for the tables the game has loaded in memory.
+pub struct GameBackend;
+
+impl ResourceBackend for GameBackend {
+    fn is_game(&self) -> bool {
+        true
+    }
+
+    fn decompressed_size(&self, hash: Hash40, region: Region) -> Result<usize, LookupError> {
+        super::arc().get_file_data_from_hash(hash, region).map(|data| data.decomp_size as usize)
+    }
+
+    fn patch_decompressed_size(&self, hash: Hash40, size: u32, region: Region) -> Result<usize, LookupError> {
+        super::arc_mut().patch_filedata(hash, size, region).map(|old_size| old_size as usize)
+    }
+
+    fn file_contents(&self, hash: Hash40, region: Region) -> Result<Vec<u8>, LookupError> {
+        super::arc().get_file_contents(hash, region)
+    }
+
+    fn canonical_hash(&self, hash: Hash40) -> Result<Hash40, LookupError> {
+        let arc = super::arc();
+        let file_info = arc.get_file_info_from_hash(hash)?;
+        Ok(arc.get_file_paths()[file_info.file_path_index].path.hash40())
+    }
+
+    fn contains_file(&self, hash: Hash40) -> bool {
+        super::arc().get_file_path_index_from_hash(hash).is_ok()
+    }
+
+    fn is_directory(&self, hash: Hash40) -> Result<bool, LookupError> {
+        Ok(super::search().get_path_list_entry_from_hash(hash)?.is_directory())
+    }
+}
+
+/// Where a `HostBackend` reads the contents of the files from
+#[cfg(not(target_os = "switch"))]
+enum HostContents {
+    Arc(ArcFile),
+    Memory(HashMap<Hash40, Vec<u8>>),
+}
+
+/// Backend for a data.arc opened from a regular filesystem, such as a small fixture on a PC.
+///
+/// The tables are installed in memory the way the game lays them out, so unsharing and file additions edit them like they would on console.
+/// The FileData tables are never written to, resized files are tracked on the side instead.
+#[cfg(not(target_os = "switch"))]
+pub struct HostBackend {
+    contents: HostContents,
+    /// Hash -> (vanilla size, patched size)
+    patched_sizes: RwLock<HashMap<Hash40, (u32, u32)>>,
+}
+
+#[cfg(not(target_os = "switch"))]
+impl HostBackend {
+    pub fn new(arc: ArcFile) -> Self {
+        super::ArcTables::from_arc(&arc).install();
+        Self {
+            contents: HostContents::Arc(arc),
+            patched_sizes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Install tables built by hand, with the contents of their files kept in memory regardless of the region.
+    pub fn from_tables(tables: super::ArcTables, contents: HashMap<Hash40, Vec<u8>>) -> Self {
+        tables.install();
+        Self {
+            contents: HostContents::Memory(contents),
+            patched_sizes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        ArcFile::open(path.as_ref())
+            .map(Self::new)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))
+    }
+
+    /// Every FileData that was resized through this backend, alongside its vanilla and new size
+    pub fn patched_sizes(&self) -> HashMap<Hash40, (u32, u32)> {
+        self.patched_sizes.read().unwrap().clone()
+    }
+}
+
+#[cfg(not(target_os = "switch"))]
+impl ResourceBackend for HostBackend {
+    fn decompressed_size(&self, hash: Hash40, region: Region) -> Result<usize, LookupError> {
+        if let Some((_, size)) = self.patched_sizes.read().unwrap().get(&hash) {
+            return Ok(*size as usize);
+        }
+
+        super::arc().get_file_data_from_hash(hash, region).map(|data| data.decomp_size as usize)
+    }
+
+    fn patch_decompressed_size(&self, hash: Hash40, size: u32, region: Region) -> Result<usize, LookupError> {
+        let old_size = self.decompressed_size(hash, region)?;
+        let vanilla_size = super::arc().get_file_data_from_hash(hash, region)?.decomp_size;
+        self.patched_sizes.write().unwrap().insert(hash, (vanilla_size, size));
+        Ok(old_size)
+    }
+
+    fn file_contents(&self, hash: Hash40, region: Region) -> Result<Vec<u8>, LookupError> {
+        match &self.contents {
+            HostContents::Arc(arc) => arc.get_file_contents(hash, region),
+            HostContents::Memory(files) => files.get(&hash).cloned().ok_or(LookupError::Missing),
+        }
+    }
+
+    fn canonical_hash(&self, hash: Hash40) -> Result<Hash40, LookupError> {
+        let arc = super::arc();
+        let file_info = arc.get_file_info_from_hash(hash)?;
+        Ok(arc.get_file_paths()[file_info.file_path_index].path.hash40())
+    }
+
+    fn contains_file(&self, hash: Hash40) -> bool {
+        super::arc().get_file_path_index_from_hash(hash).is_ok()
+    }
+
+    fn is_directory(&self, hash: Hash40) -> Result<bool, LookupError> {
+        Ok(super::search().get_path_list_entry_from_hash(hash)?.is_directory())
+    }
+}
+
+impl<B: ResourceBackend + ?Sized> ResourceBackend for &'static B {
+    fn is_game(&self) -> bool {
+        (**self).is_game()
+    }
+
+    fn decompressed_size(&self, hash: Hash40, region: Region) -> Result<usize, LookupError> {
+        (**self).decompressed_size(hash, region)
+    }
+
+    fn patch_decompressed_size(&self, hash: Hash40, size: u32, region: Region) -> Result<usize, LookupError> {
+        (**self).patch_decompressed_size(hash, size, region)
+    }
+
+    fn file_contents(&self, hash: Hash40, region: Region) -> Result<Vec<u8>, LookupError> {
+        (**self).file_contents(hash, region)
+    }
+
+    fn canonical_hash(&self, hash: Hash40) -> Result<Hash40, LookupError> {
+        (**self).canonical_hash(hash)
+    }
+
+    fn contains_file(&self, hash: Hash40) -> bool {
+        (**self).contains_file(hash)
+    }
+
+    fn is_directory(&self, hash: Hash40) -> Result<bool, LookupError> {
+        (**self).is_directory(hash)
+    }
+}
+
+static BACKEND: OnceLock<Box<dyn ResourceBackend>> = OnceLock::new();
+
+/// Replace the backend used by the filesystem. This has to happen before anything queries the backend, as the game one is used by default.
+pub fn install_backend<B: ResourceBackend + 'static>(backend: B) -> Result<(), Box<dyn ResourceBackend>> {
+    BACKEND.set(Box::new(backend))
+}
+
+pub fn backend() -> &'static dyn ResourceBackend {
+    BACKEND.get_or_init(|| Box::new(GameBackend)).as_ref()
+}
@@ -330,7 +330,7 @@ impl ResList {
 
     pub fn insert(&mut self, value: LoadInfo) {
         unsafe {
-            let node = skyline::libc::malloc(std::mem::size_of::<ListNode>()) as *mut ListNode;
+            let node = super::malloc(std::mem::size_of::<ListNode>()) as *mut ListNode;
             (*node).prev = &mut self.next as *mut *mut ListNode as *mut ListNode;
             (*node).next = self.next;
             self.next = node;
@@ -0,0 +1,191 @@
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use smash_arc::{
+    ArcFile, ArcLookup, DirInfo, DirectoryOffset, FileData, FileInfo, FileInfoBucket, FileInfoIndex, FileInfoToFileData, FilePath, FileSystemHeader,
+    FolderPathListEntry, HashToIndex, LoadedArc, LoadedSearchSection, PathListEntry, SearchLookup, SearchSectionBody,
+};
+
+use super::{CppVector, FilesystemInfo, LoadedData, LoadedDirectory, LoadedFilepath, PathInformation};
+
+static FILESYSTEM_INFO: AtomicPtr<FilesystemInfo> = AtomicPtr::new(std::ptr::null_mut());
+
+pub(super) fn installed() -> *mut FilesystemInfo {
+    FILESYSTEM_INFO.load(Ordering::SeqCst)
+}
+
+/// Give up ownership of a table, the code extending it frees the old one with `resource::free` which the host allocator backs
+fn leak<T>(table: Vec<T>) -> *mut T {
+    Box::leak(table.into_boxed_slice()).as_mut_ptr()
+}
+
+/// The tables of a data.arc that the mod filesystem reads and edits, kept off-console in place of the ones the game loads.
+///
+/// `file_info_buckets` does not include the leading entry that holds the amount of buckets, it is added when installing them.
+pub struct ArcTables {
+    pub fs_header: FileSystemHeader,
+    pub file_info_buckets: Vec<FileInfoBucket>,
+    pub file_hash_to_path_index: Vec<HashToIndex>,
+    pub file_paths: Vec<FilePath>,
+    pub file_info_indices: Vec<FileInfoIndex>,
+    pub dir_hash_to_info_index: Vec<HashToIndex>,
+    pub dir_infos: Vec<DirInfo>,
+    pub folder_offsets: Vec<DirectoryOffset>,
+    pub folder_child_hashes: Vec<HashToIndex>,
+    pub file_infos: Vec<FileInfo>,
+    pub file_info_to_datas: Vec<FileInfoToFileData>,
+    pub file_datas: Vec<FileData>,
+
+    pub folder_path_to_index: Vec<HashToIndex>,
+    pub folder_path_list: Vec<FolderPathListEntry>,
+    pub path_to_index: Vec<HashToIndex>,
+    pub path_list_indices: Vec<u32>,
+    pub path_list: Vec<PathListEntry>,
+}
+
+impl Default for ArcTables {
+    fn default() -> Self {
+        Self {
+            // The header only holds counts, and every one but the split of the folder offsets is set from the tables when installing them
+            fs_header: unsafe { std::mem::zeroed() },
+            file_info_buckets: Vec::new(),
+            file_hash_to_path_index: Vec::new(),
+            file_paths: Vec::new(),
+            file_info_indices: Vec::new(),
+            dir_hash_to_info_index: Vec::new(),
+            dir_infos: Vec::new(),
+            folder_offsets: Vec::new(),
+            folder_child_hashes: Vec::new(),
+            file_infos: Vec::new(),
+            file_info_to_datas: Vec::new(),
+            file_datas: Vec::new(),
+
+            folder_path_to_index: Vec::new(),
+            folder_path_list: Vec::new(),
+            path_to_index: Vec::new(),
+            path_list_indices: Vec::new(),
+            path_list: Vec::new(),
+        }
+    }
+}
+
+impl ArcTables {
+    pub fn from_arc(arc: &ArcFile) -> Self {
+        Self {
+            fs_header: arc.file_system.fs_header,
+            file_info_buckets: arc.get_file_info_buckets().to_vec(),
+            file_hash_to_path_index: arc.get_file_hash_to_path_index().to_vec(),
+            file_paths: arc.get_file_paths().to_vec(),
+            file_info_indices: arc.get_file_info_indices().to_vec(),
+            dir_hash_to_info_index: arc.get_dir_hash_to_info_index().to_vec(),
+            dir_infos: arc.get_dir_infos().to_vec(),
+            folder_offsets: arc.get_folder_offsets().to_vec(),
+            folder_child_hashes: arc.file_system.folder_child_hashes.to_vec(),
+            file_infos: arc.get_file_infos().to_vec(),
+            file_info_to_datas: arc.get_file_info_to_datas().to_vec(),
+            file_datas: arc.get_file_datas().to_vec(),
+
+            folder_path_to_index: arc.get_folder_path_to_index().to_vec(),
+            folder_path_list: arc.get_folder_path_list().to_vec(),
+            path_to_index: arc.get_path_to_index().to_vec(),
+            path_list_indices: arc.get_path_list_indices().to_vec(),
+            path_list: arc.get_path_list().to_vec(),
+        }
+    }
+
+    /// Lay the tables out the way the game does and point `resource::arc()`, `resource::search()` and `resource::filesystem_info()` to them.
+    ///
+    /// Nothing is loaded yet, so every LoadedFilepath, LoadedData and LoadedDirectory starts out empty. Tables installed before are leaked.
+    pub fn install(self) {
+        let Self {
+            mut fs_header,
+            file_info_buckets,
+            file_hash_to_path_index,
+            file_paths,
+            file_info_indices,
+            dir_hash_to_info_index,
+            dir_infos,
+            folder_offsets,
+            folder_child_hashes,
+            file_infos,
+            file_info_to_datas,
+            file_datas,
+            folder_path_to_index,
+            folder_path_list,
+            path_to_index,
+            path_list_indices,
+            path_list,
+        } = self;
+
+        fs_header.file_info_path_count = file_paths.len() as u32;
+        fs_header.file_info_index_count = file_info_indices.len() as u32;
+        fs_header.folder_count = dir_infos.len() as u32;
+        fs_header.hash_folder_count = folder_child_hashes.len() as u32;
+        fs_header.file_info_count = file_infos.len() as u32;
+        fs_header.file_info_sub_index_count = file_info_to_datas.len() as u32;
+        fs_header.file_data_count = file_datas.len() as u32;
+
+        let loaded_filepath_len = file_paths.len();
+        let loaded_data_len = file_info_indices.len();
+        let loaded_directory_len = folder_offsets.len();
+
+        let mut buckets = Vec::with_capacity(file_info_buckets.len() + 1);
+        buckets.push(FileInfoBucket {
+            start: file_hash_to_path_index.len() as u32,
+            count: file_info_buckets.len() as u32,
+        });
+        buckets.extend(file_info_buckets);
+
+        // Every field this leaves out is a pointer to a table the mod filesystem does not use, or the offset of a section of the file
+        let mut arc: LoadedArc = unsafe { std::mem::zeroed() };
+        arc.fs_header = Box::leak(Box::new(fs_header)) as *mut FileSystemHeader as _;
+        arc.file_info_buckets = leak(buckets) as _;
+        arc.file_hash_to_path_index = leak(file_hash_to_path_index) as _;
+        arc.file_paths = leak(file_paths) as _;
+        arc.file_info_indices = leak(file_info_indices) as _;
+        arc.dir_hash_to_info_index = leak(dir_hash_to_info_index) as _;
+        arc.dir_infos = leak(dir_infos) as _;
+        arc.folder_offsets = leak(folder_offsets) as _;
+        arc.folder_child_hashes = leak(folder_child_hashes) as _;
+        arc.file_infos = leak(file_infos) as _;
+        arc.file_info_to_datas = leak(file_info_to_datas) as _;
+        arc.file_datas = leak(file_datas) as _;
+
+        let mut body: SearchSectionBody = unsafe { std::mem::zeroed() };
+        body.folder_path_count = folder_path_list.len() as u32;
+        body.path_indices_count = path_list_indices.len() as u32;
+        body.path_count = path_list.len() as u32;
+
+        let mut search: LoadedSearchSection = unsafe { std::mem::zeroed() };
+        search.body = Box::leak(Box::new(body)) as *mut SearchSectionBody as _;
+        search.folder_path_index = leak(folder_path_to_index) as _;
+        search.folder_path_list = leak(folder_path_list) as _;
+        search.path_index = leak(path_to_index) as _;
+        search.path_list_indices = leak(path_list_indices) as _;
+        search.path_list = leak(path_list) as _;
+
+        let filesystem_info = FilesystemInfo {
+            mutex: std::ptr::null_mut(),
+            loaded_filepaths: leak(vec![LoadedFilepath::default(); loaded_filepath_len]),
+            loaded_datas: leak((0..loaded_data_len).map(|_| LoadedData::new()).collect()),
+            loaded_filepath_len: loaded_filepath_len as u32,
+            loaded_data_len: loaded_data_len as u32,
+            loaded_filepath_count: 0,
+            loaded_data_count: 0,
+            loaded_filepath_list: CppVector::new(),
+            loaded_directories: leak((0..loaded_directory_len).map(|_| LoadedDirectory::default()).collect()),
+            loaded_directory_len: loaded_directory_len as u32,
+            unk: 0,
+            unk2: CppVector::new(),
+            unk3: 0,
+            unk4: [0; 7],
+            addr: std::ptr::null(),
+            path_info: Box::leak(Box::new(PathInformation {
+                arc: Box::leak(Box::new(arc)),
+                search: Box::leak(Box::new(search)),
+            })),
+            version: 0,
+        };
+
+        FILESYSTEM_INFO.store(Box::leak(Box::new(filesystem_info)), Ordering::SeqCst);
+    }
+}
@@ -3,11 +3,26 @@ use std::{
     sync::atomic::{AtomicU32, Ordering},
 };
 
+#[cfg(target_os = "switch")]
 use skyline::nn;
 use smash_arc::{LoadedArc, LoadedSearchSection};
 
 use super::containers::{CppVector, ResList};
 
+/// Stand-ins for the nnSdk types the game structures point to, so they keep their layout off-console
+#[cfg(not(target_os = "switch"))]
+mod nn {
+    pub mod os {
+        pub type MutexType = std::ffi::c_void;
+        pub type EventType = std::ffi::c_void;
+        pub type ThreadType = std::ffi::c_void;
+    }
+
+    pub mod fs {
+        pub type FileHandle = std::ffi::c_void;
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum LoadState {
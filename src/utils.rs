@@ -1,17 +1,22 @@
+#[cfg(target_os = "switch")]
 use skyline::nn;
 
 pub mod env {
     use semver::Version;
     use std::{str::FromStr, sync::LazyLock};
 
+    #[cfg(target_os = "switch")]
     use super::*;
 
     #[non_exhaustive]
     pub enum RunEnvironment {
         Switch,
         Emulator,
+        /// Running off-console, such as the dry-run tools or the tests
+        Host,
     }
 
+    #[cfg(target_os = "switch")]
     static PLATFORM: LazyLock<RunEnvironment> = LazyLock::new(|| {
         let base_addr = unsafe { skyline::hooks::getRegionAddress(skyline::hooks::Region::Text) as u64 };
 
@@ -22,6 +27,9 @@ pub mod env {
         }
     });
 
+    #[cfg(not(target_os = "switch"))]
+    static PLATFORM: LazyLock<RunEnvironment> = LazyLock::new(|| RunEnvironment::Host);
+
     pub fn get_running_env() -> &'static RunEnvironment {
         &PLATFORM
     }
@@ -35,6 +43,7 @@ pub mod env {
     }
 
     /// Wrapper function for getting the version string of the game from nnSdk
+    #[cfg(target_os = "switch")]
     pub fn get_game_version() -> Version {
         unsafe {
             // TODO: Implement this in nnsdk-rs
@@ -44,6 +53,12 @@ pub mod env {
         }
     }
 
+    /// Off-console there is no game to ask, assume the only version ARCropolis supports
+    #[cfg(not(target_os = "switch"))]
+    pub fn get_game_version() -> Version {
+        Version::new(13, 0, 4)
+    }
+
     pub fn get_arcropolis_version() -> Version {
         Version::from_str(env!("CARGO_PKG_VERSION")).expect("ARCropolis' version should follow proper semver.")
     }
@@ -79,6 +94,7 @@ pub mod paths {
     }
 }
 
+#[cfg(target_os = "switch")]
 pub mod save {
     use super::*;
     use smash_arc::Region;
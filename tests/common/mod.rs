@@ -0,0 +1,146 @@
+//! A data.arc small enough to be written by hand, laid out like the game's with one costume shared to another.
+
+#![allow(dead_code)]
+
+use std::{collections::HashMap, path::PathBuf};
+
+use arcropolis::{
+    fs::{discover_mods, CachedFilesystem},
+    replacement::{self, FromPathExt, NO_CHILD},
+    resource::{self, ArcTables, HostBackend},
+};
+use orbits::ConflictHandler;
+use smash_arc::{
+    DirInfo, DirectoryOffset, FileData, FileDataFlags, FileDataIdx, FileInfo, FileInfoBucket, FileInfoFlags, FileInfoIdx, FileInfoIndex,
+    FileInfoIndiceIdx, FileInfoToFileData, FileInfoToFileDataBitfield, FilePath, FilePathIdx, FolderPathListEntry, Hash40, HashToIndex,
+    InfoToDataIdx,
+};
+
+/// Every data.arc has it, resorting the file hashes checks it can still be found
+pub const FIGHTER_PARAM: &str = "fighter/common/param/fighter_param.prc";
+/// Base of every file added to the data.arc
+pub const MARIO_C00_MODEL: &str = "fighter/mario/model/body/c00/model.numdlb";
+/// Shared to the c00 model, like most costumes are in the game
+pub const MARIO_C01_MODEL: &str = "fighter/mario/model/body/c01/model.numdlb";
+
+/// Decompressed size of every file in the fixture
+pub const VANILLA_SIZE: u32 = 0x100;
+
+/// Path of every file, the directory listing it, and the index of the file it is shared to if any
+const FILES: &[(&str, &str, Option<u32>)] = &[
+    (FIGHTER_PARAM, "fighter/common", None),
+    (MARIO_C00_MODEL, "fighter/mario/c00", None),
+    (MARIO_C01_MODEL, "fighter/mario/c01", Some(1)),
+];
+
+pub fn tables() -> ArcTables {
+    let mut tables = ArcTables::default();
+
+    for (index, (path, dir, shared_to)) in FILES.iter().enumerate() {
+        let index = index as u32;
+
+        let mut file_path = FilePath::from_path(path).unwrap();
+        file_path.path.set_index(index);
+        tables.file_hash_to_path_index.push(
+            HashToIndex::new()
+                .with_hash(file_path.path.hash())
+                .with_length(file_path.path.length())
+                .with_index(index),
+        );
+        tables.file_paths.push(file_path);
+
+        // A shared file loads through the FileInfo of the file it is shared to, its own is only listed in its directory
+        tables.file_info_indices.push(FileInfoIndex {
+            dir_offset_index: index,
+            file_info_index: FileInfoIdx(shared_to.unwrap_or(index)),
+        });
+        tables.file_infos.push(FileInfo {
+            file_path_index: FilePathIdx(index),
+            file_info_indice_index: FileInfoIndiceIdx(index),
+            info_to_data_index: InfoToDataIdx(index),
+            flags: FileInfoFlags::new(),
+        });
+        tables.file_info_to_datas.push(FileInfoToFileData {
+            folder_offset_index: index,
+            file_data_index: FileDataIdx(index),
+            file_info_index_and_load_type: FileInfoToFileDataBitfield::new().with_load_type(1),
+        });
+        tables.file_datas.push(FileData {
+            offset_in_folder: 0,
+            comp_size: VANILLA_SIZE,
+            decomp_size: VANILLA_SIZE,
+            flags: FileDataFlags::new(),
+        });
+
+        let dir_path = FolderPathListEntry::from_path(dir).unwrap();
+        // The fields left out are about redirections and child directories, which none of the directories have
+        let mut dir_info: DirInfo = unsafe { std::mem::zeroed() };
+        dir_info.path = dir_path.path;
+        dir_info.path.set_index(index);
+        dir_info.name = dir_path.file_name.hash40();
+        dir_info.parent = dir_path.parent.hash40();
+        dir_info.file_info_start_index = index;
+        dir_info.file_count = 1;
+        tables.dir_hash_to_info_index.push(
+            HashToIndex::new()
+                .with_hash(dir_path.path.hash())
+                .with_length(dir_path.path.length())
+                .with_index(index),
+        );
+        tables.dir_infos.push(dir_info);
+        tables.folder_offsets.push(DirectoryOffset {
+            offset: 0,
+            decomp_size: 0,
+            size: 0,
+            file_start_index: index,
+            file_count: 1,
+            directory_index: NO_CHILD,
+        });
+    }
+
+    tables.file_hash_to_path_index.sort_by_key(|index| index.hash40());
+    tables.dir_hash_to_info_index.sort_by_key(|index| index.hash40());
+    tables.file_info_buckets.push(FileInfoBucket {
+        start: 0,
+        count: FILES.len() as u32,
+    });
+    tables.fs_header.folder_offset_count_1 = FILES.len() as u32;
+
+    tables
+}
+
+/// Install the fixture as the data.arc, along with the lookups ARCropolis builds from it at boot.
+///
+/// The tables can only be edited once per process, so every test using this has to be in its own file.
+pub fn install() -> &'static HostBackend {
+    let contents: HashMap<Hash40, Vec<u8>> = FILES
+        .iter()
+        .map(|(path, ..)| (Hash40::from(*path), vec![0; VANILLA_SIZE as usize]))
+        .collect();
+
+    let backend: &'static HostBackend = Box::leak(Box::new(HostBackend::from_tables(tables(), contents)));
+    assert!(resource::install_backend(backend).is_ok(), "A backend was already installed");
+    replacement::lookup::initialize(Some(resource::arc()));
+
+    backend
+}
+
+/// Make a mod with files of the given sizes, in a folder unique to this test process
+pub fn make_mod(name: &str, files: &[(&str, usize)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("arcropolis-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+
+    for (path, size) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0; *size]).unwrap();
+    }
+
+    root
+}
+
+pub fn filesystem(roots: &[PathBuf]) -> CachedFilesystem {
+    let (launchpad, conflicts) = discover_mods(roots, ConflictHandler::NoRoot);
+    assert!(conflicts.is_empty());
+    CachedFilesystem::make_from_promise(launchpad)
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use arcropolis::resource::ResourceBackend;
+use common::*;
+use smash_arc::Hash40;
+
+#[test]
+fn bigger_replacement_resizes_its_file_data() {
+    let backend = install();
+
+    let fighter_param = Hash40::from(FIGHTER_PARAM);
+    let c01 = Hash40::from(MARIO_C01_MODEL);
+
+    let root = make_mod("patched-sizes", &[(FIGHTER_PARAM, 0x200), (MARIO_C01_MODEL, 0x80)]);
+    let mut filesystem = filesystem(&[root]);
+
+    filesystem.process_mods();
+    filesystem.reshare_files();
+    filesystem.patch_files();
+
+    let patched_sizes = backend.patched_sizes();
+    assert_eq!(patched_sizes.get(&fighter_param), Some(&(VANILLA_SIZE, 0x200)));
+    assert_eq!(backend.decompressed_size(fighter_param, config::region()).unwrap(), 0x200);
+
+    // The unshared costume got a copy of the FileData it was shared to, which is big enough already
+    assert!(!patched_sizes.contains_key(&c01));
+    assert_eq!(backend.decompressed_size(c01, config::region()).unwrap(), VANILLA_SIZE as usize);
+}
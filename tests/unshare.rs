@@ -0,0 +1,45 @@
+mod common;
+
+use std::path::Path;
+
+use arcropolis::resource::{self, ResourceBackend};
+use common::*;
+use smash_arc::{ArcLookup, Hash40};
+
+const MARIO_C08_MODEL: &str = "fighter/mario/model/body/c08/model.numdlb";
+
+#[test]
+fn replaced_costume_is_unshared_and_new_costume_is_added() {
+    let backend = install();
+
+    let c00 = Hash40::from(MARIO_C00_MODEL);
+    let c01 = Hash40::from(MARIO_C01_MODEL);
+    let c08 = Hash40::from(MARIO_C08_MODEL);
+
+    assert_eq!(backend.canonical_hash(c01).unwrap(), c00);
+    assert!(!backend.contains_file(c08));
+
+    let root = make_mod("unshare", &[(MARIO_C01_MODEL, 0x80), (MARIO_C08_MODEL, 0x80)]);
+    let mut filesystem = filesystem(&[root]);
+
+    let operations = filesystem.plan_mod_operations();
+    assert!(operations.added_files.iter().any(|local| local.as_path() == Path::new(MARIO_C08_MODEL)));
+    assert!(operations.unshared_files.contains(&c01));
+
+    filesystem.process_mods();
+    filesystem.reshare_files();
+
+    assert_eq!(backend.canonical_hash(c01).unwrap(), c01);
+    assert_eq!(backend.canonical_hash(c00).unwrap(), c00);
+    assert!(backend.contains_file(c08));
+
+    // Still sharing, the replacement would be keyed on the c00 model and loaded for both costumes
+    assert!(filesystem.hash_lookup().contains_key(&c01));
+    assert!(!filesystem.hash_lookup().contains_key(&c00));
+
+    let arc = resource::arc();
+    assert_ne!(
+        usize::from(arc.get_file_info_from_hash(c01).unwrap().info_to_data_index),
+        usize::from(arc.get_file_info_from_hash(c00).unwrap().info_to_data_index)
+    );
+}